@@ -7,11 +7,13 @@
 /// Helpers are provided for both raw Lark grammars ([`lark_str_test`],
 /// [`lark_ok`], [`lark_err_test`]) and JSON-schema grammars
 /// ([`json_schema_check`], [`json_test_many`]).
+use std::sync::Arc;
+
 use anyhow::Result;
 use llguidance::{
     api::{GrammarInit, TopLevelGrammar},
     toktrie::bytes::limit_str,
-    TokenParser,
+    ImportResolver, RetrieveWrapper, TokenParser,
 };
 use serde_json::Value;
 
@@ -28,9 +30,39 @@ pub enum NumericBounds {
 // ── Parser construction helpers ──────────────────────────────────────────────
 
 pub fn make_parser(lark: &str, quiet: bool) -> Result<TokenParser> {
-    let grm = TopLevelGrammar::from_lark(lark.to_string());
+    make_parser_ext(lark, quiet, None)
+}
+
+/// Like [`make_parser`], resolving `%import`s with `import_resolver`.
+pub fn make_parser_ext(
+    lark: &str,
+    quiet: bool,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
+) -> Result<TokenParser> {
+    make_parser_with_resolvers(lark, quiet, import_resolver, None)
+}
+
+/// Like [`make_parser_ext`], also resolving external `$ref`s of JSON schemas
+/// with `json_retriever`.
+pub fn make_parser_with_resolvers(
+    lark: &str,
+    quiet: bool,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
+    json_retriever: Option<RetrieveWrapper>,
+) -> Result<TokenParser> {
+    let mut init = GrammarInit::Serialized(TopLevelGrammar::from_lark(lark.to_string()));
+    if import_resolver.is_some() || json_retriever.is_some() {
+        let factory = get_parser_factory();
+        let (grammar, lexer_spec) = init.to_internal_with_imports(
+            Some(factory.tok_env().clone()),
+            factory.limits().clone(),
+            import_resolver,
+            json_retriever,
+        )?;
+        init = GrammarInit::Internal(grammar, lexer_spec);
+    }
     let mut parser = get_parser_factory().create_parser_from_init(
-        GrammarInit::Serialized(grm),
+        init,
         if quiet { 0 } else { 2 },
         if quiet { 1 } else { 2 },
     )?;
@@ -52,7 +84,11 @@ pub fn lark_ok(lark: &str) {
 }
 
 pub fn lark_err_test(lark: &str, err: &str) {
-    match make_parser(lark, false) {
+    lark_err_test_ext(lark, None, err);
+}
+
+pub fn lark_err_test_ext(lark: &str, import_resolver: Option<Arc<dyn ImportResolver>>, err: &str) {
+    match make_parser_ext(lark, false, import_resolver) {
         Err(e) => {
             let e = format!("{e}");
             if !e.contains(err) {
@@ -64,13 +100,34 @@ pub fn lark_err_test(lark: &str, err: &str) {
 }
 
 pub fn json_err_test(schema: &Value, err: &str) {
-    lark_err_test(
-        &format!(r#"start: %json {}"#, serde_json::to_string(schema).unwrap()),
-        err,
-    );
+    json_err_test_ext(schema, None, err);
+}
+
+/// Like [`json_err_test`], resolving external `$ref`s with `json_retriever`.
+pub fn json_err_test_ext(schema: &Value, json_retriever: Option<RetrieveWrapper>, err: &str) {
+    let lark = format!(r#"start: %json {}"#, serde_json::to_string(schema).unwrap());
+    match make_parser_with_resolvers(&lark, false, None, json_retriever) {
+        Err(e) => {
+            let e = format!("{e}");
+            if !e.contains(err) {
+                panic!("unexpected error: {e}, expecting {err:?}; grm:\n{lark}");
+            }
+        }
+        Ok(_) => panic!("expected error: {err}; grm:\n{lark}"),
+    }
 }
 
 pub fn lark_str_test(lark: &str, should_accept: bool, input: &str, quiet: bool) {
+    lark_str_test_ext(lark, None, should_accept, input, quiet);
+}
+
+pub fn lark_str_test_ext(
+    lark: &str,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
+    should_accept: bool,
+    input: &str,
+    quiet: bool,
+) {
     let trie = get_tok_env().tok_trie();
     let (final_reject, input) = if let Some(input) = input.strip_prefix("FINAL_REJECT:") {
         (true, input)
@@ -93,7 +150,7 @@ pub fn lark_str_test(lark: &str, should_accept: bool, input: &str, quiet: bool)
     }
 
     // let t0 = std::time::Instant::now();
-    let mut p = make_parser(lark, quiet).unwrap();
+    let mut p = make_parser_ext(lark, quiet, import_resolver).unwrap();
     // println!("make_parser: {:?}", t0.elapsed());
 
     for tok in tokens.iter() {
@@ -147,6 +204,16 @@ pub fn lark_str_test_many_ext(quiet: bool, lark: &str, passing: &[&str], failing
 /// convention.  Instead it serialises `json_obj`, feeds the tokens, and
 /// compares the parser's final `is_accepting()` state to `expect_valid`.
 pub fn json_schema_check(schema: &Value, json_obj: &Value, expect_valid: bool) {
+    json_schema_check_ext(schema, None, json_obj, expect_valid);
+}
+
+/// Like [`json_schema_check`], resolving external `$ref`s with `json_retriever`.
+pub fn json_schema_check_ext(
+    schema: &Value,
+    json_retriever: Option<RetrieveWrapper>,
+    json_obj: &Value,
+    expect_valid: bool,
+) {
     /*
        This is a modification of the lark_str_test function, which makes the
        assumption that the input Value completely satifies the schema.
@@ -163,7 +230,9 @@ pub fn json_schema_check(schema: &Value, json_obj: &Value, expect_valid: bool) {
 
     let tokens = get_tok_env().tokenize(&json_string);
 
-    let mut p = make_parser(&lark_grammar, crate::is_quiet_mode()).unwrap();
+    let mut p =
+        make_parser_with_resolvers(&lark_grammar, crate::is_quiet_mode(), None, json_retriever)
+            .unwrap();
 
     for (i, tok) in tokens.iter().enumerate() {
         let m = p.compute_mask().unwrap();
@@ -190,11 +259,21 @@ pub fn json_schema_check(schema: &Value, json_obj: &Value, expect_valid: bool) {
 }
 
 pub fn json_test_many(schema: &Value, passing: &[Value], failing: &[Value]) {
+    json_test_many_ext(schema, None, passing, failing);
+}
+
+/// Like [`json_test_many`], resolving external `$ref`s with `json_retriever`.
+pub fn json_test_many_ext(
+    schema: &Value,
+    json_retriever: Option<RetrieveWrapper>,
+    passing: &[Value],
+    failing: &[Value],
+) {
     for s in passing {
-        json_schema_check(schema, s, true);
+        json_schema_check_ext(schema, json_retriever.clone(), s, true);
     }
     for s in failing {
-        json_schema_check(schema, s, false);
+        json_schema_check_ext(schema, json_retriever.clone(), s, false);
     }
 }
 
@@ -202,6 +281,21 @@ pub fn lark_str_test_many(lark: &str, passing: &[&str], failing: &[&str]) {
     lark_str_test_many_ext(false, lark, passing, failing);
 }
 
+/// Like [`lark_str_test_many`], resolving `%import`s with `import_resolver`.
+pub fn lark_import_test_many(
+    lark: &str,
+    import_resolver: Arc<dyn ImportResolver>,
+    passing: &[&str],
+    failing: &[&str],
+) {
+    for s in passing {
+        lark_str_test_ext(lark, Some(import_resolver.clone()), true, s, false);
+    }
+    for s in failing {
+        lark_str_test_ext(lark, Some(import_resolver.clone()), false, s, false);
+    }
+}
+
 pub fn lark_str_test_many_quiet(lark: &str, passing: &[&str], failing: &[&str]) {
     lark_str_test_many_ext(true, lark, passing, failing);
 }
@@ -77,6 +77,16 @@ lazy_static! {
         fact.set_buffer_log_level(0);
         fact
     };
+    static ref NO_BACKTRACK_PARSER_FACTORY: ParserFactory = {
+        let mut fact = ParserFactory::new(
+            PARSER_FACTORY.tok_env(),
+            InferenceCapabilities::default(),
+            &SlicedBiasComputer::general_slices(),
+        )
+        .unwrap();
+        fact.quiet();
+        fact
+    };
 }
 
 pub fn get_tok_env() -> &'static TokEnv {
@@ -86,3 +96,8 @@ pub fn get_tok_env() -> &'static TokEnv {
 pub fn get_parser_factory() -> &'static ParserFactory {
     &PARSER_FACTORY
 }
+
+/// Quiet factory without ff_tokens or backtracking, as required by `Matcher`.
+pub fn get_no_backtrack_parser_factory() -> &'static ParserFactory {
+    &NO_BACKTRACK_PARSER_FACTORY
+}
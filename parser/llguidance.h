@@ -152,6 +152,16 @@ typedef struct LlgConstraintInit {
    * Default values will be used for all fields that are 0.
    */
   struct LlgParserLimits limits;
+  /**
+   * Directory to load external `$ref`s of JSON schemas from, or NULL.
+   * The path of the `$ref` URI is resolved relative to this directory.
+   */
+  const char *json_schema_dir;
+  /**
+   * JSON object mapping URIs to schemas, used for external `$ref`s
+   * of JSON schemas, or NULL. Takes precedence over `json_schema_dir`.
+   */
+  const char *json_schema_bundle;
 } LlgConstraintInit;
 
 /**
@@ -617,6 +627,26 @@ void llg_free_constraint(struct LlgConstraint *cc);
  */
 const char *llg_flush_logs(struct LlgConstraint *cc);
 
+/**
+ * Get the capture tree of the constraint as JSON.
+ *
+ * The result is a list of `{"name", "start", "end", "children"}` objects,
+ * where `start` and `end` are byte offsets into the output of the constraint.
+ * The returned string is NUL-terminated and remains valid until the next
+ * call to this function or until the constraint is freed.
+ */
+const char *llg_get_capture_tree(struct LlgConstraint *cc);
+
+/**
+ * Get the parse tree of the output of the constraint as JSON.
+ *
+ * See [`llg_matcher_get_parse_tree()`] for the format.
+ * Returns `null` if the constraint was not created successfully.
+ * The returned string is NUL-terminated and remains valid until the next
+ * call to this function or until the constraint is freed.
+ */
+const char *llg_get_parse_tree(struct LlgConstraint *cc, bool named_only);
+
 /**
  * Create a new stop-sequence controller.
  *
@@ -755,6 +785,30 @@ int32_t llg_matcher_consume_tokens(struct LlgMatcher *matcher,
  */
 const char *llg_matcher_get_error(struct LlgMatcher *matcher);
 
+/**
+ * Get the capture tree of the matcher as JSON.
+ *
+ * The result is a list of `{"name", "start", "end", "children"}` objects,
+ * where `start` and `end` are byte offsets into the consumed output;
+ * nested captures are listed in `children`.
+ * The returned string is NUL-terminated and remains valid until the next
+ * call to this function or until the matcher is freed.
+ */
+const char *llg_matcher_get_capture_tree(struct LlgMatcher *matcher);
+
+/**
+ * Get the parse tree of the matcher as JSON.
+ *
+ * Nodes are `{"name", "start", "end", "is_lexeme", "children"}` objects,
+ * where `start` and `end` are byte offsets into the consumed output.
+ * Rules have their right-hand side in `children`; lexemes have no children.
+ * If `named_only` is set, nodes of helper symbols are replaced by their children.
+ * Returns `null` if the matcher is in an error state.
+ * The returned string is NUL-terminated and remains valid until the next
+ * call to this function or until the matcher is freed.
+ */
+const char *llg_matcher_get_parse_tree(struct LlgMatcher *matcher, bool named_only);
+
 /**
  * Check whether the matcher is in an error state.
  */
@@ -54,6 +54,20 @@ pub struct LLGuidanceOptions {
     /// including nested sub-grammars.
     #[serde(default)]
     pub allow_initial_skip: bool,
+
+    /// If set, string and regex literals in the Lark grammar use Python syntax,
+    /// as in the upstream Python Lark (e.g., `"\x41"`, `/(?x) a b # comment/`).
+    /// It applies to the entire Lark file where it is set (and the modules it imports),
+    /// irrespective of the position of the `%llguidance` declaration.
+    #[serde(default)]
+    pub python_compat: bool,
+
+    /// If set, Lark rules are kept as separate symbols in the grammar
+    /// (they are normally inlined by the grammar optimizer where possible),
+    /// so that they all show up in `Matcher::parse_tree()`.
+    /// This makes the parser somewhat slower.
+    #[serde(default)]
+    pub parse_tree: bool,
 }
 
 impl LLGuidanceOptions {
@@ -67,6 +81,12 @@ impl LLGuidanceOptions {
         if other.allow_initial_skip {
             self.allow_initial_skip = true;
         }
+        if other.python_compat {
+            self.python_compat = true;
+        }
+        if other.parse_tree {
+            self.parse_tree = true;
+        }
     }
 }
 
@@ -123,6 +143,12 @@ pub struct NodeProps {
     pub max_tokens: Option<usize>,
     pub name: Option<String>,
     pub capture_name: Option<String>,
+    /// Preference of this node over alternative derivations of the same text
+    /// (see `SymbolProps::priority`).
+    pub priority: Option<i32>,
+    /// Mark the node as a named rule of the source grammar (see `SymbolProps::named`).
+    #[serde(default)]
+    pub named: bool,
 }
 
 #[derive(Clone)]
@@ -182,6 +208,27 @@ pub struct RegexExt {
     pub substring_words: Option<String>,
     /// Similar to `substring_chunks: s.split('')`
     pub substring_chars: Option<String>,
+
+    /// JSON schema type of the lexeme: `"number"`, `"integer"`, or `"string"`.
+    /// The fields below are interpreted as in JSON schema; numeric ones require `type` to be set,
+    /// while string ones default it to `"string"`.
+    /// Strings are matched without the quotes and escaping.
+    #[serde(rename = "type")]
+    pub tp: Option<String>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    #[serde(rename = "exclusiveMinimum")]
+    pub exclusive_minimum: Option<f64>,
+    #[serde(rename = "exclusiveMaximum")]
+    pub exclusive_maximum: Option<f64>,
+    #[serde(rename = "multipleOf")]
+    pub multiple_of: Option<f64>,
+    pub format: Option<String>,
+    pub pattern: Option<String>,
+    #[serde(rename = "minLength")]
+    pub min_length: Option<u64>,
+    #[serde(rename = "maxLength")]
+    pub max_length: Option<u64>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
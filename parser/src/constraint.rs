@@ -3,6 +3,7 @@ use toktrie::{StepResult, TokenId};
 
 use crate::{
     api::StopReason,
+    earley::{CaptureNode, ParseNode},
     loginfo,
     output::{ParserOutput, Reporter},
     panic_utils, TokenParser,
@@ -207,10 +208,25 @@ impl Constraint {
     /// It only returns 'STOP' if previous compute_mask() already returned 'STOP'
     /// (in which case there's little point calling commit_token()).
     pub fn commit_token(&mut self, sampled_token: Option<TokenId>) -> Result<CommitResult> {
-        self.catch_unwind(|s| s.commit_token_inner(sampled_token))
+        self.commit_token_with_logprob(sampled_token, None)
     }
 
-    fn commit_token_inner(&mut self, sampled_token: Option<TokenId>) -> Result<CommitResult> {
+    /// Like [`Constraint::commit_token()`], but also takes the log-probability of the sampled token.
+    /// These are summed over the bytes of each capture and text chunk
+    /// and reported as `log_prob` in [`Constraint::flush_progress()`].
+    pub fn commit_token_with_logprob(
+        &mut self,
+        sampled_token: Option<TokenId>,
+        logprob: Option<f64>,
+    ) -> Result<CommitResult> {
+        self.catch_unwind(|s| s.commit_token_inner(sampled_token, logprob))
+    }
+
+    fn commit_token_inner(
+        &mut self,
+        sampled_token: Option<TokenId>,
+        logprob: Option<f64>,
+    ) -> Result<CommitResult> {
         let n_tokens = self.parser.num_tokens();
         loginfo!(
             self.parser.logger,
@@ -244,7 +260,7 @@ impl Constraint {
                 anyhow::anyhow!("sampled_token is required when mask was present")
             })?;
 
-            let mut bt = self.parser.consume_token(t)?;
+            let mut bt = self.parser.consume_token_with_logprob(t, logprob)?;
             let mut tokens = vec![t];
             if bt > 0 {
                 loginfo!(self.parser.logger, "backtrack sampled");
@@ -276,6 +292,17 @@ impl Constraint {
         self.reporter.get_progress(&self.parser, &self.last_res)
     }
 
+    /// Captures nested according to their byte spans in the output
+    /// (offsets into `parser.final_bytes()`).
+    pub fn capture_tree(&self) -> Vec<CaptureNode> {
+        self.parser.capture_tree()
+    }
+
+    /// Parse tree of the output so far, see [`crate::Matcher::parse_tree()`].
+    pub fn parse_tree(&self, named_only: bool) -> ParseNode {
+        self.parser.parse_tree(named_only)
+    }
+
     /// Logs to be sent to the user.
     pub fn flush_logs(&mut self) -> String {
         self.parser.logger.get_and_clear_logs()
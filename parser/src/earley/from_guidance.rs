@@ -8,7 +8,7 @@ use crate::api::{GrammarId, GrammarInit, GrammarWithLexer, ParserLimits, TopLeve
 use crate::earley::lexerspec::LexemeClass;
 use crate::Instant;
 use crate::{loginfo, JsonCompileOptions, Logger};
-use crate::{GrammarBuilder, HashMap};
+use crate::{GrammarBuilder, HashMap, ImportResolver, RetrieveWrapper};
 use anyhow::{bail, ensure, Result};
 use toktrie::TokEnv;
 
@@ -147,6 +147,18 @@ impl GrammarInit {
         self,
         tok_env: Option<TokEnv>,
         limits: ParserLimits,
+    ) -> Result<(Grammar, LexerSpec)> {
+        self.to_internal_with_imports(tok_env, limits, None, None)
+    }
+
+    /// Like [`GrammarInit::to_internal`], resolving Lark `%import`s with `import_resolver`
+    /// and external `$ref`s of JSON schemas with `json_retriever`.
+    pub fn to_internal_with_imports(
+        self,
+        tok_env: Option<TokEnv>,
+        limits: ParserLimits,
+        import_resolver: Option<Arc<dyn ImportResolver>>,
+        json_retriever: Option<RetrieveWrapper>,
     ) -> Result<(Grammar, LexerSpec)> {
         match self {
             GrammarInit::Internal(g, l) => Ok((g, l)),
@@ -154,7 +166,9 @@ impl GrammarInit {
             GrammarInit::Serialized(input) => {
                 ensure!(!input.grammars.is_empty(), "empty grammars array");
 
-                let builder = GrammarBuilder::new(tok_env, limits.clone());
+                let mut builder = GrammarBuilder::new(tok_env, limits.clone());
+                builder.set_import_resolver(import_resolver);
+                builder.set_json_retriever(json_retriever);
 
                 let ctx = CompileCtx {
                     builder: Some(builder),
@@ -180,9 +194,16 @@ impl GrammarInit {
         logger: &mut Logger,
         limits: ParserLimits,
         extra_lexemes: Vec<String>,
+        import_resolver: Option<Arc<dyn ImportResolver>>,
+        json_retriever: Option<RetrieveWrapper>,
     ) -> Result<Arc<CGrammar>> {
         let t0 = Instant::now();
-        let (grammar, mut lexer_spec) = self.to_internal(tok_env, limits.clone())?;
+        let (grammar, mut lexer_spec) = self.to_internal_with_imports(
+            tok_env,
+            limits.clone(),
+            import_resolver,
+            json_retriever,
+        )?;
         lexer_spec.add_extra_lexemes(&extra_lexemes);
         compile_grammar(t0, grammar, lexer_spec, logger, &limits)
     }
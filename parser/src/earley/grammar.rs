@@ -58,6 +58,13 @@ pub struct SymbolProps {
     pub grammar_id: LexemeClass,
     pub is_start: bool,
     pub parametric: bool,
+    /// Rule priority (Lark's `rule.N:`); it doesn't change the accepted strings,
+    /// only which derivation is preferred when the same text can be derived
+    /// in several ways.
+    pub priority: i32,
+    /// A named rule of the source grammar (e.g., a Lark rule, with `parse_tree` option);
+    /// these are not inlined and are listed in `named_only` parse trees.
+    pub named: bool,
 }
 
 impl Default for SymbolProps {
@@ -70,6 +77,8 @@ impl Default for SymbolProps {
             is_start: false,
             grammar_id: LexemeClass::ROOT,
             parametric: false,
+            priority: 0,
+            named: false,
         }
     }
 }
@@ -81,6 +90,8 @@ impl SymbolProps {
             || self.capture_name.is_some()
             || self.stop_capture_name.is_some()
             || self.is_start
+            || self.priority != 0
+            || self.named
     }
 
     // this is used when a rule like 'self -> [self.for_wrapper()]` is added
@@ -94,6 +105,8 @@ impl SymbolProps {
             grammar_id: self.grammar_id,
             is_start: false,
             parametric: false,
+            priority: 0,
+            named: false,
         }
     }
 
@@ -120,6 +133,12 @@ impl Display for SymbolProps {
         if self.temperature != 0.0 {
             write!(f, " temp={:.2}", self.temperature)?;
         }
+        if self.priority != 0 {
+            write!(f, " priority={}", self.priority)?;
+        }
+        if self.named {
+            write!(f, " NAMED")?;
+        }
         Ok(())
     }
 }
@@ -565,6 +584,12 @@ impl Grammar {
         if let Some(capture_name) = props.capture_name {
             sym.props.capture_name = Some(capture_name);
         }
+        if let Some(priority) = props.priority {
+            sym.props.priority = priority;
+        }
+        if props.named {
+            sym.props.named = true;
+        }
     }
 
     pub fn sym_props_mut(&mut self, sym: SymIdx) -> &mut SymbolProps {
@@ -804,11 +829,17 @@ impl Grammar {
                 let mut rhs = Vec::with_capacity(rule.rhs.len());
                 for s in &rule.rhs {
                     if let Some(repl) = repl.get(&s.0) {
-                        assert!(s.1.is_null() || s.1.is_self_ref());
-                        rhs.extend(
-                            repl.iter()
-                                .map(|r| (outp.copy_from(self, r.0), r.1.clone())),
-                        );
+                        if s.1.is_null() || s.1.is_self_ref() {
+                            rhs.extend(
+                                repl.iter()
+                                    .map(|r| (outp.copy_from(self, r.0), r.1.clone())),
+                            );
+                        } else {
+                            // only aliases ('sym::_ : trg::_') are used with a parameter;
+                            // the parameter is passed through to the target
+                            assert!(repl.len() == 1 && repl[0].1.is_self_ref());
+                            rhs.push((outp.copy_from(self, repl[0].0), s.1.clone()));
+                        }
                     } else {
                         rhs.push((outp.copy_from(self, s.0), s.1.clone()));
                     }
@@ -1204,6 +1235,10 @@ impl CGrammar {
         self.start_symbol
     }
 
+    pub fn num_symbols(&self) -> usize {
+        self.symbols.len()
+    }
+
     pub fn rules_of(&self, sym: CSymIdx) -> &[RhsPtr] {
         &self.sym_data(sym).rules
     }
@@ -53,6 +53,9 @@ pub struct LexemeSpec {
     ends_at_eos: bool,
     lazy: bool,
     contextual: bool,
+    // when several lexemes match the same text, only the ones with highest priority are used;
+    // None (no priority requested) counts as 0
+    priority: Option<i32>,
     max_tokens: usize,
     pub(crate) is_extra: bool,
     pub(crate) is_suffix: bool,
@@ -92,6 +95,10 @@ impl LexemeSpec {
         self.max_tokens
     }
 
+    pub fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+
     pub fn to_string(&self, max_len: usize, exprset: Option<&ExprSet>) -> String {
         use std::fmt::Write;
         let mut f = String::new();
@@ -109,6 +116,9 @@ impl LexemeSpec {
         if self.is_extra {
             f.push_str(" extra");
         }
+        if let Some(priority) = self.priority.filter(|&p| p != 0) {
+            write!(f, " priority={priority}").unwrap();
+        }
         if !self.token_ranges.is_empty() {
             write!(f, " tokens={}", token_ranges_to_string(&self.token_ranges)).unwrap();
         }
@@ -269,7 +279,7 @@ impl LexerSpec {
             .iter()
             .map(|lex| RxLexeme {
                 rx: lex.compiled_rx,
-                priority: 0,
+                priority: lex.priority.unwrap_or(0),
                 lazy: lex.lazy,
             })
             .collect();
@@ -353,6 +363,7 @@ impl LexerSpec {
             compiled_rx: ExprRef::INVALID,
             lazy: false,
             contextual: false,
+            priority: None,
             ends_at_eos: false,
             is_skip: false,
             is_suffix: false,
@@ -433,6 +444,14 @@ impl LexerSpec {
         })
     }
 
+    /// Make the lexeme preferred over lexemes of lower priority matching the same text.
+    /// Lexemes are shared, so the highest priority requested wins
+    /// (a negative priority only applies if no user of the lexeme asked for more).
+    pub fn request_priority(&mut self, idx: LexemeIdx, priority: i32) {
+        let lex = &mut self.lexemes[idx.as_usize()];
+        lex.priority = Some(lex.priority.map_or(priority, |p| p.max(priority)));
+    }
+
     pub fn add_extra_lexemes(&mut self, extra_lexemes: &[String]) {
         assert!(self.num_extra_lexemes == 0);
         self.num_extra_lexemes = extra_lexemes.len();
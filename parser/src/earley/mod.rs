@@ -1,6 +1,7 @@
 mod from_guidance;
 mod grammar;
 pub(crate) mod lexer;
+mod parse_tree;
 mod parser;
 mod slicer;
 
@@ -14,5 +15,8 @@ pub use grammar::{
     BitIdx, CGrammar, CSymIdx, Grammar, ParamCond, ParamExpr, ParamRef, ParamValue, SymIdx,
     SymbolProps,
 };
-pub use parser::{BiasComputer, Parser, ParserError, ParserMetrics, ParserRecognizer, ParserStats};
+pub use parse_tree::ParseNode;
+pub use parser::{
+    BiasComputer, CaptureNode, Parser, ParserError, ParserMetrics, ParserRecognizer, ParserStats,
+};
 pub use slicer::SlicedBiasComputer;
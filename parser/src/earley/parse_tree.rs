@@ -0,0 +1,482 @@
+// Reconstruction of a single derivation from the Earley table.
+//
+// We walk backwards from a complete (or, for a prefix, the innermost) item
+// in the last row, and for every symbol before the dot find where it started,
+// as in the standard Earley parse tree extraction.
+// Ambiguities are resolved as follows:
+//   - the split points are chosen so that earlier symbols get the longest
+//     possible span (in particular, nullable symbols at the end are empty);
+//   - among rules of a symbol deriving the same span, the one with the highest
+//     priority of its right-hand side symbols (Lark's `rule.N`) wins,
+//     and then the one listed first in the grammar;
+//   - empty symbols are not expanded if they are nullable, except that
+//     a rule doesn't end with empty symbols when an earlier symbol would
+//     then have to derive its whole span;
+//   - a rule is never used again for the same span inside itself
+//     (so that cycles like `a: b | "x"`, `b: a?` terminate).
+
+use std::{cmp::Ordering, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use crate::HashSet;
+
+use super::grammar::{CGrammar, CSymIdx, RhsPtr};
+
+/// A node in the parse tree, see [`crate::Matcher::parse_tree()`].
+///
+/// `start` and `end` are byte offsets into the parsed bytes
+/// (for [`crate::TokenParser`] and [`crate::Matcher`], into `final_bytes()`).
+/// Lexemes have `is_lexeme` set and no children;
+/// the `children` of a rule are the nodes for its right-hand side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseNode {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub is_lexeme: bool,
+    pub children: Vec<ParseNode>,
+}
+
+impl ParseNode {
+    /// Shift all offsets in the subtree down by `delta`.
+    pub fn shift_down(&mut self, delta: usize) {
+        // parse trees can be deep, so avoid recursion
+        let mut todo = vec![self];
+        while let Some(n) = todo.pop() {
+            n.start = n.start.saturating_sub(delta);
+            n.end = n.end.saturating_sub(delta);
+            todo.extend(n.children.iter_mut());
+        }
+    }
+}
+
+/// Snapshot of an Earley row.
+pub(crate) struct ParseRow {
+    /// (rule with dot position, start row)
+    pub items: Vec<(RhsPtr, usize)>,
+    /// Visible bytes of the lexeme that created this row (empty for row 0).
+    pub lexeme: Range<usize>,
+    /// Whether that lexeme was an ignored one (%ignore); such rows copy the previous one.
+    pub is_skip: bool,
+}
+
+#[derive(Clone)]
+struct Frame {
+    lhs: CSymIdx,
+    // dot position; we move it towards the start of the rule
+    ptr: RhsPtr,
+    start: usize,
+    end: usize,
+    // (rule, start, end) when the frame was created
+    key: FrameKey,
+    // position of the node if it turns out to be empty
+    empty_pos: usize,
+    // in reverse order
+    children: Vec<ParseNode>,
+}
+
+type FrameKey = (RhsPtr, usize, usize);
+
+// (item, its start row, row it's in)
+type ChainItem = (RhsPtr, usize, usize);
+
+// (start row of the symbol before the dot, its complete rule or None if it's empty)
+type Choice = (usize, Option<RhsPtr>);
+
+// limit on the number of backtracking steps in one tree
+const MAX_BACKTRACK: usize = 10_000;
+
+enum Step {
+    Nodes(Vec<ParseNode>),
+    // in order of preference; whether to try the others on a dead end
+    Choose(Vec<Choice>, bool),
+    Done,
+}
+
+struct ChoicePoint {
+    // index of the frame in the stack, and its state before the choice
+    depth: usize,
+    frame: Frame,
+    // remaining choices, in reverse order of preference
+    rest: Vec<Choice>,
+}
+
+struct TreeBuilder<'a> {
+    grammar: &'a CGrammar,
+    rows: &'a [ParseRow],
+    items: HashSet<(usize, RhsPtr, usize)>,
+    named_only: bool,
+}
+
+pub(crate) fn build_parse_tree(
+    grammar: &CGrammar,
+    rows: &[ParseRow],
+    named_only: bool,
+) -> ParseNode {
+    let mut items = HashSet::default();
+    for (row_idx, row) in rows.iter().enumerate() {
+        for &(ptr, start) in &row.items {
+            items.insert((row_idx, ptr, start));
+        }
+    }
+    let b = TreeBuilder {
+        grammar,
+        rows,
+        items,
+        named_only,
+    };
+    b.build_root()
+}
+
+impl TreeBuilder<'_> {
+    fn prev_ptr(ptr: RhsPtr) -> RhsPtr {
+        RhsPtr::from_index(ptr.as_index() as u32 - 1)
+    }
+
+    fn is_complete(&self, ptr: RhsPtr) -> bool {
+        self.grammar.sym_idx_dot(ptr) == CSymIdx::NULL
+    }
+
+    // skip rows are copies of the previous row
+    fn deskip(&self, mut row_idx: usize, limit: usize) -> usize {
+        while row_idx > limit && self.rows[row_idx].is_skip {
+            row_idx -= 1;
+        }
+        row_idx
+    }
+
+    fn row_offset(&self, row_idx: usize) -> usize {
+        self.rows[self.deskip(row_idx, 0)].lexeme.end
+    }
+
+    fn rule_priority(&self, ptr: RhsPtr) -> i32 {
+        let (rhs, _, _) = self.grammar.rule_rhs(ptr);
+        rhs.iter()
+            .map(|s| self.grammar.sym_data(*s).props.priority)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // is rule 'a' preferred over rule 'b' (of the same symbol, for the same span)?
+    fn rule_better(&self, a: RhsPtr, b: RhsPtr) -> bool {
+        let (pa, pb) = (self.rule_priority(a), self.rule_priority(b));
+        pa > pb || (pa == pb && a.as_index() < b.as_index())
+    }
+
+    fn node_name(&self, sym: CSymIdx) -> String {
+        let data = self.grammar.sym_data(sym);
+        if data.props.named || data.is_terminal {
+            // drop the suffix added when the name was already taken
+            if let Some((base, suff)) = data.name.rsplit_once('#') {
+                if !base.is_empty() && suff.chars().all(|c| c.is_ascii_digit()) {
+                    return base.to_string();
+                }
+            }
+        }
+        data.name.clone()
+    }
+
+    fn new_frame(&self, lhs: CSymIdx, ptr: RhsPtr, start: usize, end: usize) -> Frame {
+        Frame {
+            lhs,
+            ptr,
+            start,
+            end,
+            key: (ptr, start, end),
+            empty_pos: self.row_offset(end),
+            children: vec![],
+        }
+    }
+
+    fn finish(&self, mut frame: Frame) -> Vec<ParseNode> {
+        frame.children.reverse();
+        if self.named_only && !self.grammar.sym_data(frame.lhs).props.named {
+            return frame.children;
+        }
+        vec![self.make_node(frame.lhs, frame.children, frame.empty_pos)]
+    }
+
+    fn make_node(&self, sym: CSymIdx, children: Vec<ParseNode>, empty_pos: usize) -> ParseNode {
+        let non_empty = |c: &&ParseNode| c.start < c.end;
+        let (start, end) = match (
+            children.iter().find(non_empty),
+            children.iter().rev().find(non_empty),
+        ) {
+            (Some(a), Some(b)) => (a.start, b.end),
+            _ => {
+                let p = children.first().map_or(empty_pos, |c| c.start);
+                (p, p)
+            }
+        };
+        ParseNode {
+            name: self.node_name(sym),
+            start,
+            end,
+            is_lexeme: false,
+            children,
+        }
+    }
+
+    fn step(&self, frame: &mut Frame, active: &HashSet<FrameKey>) -> Step {
+        let prev = Self::prev_ptr(frame.ptr);
+        let sym = self.grammar.sym_idx_dot(prev);
+        if sym == CSymIdx::NULL {
+            return Step::Done;
+        }
+        let end = self.deskip(frame.end, frame.start);
+        let data = self.grammar.sym_data(sym);
+
+        if data.is_terminal {
+            if end == 0 {
+                return Step::Done;
+            }
+            let lexeme = &self.rows[end].lexeme;
+            frame.ptr = prev;
+            frame.end = end - 1;
+            return Step::Nodes(vec![ParseNode {
+                name: self.node_name(sym),
+                start: lexeme.start,
+                end: lexeme.end,
+                is_lexeme: true,
+                children: vec![],
+            }]);
+        }
+
+        // find where 'sym' starts; the largest start wins
+        let empty = (data.is_nullable || !data.cond_nullable.is_empty())
+            && self.items.contains(&(end, prev, frame.start));
+        let mut choices = vec![];
+        for &(ptr, k) in &self.rows[end].items {
+            if k < frame.start
+                || (k == end && empty)
+                || active.contains(&(ptr, k, end))
+                || !self.is_complete(ptr)
+                || self.grammar.sym_idx_lhs(ptr) != sym
+                || !self.items.contains(&(k, prev, frame.start))
+            {
+                continue;
+            }
+            choices.push((k, Some(ptr)));
+        }
+        choices.sort_by(|(ka, pa), (kb, pb)| {
+            kb.cmp(ka).then_with(|| {
+                let (pa, pb) = (pa.unwrap(), pb.unwrap());
+                if self.rule_better(pa, pb) {
+                    Ordering::Less
+                } else if self.rule_better(pb, pa) {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+        });
+
+        // until the rule has consumed something, non-empty symbols are preferred;
+        // otherwise the earlier symbols would have to derive the whole span
+        // of the rule again
+        let consumed = end < self.deskip(frame.key.2, frame.start);
+        if empty {
+            if consumed {
+                choices.insert(0, (end, None));
+            } else {
+                choices.push((end, None));
+            }
+        }
+        Step::Choose(choices, !consumed)
+    }
+
+    fn apply(&self, frame: &mut Frame, (k, complete): Choice) -> Option<Frame> {
+        let prev = Self::prev_ptr(frame.ptr);
+        let sym = self.grammar.sym_idx_dot(prev);
+        let end = self.deskip(frame.end, frame.start);
+        frame.ptr = prev;
+        frame.end = k;
+        match complete {
+            Some(ptr) => Some(self.new_frame(sym, ptr, k, end)),
+            None => {
+                let nodes = self.finish(self.new_frame(sym, prev, k, end));
+                frame.children.extend(nodes.into_iter().rev());
+                None
+            }
+        }
+    }
+
+    // Depth-first, but a choice made before a rule has consumed anything may
+    // lead to a dead end, when the only derivations left would go through
+    // a rule already on the stack for the same span; we then backtrack.
+    // The frames above such a choice only have empty children, so they are
+    // cheap to restore.
+    fn build(&self, root: Frame) -> Vec<ParseNode> {
+        let mut active = HashSet::default();
+        active.insert(root.key);
+        let mut stack = vec![root];
+        let mut choice_points: Vec<ChoicePoint> = vec![];
+        let mut budget = MAX_BACKTRACK;
+        loop {
+            let depth = stack.len() - 1;
+            let top = stack.last_mut().unwrap();
+            let done = match self.step(top, &active) {
+                Step::Nodes(nodes) => {
+                    top.children.extend(nodes.into_iter().rev());
+                    false
+                }
+                Step::Choose(mut choices, record) if !choices.is_empty() => {
+                    choices.reverse();
+                    let choice = choices.pop().unwrap();
+                    if record && !choices.is_empty() {
+                        choice_points.push(ChoicePoint {
+                            depth,
+                            frame: top.clone(),
+                            rest: choices,
+                        });
+                    }
+                    if let Some(child) = self.apply(top, choice) {
+                        active.insert(child.key);
+                        stack.push(child);
+                    }
+                    false
+                }
+                // dead end
+                Step::Choose(_, _) => {
+                    !self.backtrack(&mut stack, &mut active, &mut choice_points, &mut budget)
+                }
+                Step::Done => true,
+            };
+            if done {
+                // a dead end without alternatives shouldn't happen,
+                // but don't fail if it does
+                let frame = stack.pop().unwrap();
+                active.remove(&frame.key);
+                choice_points.retain(|c| c.depth < stack.len());
+                let nodes = self.finish(frame);
+                match stack.last_mut() {
+                    Some(parent) => parent.children.extend(nodes.into_iter().rev()),
+                    None => return nodes,
+                }
+            }
+        }
+    }
+
+    fn backtrack(
+        &self,
+        stack: &mut Vec<Frame>,
+        active: &mut HashSet<FrameKey>,
+        choice_points: &mut Vec<ChoicePoint>,
+        budget: &mut usize,
+    ) -> bool {
+        while let Some(cp) = choice_points.last_mut() {
+            let Some(choice) = cp.rest.pop() else {
+                choice_points.pop();
+                continue;
+            };
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+            for f in stack.drain(cp.depth..) {
+                active.remove(&f.key);
+            }
+            let mut frame = cp.frame.clone();
+            active.insert(frame.key);
+            let child = self.apply(&mut frame, choice);
+            stack.push(frame);
+            if let Some(child) = child {
+                active.insert(child.key);
+                stack.push(child);
+            }
+            if cp.rest.is_empty() {
+                choice_points.pop();
+            }
+            return true;
+        }
+        false
+    }
+
+    // chain of items from the start symbol down to the item (sym, start)
+    fn parent_chain(&self, sym: CSymIdx, start: usize) -> Vec<ChainItem> {
+        let root = self.grammar.start();
+        let mut visited = HashSet::default();
+        // (sym, start, index of parent entry in 'todo', item)
+        let mut todo: Vec<(CSymIdx, usize, usize, Option<ChainItem>)> =
+            vec![(sym, start, usize::MAX, None)];
+        visited.insert((sym, start));
+        let mut ptr = 0;
+        while ptr < todo.len() {
+            let (sym, row_idx, _, _) = todo[ptr];
+            if sym == root && row_idx == 0 {
+                let mut chain = vec![];
+                let mut idx = ptr;
+                while let (_, _, parent, Some(item)) = todo[idx] {
+                    chain.push(item);
+                    idx = parent;
+                }
+                // innermost first
+                chain.reverse();
+                return chain;
+            }
+            for &(p, s) in &self.rows[row_idx].items {
+                if self.grammar.sym_idx_dot(p) == sym {
+                    let lhs = self.grammar.sym_idx_lhs(p);
+                    if visited.insert((lhs, s)) {
+                        todo.push((lhs, s, ptr, Some((p, s, row_idx))));
+                    }
+                }
+            }
+            ptr += 1;
+        }
+        vec![]
+    }
+
+    fn build_root(&self) -> ParseNode {
+        let root = self.grammar.start();
+        let last = self.deskip(self.rows.len() - 1, 0);
+
+        let mut complete: Option<RhsPtr> = None;
+        for &(ptr, start) in &self.rows[last].items {
+            if start == 0
+                && self.is_complete(ptr)
+                && self.grammar.sym_idx_lhs(ptr) == root
+                && complete.is_none_or(|c| self.rule_better(ptr, c))
+            {
+                complete = Some(ptr);
+            }
+        }
+
+        let mut nodes = if let Some(ptr) = complete {
+            self.build(self.new_frame(root, ptr, 0, last))
+        } else {
+            // the innermost item that has consumed something
+            let inner = self.rows[last]
+                .items
+                .iter()
+                .filter(|(ptr, _)| self.grammar.sym_idx_dot(Self::prev_ptr(*ptr)) != CSymIdx::NULL)
+                .fold(
+                    None,
+                    |acc: Option<(RhsPtr, usize)>, &(ptr, start)| match acc {
+                        Some((_, s)) if s >= start => acc,
+                        _ => Some((ptr, start)),
+                    },
+                );
+            match inner {
+                None => vec![],
+                Some((ptr, start)) => {
+                    let lhs = self.grammar.sym_idx_lhs(ptr);
+                    let mut nodes = self.build(self.new_frame(lhs, ptr, start, last));
+                    for (p, s, row_idx) in self.parent_chain(lhs, start) {
+                        let mut frame = self.new_frame(self.grammar.sym_idx_lhs(p), p, s, row_idx);
+                        frame.children = nodes.into_iter().rev().collect();
+                        nodes = self.build(frame);
+                    }
+                    nodes
+                }
+            }
+        };
+
+        if nodes.len() == 1 && !nodes[0].is_lexeme {
+            return nodes.pop().unwrap();
+        }
+        // the root is kept even if not named
+        self.make_node(root, nodes, self.row_offset(last))
+    }
+}
@@ -32,6 +32,7 @@ use super::{
     grammar::{CGrammar, CSymIdx, CSymbol, RhsPtr},
     lexer::{LexerResult, PreLexeme},
     lexerspec::{Lexeme, LexemeIdx, LexemeSpec, LexerSpec},
+    parse_tree::{build_parse_tree, ParseNode, ParseRow},
     perf::ParserPerfCounters,
     regexvec::{LexemeSet, LexerStats},
 };
@@ -268,6 +269,8 @@ struct Scratch {
 struct RowInfo {
     // TODO: possibly use u32 not usize here
     start_byte_idx: usize,
+    // offset of the lexeme in get_bytes(), that is without hidden bytes
+    lexeme_byte_idx: usize,
     lexeme: Lexeme,
     token_idx_start: usize,
     token_idx_stop: usize,
@@ -320,21 +323,101 @@ struct LexerState {
     byte: Option<u8>,
 }
 
+/// A capture in the capture tree.
+///
+/// `start` and `end` are byte offsets into the parsed bytes
+/// (for [`crate::TokenParser`] and [`crate::Matcher`], into `final_bytes()`).
+/// `children` are the captures nested inside this one, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureNode {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<CaptureNode>,
+}
+
+impl CaptureNode {
+    fn same_span(&self, other: &CaptureNode) -> bool {
+        self.name == other.name && self.start == other.start && self.end == other.end
+    }
+
+    fn contains(&self, other: &CaptureNode) -> bool {
+        self.same_span(other)
+            || (self.start <= other.start
+                && other.end <= self.end
+                && self.children.iter().rev().any(|c| c.contains(other)))
+    }
+
+    /// Shift all offsets in the subtree down by `delta`.
+    pub fn shift_down(&mut self, delta: usize) {
+        self.start = self.start.saturating_sub(delta);
+        self.end = self.end.saturating_sub(delta);
+        for c in self.children.iter_mut() {
+            c.shift_down(delta);
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Captures {
     capture_list: Vec<(String, Vec<u8>)>,
+    // byte spans of capture_list entries
+    capture_spans: Vec<Range<usize>>,
     capture_map: HashMap<String, Vec<u8>>,
+    // roots of the capture tree; their end offsets are non-decreasing
+    capture_tree: Vec<CaptureNode>,
 }
 
 impl Captures {
     fn new() -> Self {
         Captures {
             capture_list: vec![],
+            capture_spans: vec![],
             capture_map: HashMap::default(),
+            capture_tree: vec![],
         }
     }
 
-    fn push(&mut self, cap: (String, Vec<u8>)) {
+    fn push_node(&mut self, name: &str, start: usize, end: usize) {
+        let mut node = CaptureNode {
+            name: name.to_string(),
+            start,
+            end,
+            children: vec![],
+        };
+        // the same capture can be completed by several Earley items
+        if self
+            .capture_tree
+            .iter()
+            .rev()
+            .take_while(|r| r.end >= start)
+            .any(|r| r.contains(&node))
+        {
+            return;
+        }
+        // captures complete bottom-up, so the ones inside are already there
+        let mut first_child = self.capture_tree.len();
+        while first_child > 0 {
+            let prev = &self.capture_tree[first_child - 1];
+            if prev.start >= start && prev.end <= end {
+                first_child -= 1;
+            } else {
+                break;
+            }
+        }
+        node.children = self.capture_tree.split_off(first_child);
+        self.capture_tree.push(node);
+    }
+
+    /// Drop captures that extend past `len` bytes (after rollback).
+    fn truncate_tree(&mut self, len: usize) {
+        while self.capture_tree.last().is_some_and(|c| c.end > len) {
+            let last = self.capture_tree.pop().unwrap();
+            self.capture_tree.extend(last.children);
+        }
+    }
+
+    fn push(&mut self, cap: (String, Vec<u8>), span: Range<usize>) {
         let (name, bytes) = cap;
         // in Guidance, the __LIST_APPEND: ones are supposed to be appended not overwritten
         if !name.starts_with("__LIST_APPEND:") {
@@ -345,6 +428,7 @@ impl Captures {
             }
         }
         self.capture_list.push((name.clone(), bytes.clone()));
+        self.capture_spans.push(span);
         self.capture_map.insert(name, bytes);
     }
 }
@@ -983,6 +1067,40 @@ impl ParserState {
         self.grammar.sym_data(self.item_lhs(item))
     }
 
+    fn parse_tree(&self, lexer: &Lexer, named_only: bool) -> ParseNode {
+        let rows = (0..self.num_rows())
+            .map(|idx| {
+                let items = self.rows[idx]
+                    .item_indices()
+                    .map(|i| {
+                        let item = self.scratch.items[i];
+                        (item.rhs_ptr(), item.start_pos())
+                    })
+                    .collect();
+                if idx == 0 {
+                    return ParseRow {
+                        items,
+                        lexeme: 0..0,
+                        is_skip: false,
+                    };
+                }
+                let info = &self.row_infos[idx - 1];
+                let start = info.lexeme_byte_idx;
+                let is_skip = lexer
+                    .lexemes_from_idx(info.lexeme.idx)
+                    .as_slice()
+                    .iter()
+                    .any(|lx| self.lexer_spec().lexeme_spec(*lx).is_skip);
+                ParseRow {
+                    items,
+                    lexeme: start..start + info.lexeme.visible_bytes().len(),
+                    is_skip,
+                }
+            })
+            .collect::<Vec<_>>();
+        build_parse_tree(&self.grammar, &rows, named_only)
+    }
+
     fn hidden_start(&self, lexer: &mut Lexer) -> usize {
         let lexer_state = self.lexer_state().lexer_state;
         let hidden_len = lexer.possible_hidden_len(lexer_state);
@@ -1026,6 +1144,7 @@ impl ParserState {
         self.lexer_stack.truncate(new_len + 1);
 
         self.row_infos.truncate(self.num_rows());
+        self.captures.truncate_tree(new_len);
         self.token_idx = *self.byte_to_token_idx.last().unwrap_or(&0) as usize;
         self.last_force_bytes_len = usize::MAX;
         self.lexer_stack_top_eos = false;
@@ -1872,24 +1991,48 @@ impl ParserState {
             if is_lexeme { "lexeme" } else { "full" }
         );
 
+        // the current lexeme starts where the previous row started
+        let lexeme_start = self.lexeme_start_byte(curr_idx);
+
         if let Some(var_name) = sym_data.props.stop_capture_name.as_ref() {
             let bytes = lexeme.hidden_bytes();
-            self.captures.push(self.mk_capture(var_name, bytes));
+            // hidden bytes are not part of the output
+            let pos = lexeme_start + lexeme.num_visible_bytes();
+            self.captures.push_node(var_name, pos, pos);
+            self.captures
+                .push(self.mk_capture(var_name, bytes), pos..pos);
         }
 
         if let Some(var_name) = sym_data.props.capture_name.as_ref() {
             let mut bytes = Vec::new();
+            let mut start = lexeme_start;
             if capture_start < curr_idx {
+                start = self.row_infos[capture_start].lexeme_byte_idx;
                 bytes = self.row_infos[capture_start..curr_idx]
                     .iter()
                     .map(|ri| ri.lexeme.upper_visible_bytes(is_lexeme))
                     .collect::<Vec<_>>()
                     .concat();
             }
+            let visible = lexeme.upper_visible_bytes(is_lexeme);
+            let end = lexeme_start + visible.len();
             if is_lexeme || capture_start < curr_idx {
-                bytes.extend_from_slice(lexeme.upper_visible_bytes(is_lexeme));
+                bytes.extend_from_slice(visible);
+            } else {
+                // empty capture after the current lexeme
+                start = end;
             }
-            self.captures.push(self.mk_capture(var_name, &bytes));
+            self.captures.push_node(var_name, start, end);
+            self.captures
+                .push(self.mk_capture(var_name, &bytes), start..end);
+        }
+    }
+
+    fn lexeme_start_byte(&self, curr_idx: usize) -> usize {
+        if curr_idx == 0 {
+            0
+        } else {
+            self.row_infos[curr_idx - 1].lexeme_byte_idx
         }
     }
 
@@ -2027,7 +2170,10 @@ impl ParserState {
                         if let Some(var_name) = &sym_data.props.capture_name {
                             // nullable capture
                             debug!("      capture: {} NULL", var_name);
-                            self.captures.push((var_name.clone(), vec![]));
+                            let pos = self.lexeme_start_byte(curr_idx)
+                                + lexeme.upper_visible_bytes(false).len();
+                            self.captures.push_node(var_name, pos, pos);
+                            self.captures.push((var_name.clone(), vec![]), pos..pos);
                         }
                     }
                 }
@@ -2108,6 +2254,7 @@ impl ParserState {
                     token_idx_start: self.token_idx,
                     token_idx_stop: self.token_idx,
                     start_byte_idx,
+                    lexeme_byte_idx: 0,
                 });
                 // debug!("  push: {idx} {} {}", self.rows.len(), self.row_infos.len());
             }
@@ -2325,6 +2472,12 @@ impl ParserState {
                     .saturating_sub(1);
                 self.row_infos[added_row].start_byte_idx -= new_start;
             }
+            self.row_infos[added_row].lexeme_byte_idx = self.row_infos[added_row - 1]
+                .lexeme_byte_idx
+                + self.row_infos[added_row - 1]
+                    .lexeme
+                    .upper_visible_bytes(false)
+                    .len();
         }
         debug_def!(
             self,
@@ -2738,10 +2891,27 @@ impl Parser {
         &self.state.captures.capture_list
     }
 
+    /// Byte spans in [`Parser::get_bytes()`] of [`Parser::captures()`], in the same order.
+    pub fn capture_spans(&self) -> &[Range<usize>] {
+        &self.state.captures.capture_spans
+    }
+
     pub fn get_capture(&self, name: &str) -> Option<&[u8]> {
         self.state.captures.capture_map.get(name).map(|v| &v[..])
     }
 
+    /// Captures nested according to their byte spans in [`Parser::get_bytes()`].
+    pub fn capture_tree(&self) -> &[CaptureNode] {
+        &self.state.captures.capture_tree
+    }
+
+    /// The parse tree of the bytes parsed so far (as in [`Parser::get_bytes()`]);
+    /// see [`crate::Matcher::parse_tree()`].
+    pub fn parse_tree(&self, named_only: bool) -> ParseNode {
+        let shared = self.shared.lock().unwrap();
+        self.state.parse_tree(shared.lexer(), named_only)
+    }
+
     pub fn stats(&self) -> &ParserStats {
         &self.state.stats
     }
@@ -205,8 +205,8 @@ pub struct RegexVec {
     next_byte: NextByteCache,
     relevance: RelevanceCache,
     alpha: AlphabetInfo,
-    #[allow(dead_code)]
     rx_lexemes: Vec<RxLexeme>,
+    has_priorities: bool,
     lazy: LexemeSet,
     subsumable: LexemeSet,
     rx_list: Vec<ExprRef>,
@@ -291,8 +291,7 @@ impl RegexVec {
         let mut res = None;
         let exprs = &self.exprs;
         for (idx2, e) in iter_state(&self.rx_sets, state) {
-            if res.is_none() && exprs.is_nullable(e) {
-                assert!(desc.greedy_accepting.contains(idx2));
+            if res.is_none() && exprs.is_nullable(e) && desc.greedy_accepting.contains(idx2) {
                 res = Some(exprs.lookahead_len(e).unwrap_or(0));
             }
         }
@@ -449,14 +448,30 @@ impl RegexVec {
         }
 
         if lazies.is_some() {
-            desc.lazy_accepting = lazies;
+            desc.lazy_accepting = self.top_priority(lazies);
             desc.lazy_hidden_len = hidden_len;
         } else if all_eoi {
-            desc.lazy_accepting = eois;
+            desc.lazy_accepting = self.top_priority(eois);
             // no hidden len
         }
     }
 
+    /// Keep only the lexemes with the highest priority.
+    fn top_priority(&self, lexemes: MatchingLexemes) -> MatchingLexemes {
+        if !self.has_priorities || lexemes.len() < 2 {
+            return lexemes;
+        }
+        let prio = |idx: &LexemeIdx| self.rx_lexemes[idx.as_usize()].priority;
+        let max = lexemes.as_slice().iter().map(prio).max().unwrap();
+        let mut res = MatchingLexemes::None;
+        for idx in lexemes.as_slice() {
+            if prio(idx) == max {
+                res.add(*idx);
+            }
+        }
+        res
+    }
+
     /// Check if the there is only one transition out of state.
     /// This is an approximation - see docs for NextByte.
     pub fn next_byte(&mut self, state: StateID) -> NextByte {
@@ -565,7 +580,6 @@ impl RegexVec {
 pub(crate) struct RxLexeme {
     pub rx: ExprRef,
     pub lazy: bool,
-    #[allow(dead_code)]
     pub priority: i32,
 }
 
@@ -624,6 +638,8 @@ impl RegexVec {
             }
         }
 
+        let has_priorities = rx_lexemes.iter().any(|r| r.priority != 0);
+
         let rx_sets = StateID::new_hash_cons();
         let mut r = RegexVec {
             deriv: DerivCache::new(),
@@ -633,6 +649,7 @@ impl RegexVec {
             lazy,
             subsumable,
             rx_lexemes,
+            has_priorities,
             exprs: exprset,
             alpha,
             rx_list,
@@ -705,6 +722,7 @@ impl RegexVec {
                 res.greedy_accepting.add(idx);
             }
         }
+        res.greedy_accepting = self.top_priority(res.greedy_accepting);
 
         if res.possible.is_empty() {
             assert!(state == StateID::DEAD);
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use toktrie::{TokEnv, TokenId};
+
+use crate::Matcher;
+
+/// Limits and options for [`Enumerator`].
+#[derive(Debug, Clone)]
+pub struct EnumerateOptions {
+    /// Maximum number of tokens in a completion; longer ones are not explored.
+    /// Default: 32
+    pub max_tokens: usize,
+
+    /// Stop after this many completions.
+    /// Default: 1000
+    pub max_count: usize,
+
+    /// Only explore token sequences that are the tokenization of their bytes,
+    /// so that every string is yielded at most once.
+    /// This assumes prefixes of such sequences are also tokenizations of their bytes,
+    /// which holds for BPE tokenizers (modulo pre-tokenizer corner cases).
+    /// Otherwise, every token sequence leading to an accepting state is yielded.
+    /// Default: true
+    pub canonical_only: bool,
+}
+
+impl Default for EnumerateOptions {
+    fn default() -> Self {
+        Self {
+            max_tokens: 32,
+            max_count: 1000,
+            canonical_only: true,
+        }
+    }
+}
+
+/// A complete output of the grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Bytes generated after the starting state of the matcher.
+    pub bytes: Vec<u8>,
+    pub tokens: Vec<TokenId>,
+}
+
+/// Enumerates completions of the grammar, starting from the current state of a [`Matcher`].
+///
+/// This is a depth-first search over allowed tokens, using
+/// [`Matcher::compute_mask()`], [`Matcher::consume_token()`] and [`Matcher::rollback()`].
+/// Completions are yielded in order of token ids on the path;
+/// EOS is never part of [`Completion::tokens`].
+/// The number of explored states can be exponential in `max_tokens`,
+/// so this is meant for small grammars, e.g., closed-vocabulary classification,
+/// or testing grammars.
+pub struct Enumerator {
+    matcher: Matcher,
+    tok_env: TokEnv,
+    options: EnumerateOptions,
+    // tokens consumed since the start
+    tokens: Vec<TokenId>,
+    // length of final_bytes() at the start
+    start_bytes: usize,
+    // for each node on the path, the tokens still to explore (in reverse order)
+    stack: Vec<Vec<TokenId>>,
+    num_yielded: usize,
+    started: bool,
+    done: bool,
+}
+
+impl Enumerator {
+    pub fn new(matcher: Matcher, options: EnumerateOptions) -> Result<Self> {
+        let tok_env = matcher.tok_env()?;
+        let start_bytes = matcher.final_bytes().len();
+        Ok(Enumerator {
+            matcher,
+            tok_env,
+            options,
+            tokens: vec![],
+            start_bytes,
+            stack: vec![],
+            num_yielded: 0,
+            started: false,
+            done: false,
+        })
+    }
+
+    /// The matcher; between calls to [`Iterator::next()`] it's in the state
+    /// after the tokens of the last completion.
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    // called after reaching a new node; pushes its children to the stack
+    fn visit(&mut self) -> Result<Option<Completion>> {
+        let bytes = self.matcher.final_bytes()[self.start_bytes..].to_vec();
+        if self.options.canonical_only && self.tok_env.tokenize_bytes(&bytes) != self.tokens {
+            // no extension will be canonical either
+            self.stack.push(vec![]);
+            return Ok(None);
+        }
+
+        let accepting = self.matcher.is_accepting()?;
+
+        let children = if self.tokens.len() < self.options.max_tokens && !self.matcher.is_stopped()
+        {
+            let trie = self.tok_env.tok_trie();
+            let mut mask = self.matcher.compute_mask()?;
+            for &t in trie.eos_tokens() {
+                mask.disallow_token(t);
+            }
+            let mut children = mask.to_list();
+            children.reverse();
+            children
+        } else {
+            vec![]
+        };
+        self.stack.push(children);
+
+        if !accepting {
+            return Ok(None);
+        }
+        Ok(Some(Completion {
+            bytes,
+            tokens: self.tokens.clone(),
+        }))
+    }
+
+    fn next_inner(&mut self) -> Result<Option<Completion>> {
+        if !self.started {
+            self.started = true;
+            if let Some(c) = self.visit()? {
+                return Ok(Some(c));
+            }
+        }
+        while let Some(children) = self.stack.last_mut() {
+            match children.pop() {
+                Some(t) => {
+                    self.matcher.consume_token(t)?;
+                    self.tokens.push(t);
+                    if let Some(c) = self.visit()? {
+                        return Ok(Some(c));
+                    }
+                }
+                None => {
+                    self.stack.pop();
+                    if self.tokens.pop().is_some() {
+                        self.matcher.rollback(1)?;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Enumerator {
+    type Item = Result<Completion>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.num_yielded >= self.options.max_count {
+            return None;
+        }
+        match self.next_inner() {
+            Ok(Some(c)) => {
+                self.num_yielded += 1;
+                Some(Ok(c))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -6,7 +6,7 @@ use toktrie::{InferenceCapabilities, TokEnv};
 use crate::{
     api::{GrammarInit, ParserLimits, TopLevelGrammar},
     earley::{perf::ParserPerfCounters, SlicedBiasComputer},
-    Logger, TokenParser,
+    ImportResolver, Logger, RetrieveWrapper, TokenParser,
 };
 
 /// Compiles grammars and holds shared tokenizer state.
@@ -14,6 +14,8 @@ use crate::{
 /// A single `ParserFactory` is typically created once per model/tokenizer
 /// and reused across requests.  Call [`ParserFactory::create_parser()`] to
 /// obtain a [`TokenParser`] for an individual generation session.
+/// Cloning is cheap, as the tokenizer state is shared.
+#[derive(Clone)]
 pub struct ParserFactory {
     tok_env: TokEnv,
     slicer: Arc<SlicedBiasComputer>,
@@ -22,6 +24,8 @@ pub struct ParserFactory {
     buffer_log_level: u32,
     limits: ParserLimits,
     perf_counters: Arc<ParserPerfCounters>,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
+    json_retriever: Option<RetrieveWrapper>,
 }
 
 impl ParserFactory {
@@ -39,6 +43,8 @@ impl ParserFactory {
             buffer_log_level: 0,
             limits: ParserLimits::default(),
             perf_counters: Arc::new(ParserPerfCounters::default()),
+            import_resolver: None,
+            json_retriever: None,
         })
     }
 
@@ -64,6 +70,8 @@ impl ParserFactory {
             buffer_log_level: self.buffer_log_level,
             limits: self.limits.clone(),
             perf_counters: self.perf_counters.clone(),
+            import_resolver: self.import_resolver.clone(),
+            json_retriever: self.json_retriever.clone(),
         })
     }
 
@@ -103,6 +111,28 @@ impl ParserFactory {
         self
     }
 
+    /// Set the resolver for Lark `%import`s of modules other than `common`.
+    pub fn set_import_resolver(&mut self, resolver: Option<Arc<dyn ImportResolver>>) -> &mut Self {
+        self.import_resolver = resolver;
+        self
+    }
+
+    pub fn import_resolver(&self) -> Option<Arc<dyn ImportResolver>> {
+        self.import_resolver.clone()
+    }
+
+    /// Set the retriever for external `$ref`s of JSON schemas (top-level or `%json` in Lark).
+    /// Schemas can't override it, so this is the only way to let them read files
+    /// (with [`DirRetriever`](crate::DirRetriever)).
+    pub fn set_json_retriever(&mut self, retriever: Option<RetrieveWrapper>) -> &mut Self {
+        self.json_retriever = retriever;
+        self
+    }
+
+    pub fn json_retriever(&self) -> Option<RetrieveWrapper> {
+        self.json_retriever.clone()
+    }
+
     pub fn extra_lexemes(&self) -> Vec<String> {
         self.slicer.extra_lexemes()
     }
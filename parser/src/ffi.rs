@@ -30,6 +30,7 @@ use std::{
 };
 
 use anyhow::{bail, ensure, Result};
+use serde_json::Value;
 use toktrie::{
     ApproximateTokEnv, InferenceCapabilities, SimpleVob, TokEnv, TokRxInfo, TokTrie, TokenizerEnv,
 };
@@ -37,7 +38,9 @@ use toktrie::{
 use crate::{
     api::{GrammarInit, ParserLimits, TopLevelGrammar},
     earley::{SlicedBiasComputer, ValidationResult},
-    CommitResult, Constraint, Logger, Matcher, ParserFactory, StopController, TokenParser,
+    json::bundle_and_dir_retriever,
+    CommitResult, Constraint, Logger, Matcher, ParserFactory, RetrieveWrapper, StopController,
+    TokenParser,
 };
 
 struct CTokenizerInner {
@@ -431,6 +434,12 @@ pub struct LlgConstraintInit {
     /// The resource limits for the parser.
     /// Default values will be used for all fields that are 0.
     pub limits: ParserLimits,
+    /// Directory to load external `$ref`s of JSON schemas from, or NULL.
+    /// The path of the `$ref` URI is resolved relative to this directory.
+    pub json_schema_dir: *const c_char,
+    /// JSON object mapping URIs to schemas, used for external `$ref`s
+    /// of JSON schemas, or NULL. Takes precedence over `json_schema_dir`.
+    pub json_schema_bundle: *const c_char,
 }
 
 impl LlgConstraintInit {
@@ -460,9 +469,42 @@ impl LlgConstraintInit {
         Ok(unsafe { &(*self.tokenizer).factory })
     }
 
+    /// Build the retriever for external `$ref`s of JSON schemas
+    /// (top-level or `%json` in Lark) from `json_schema_dir` and `json_schema_bundle`.
+    fn json_retriever(&self) -> Result<Option<RetrieveWrapper>> {
+        let dir = if self.json_schema_dir.is_null() {
+            None
+        } else {
+            // SAFETY: the C caller needs to ensure the string is valid
+            Some(unsafe { c_str_to_str(self.json_schema_dir, "json_schema_dir") }?)
+        };
+        let bundle = if self.json_schema_bundle.is_null() {
+            None
+        } else {
+            // SAFETY: the C caller needs to ensure the string is valid
+            let bundle = unsafe { c_str_to_str(self.json_schema_bundle, "json_schema_bundle") }?;
+            match serde_json::from_str(bundle)? {
+                Value::Object(bundle) => Some(bundle),
+                _ => bail!("json_schema_bundle must be a JSON object"),
+            }
+        };
+        Ok(bundle_and_dir_retriever(bundle.as_ref(), dir))
+    }
+
     /// Compile `grammar` into a [`TokenParser`].
     pub fn build_parser(&self, grammar: TopLevelGrammar) -> Result<TokenParser> {
-        self.factory()?.create_parser_from_init_ext(
+        let factory = self.factory()?;
+        let with_retriever;
+        let factory = match self.json_retriever()? {
+            Some(retriever) => {
+                let mut f = factory.clone();
+                f.set_json_retriever(Some(retriever));
+                with_retriever = f;
+                &with_retriever
+            }
+            None => factory,
+        };
+        factory.create_parser_from_init_ext(
             GrammarInit::Serialized(grammar),
             self.logger(),
             self.inference_capabilities(),
@@ -504,6 +546,8 @@ unsafe impl Send for LlgConstraintStep {}
 pub struct LlgConstraint {
     local_error: Option<String>,
     last_logs: String,
+    last_capture_tree: String,
+    last_parse_tree: String,
     pub(crate) constraint: Option<Constraint>,
     last_commit_result: CommitResult,
 }
@@ -524,6 +568,8 @@ impl Clone for LlgConstraint {
         LlgConstraint {
             local_error: self.local_error.clone(),
             last_logs: self.last_logs.clone(),
+            last_capture_tree: self.last_capture_tree.clone(),
+            last_parse_tree: self.last_parse_tree.clone(),
             constraint: self.constraint.clone(),
             last_commit_result: self.last_commit_result.clone(),
         }
@@ -535,6 +581,8 @@ impl Default for LlgConstraint {
         LlgConstraint {
             local_error: None,
             last_logs: "\x00".to_string(),
+            last_capture_tree: "[]\x00".to_string(),
+            last_parse_tree: "null\x00".to_string(),
             constraint: None,
             last_commit_result: CommitResult::default(),
         }
@@ -657,6 +705,8 @@ pub extern "C" fn llg_constraint_init_set_defaults(
         ff_tokens_ok: false,
         backtrack_ok: false,
         limits: ParserLimits::default(),
+        json_schema_dir: std::ptr::null(),
+        json_schema_bundle: std::ptr::null(),
     };
 }
 
@@ -1137,6 +1187,36 @@ pub extern "C" fn llg_flush_logs(cc: &mut LlgConstraint) -> *const c_char {
     cc.last_logs.as_ptr() as *const c_char
 }
 
+/// Get the capture tree of the constraint as JSON.
+///
+/// The result is a list of `{"name", "start", "end", "children"}` objects,
+/// where `start` and `end` are byte offsets into the output of the constraint.
+/// The returned string is NUL-terminated and remains valid until the next
+/// call to this function or until the constraint is freed.
+#[no_mangle]
+pub extern "C" fn llg_get_capture_tree(cc: &mut LlgConstraint) -> *const c_char {
+    if let Some(constraint) = &cc.constraint {
+        cc.last_capture_tree = serde_json::to_string(&constraint.capture_tree()).unwrap();
+        cc.last_capture_tree.push('\0');
+    }
+    cc.last_capture_tree.as_ptr() as *const c_char
+}
+
+/// Get the parse tree of the output of the constraint as JSON.
+///
+/// See [`llg_matcher_get_parse_tree()`] for the format.
+/// Returns `null` if the constraint was not created successfully.
+/// The returned string is NUL-terminated and remains valid until the next
+/// call to this function or until the constraint is freed.
+#[no_mangle]
+pub extern "C" fn llg_get_parse_tree(cc: &mut LlgConstraint, named_only: bool) -> *const c_char {
+    if let Some(constraint) = &cc.constraint {
+        cc.last_parse_tree = serde_json::to_string(&constraint.parse_tree(named_only)).unwrap();
+        cc.last_parse_tree.push('\0');
+    }
+    cc.last_parse_tree.as_ptr() as *const c_char
+}
+
 fn build_stop_controller(
     tokenizer: &LlgTokenizer,
     stop_tokens: &[u32],
@@ -1249,6 +1329,8 @@ pub unsafe extern "C" fn llg_free_stop_controller(stop_ctrl: *mut LlgStopControl
 /// [`llg_matcher_get_error()`]. Free with [`llg_free_matcher()`].
 pub struct LlgMatcher {
     last_error: Option<String>,
+    last_capture_tree: String,
+    last_parse_tree: String,
     matcher: Matcher,
     saved_mask: Option<SimpleVob>,
     tok_env: TokEnv,
@@ -1314,6 +1396,8 @@ pub unsafe extern "C" fn llg_new_matcher(
     Box::into_raw(Box::new(LlgMatcher {
         matcher,
         last_error: None,
+        last_capture_tree: String::new(),
+        last_parse_tree: String::new(),
         saved_mask: None,
         tok_env,
     }))
@@ -1491,6 +1575,40 @@ pub extern "C" fn llg_matcher_get_error(matcher: &mut LlgMatcher) -> *const c_ch
     matcher.last_error.as_ref().unwrap().as_ptr() as *const c_char
 }
 
+/// Get the capture tree of the matcher as JSON.
+///
+/// The result is a list of `{"name", "start", "end", "children"}` objects,
+/// where `start` and `end` are byte offsets into the consumed output;
+/// nested captures are listed in `children`.
+/// The returned string is NUL-terminated and remains valid until the next
+/// call to this function or until the matcher is freed.
+#[no_mangle]
+pub extern "C" fn llg_matcher_get_capture_tree(matcher: &mut LlgMatcher) -> *const c_char {
+    matcher.last_capture_tree = serde_json::to_string(&matcher.matcher.capture_tree()).unwrap();
+    matcher.last_capture_tree.push('\0');
+    matcher.last_capture_tree.as_ptr() as *const c_char
+}
+
+/// Get the parse tree of the matcher as JSON.
+///
+/// Nodes are `{"name", "start", "end", "is_lexeme", "children"}` objects,
+/// where `start` and `end` are byte offsets into the consumed output.
+/// Rules have their right-hand side in `children`; lexemes have no children.
+/// If `named_only` is set, nodes of helper symbols are replaced by their children.
+/// Returns `null` if the matcher is in an error state.
+/// The returned string is NUL-terminated and remains valid until the next
+/// call to this function or until the matcher is freed.
+#[no_mangle]
+pub extern "C" fn llg_matcher_get_parse_tree(
+    matcher: &mut LlgMatcher,
+    named_only: bool,
+) -> *const c_char {
+    matcher.last_parse_tree =
+        serde_json::to_string(&matcher.matcher.parse_tree(named_only)).unwrap();
+    matcher.last_parse_tree.push('\0');
+    matcher.last_parse_tree.as_ptr() as *const c_char
+}
+
 /// Check whether the matcher is in an error state.
 #[no_mangle]
 pub extern "C" fn llg_matcher_is_error(matcher: &mut LlgMatcher) -> bool {
@@ -1596,6 +1714,8 @@ pub extern "C" fn llg_clone_matcher(matcher: &LlgMatcher) -> *mut LlgMatcher {
     Box::into_raw(Box::new(LlgMatcher {
         matcher: matcher.matcher.deep_clone(),
         last_error: matcher.last_error.clone(),
+        last_capture_tree: String::new(),
+        last_parse_tree: String::new(),
         saved_mask: None,
         tok_env: matcher.tok_env.clone(),
     }))
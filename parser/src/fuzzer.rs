@@ -0,0 +1,583 @@
+use std::collections::{hash_map::Entry, VecDeque};
+
+use anyhow::{anyhow, bail, Result};
+use derivre::{Regex, RegexAst, StateID};
+use toktrie::{ApproximateTokEnv, TokenId};
+
+use crate::{
+    api::TopLevelGrammar,
+    earley::{
+        lexerspec::{LexemeClass, LexemeIdx, LexerSpec},
+        CSymIdx,
+    },
+    HashMap, Matcher, ParserFactory, TokenParser,
+};
+
+/// How bytes of a lexeme are chosen by [`GrammarFuzzer`],
+/// where the regex of the lexeme leaves a choice.
+#[derive(Debug, Clone)]
+pub struct LexemeDistribution {
+    /// At every byte, the lexeme is finished (in the shortest possible way)
+    /// with probability `1 / (mean_len + 1)`.
+    pub mean_len: f64,
+
+    /// After this many bytes, the lexeme is always finished.
+    pub max_len: usize,
+
+    /// Relative weight of bytes other than the common ones
+    /// (ASCII letters, digits and space for strings; digits for numbers),
+    /// which have weight 1.0.
+    pub other_weight: f64,
+
+    /// Relative weight of non-ASCII bytes.
+    pub non_ascii_weight: f64,
+}
+
+/// Options for [`GrammarFuzzer`].
+#[derive(Debug, Clone)]
+pub struct FuzzOptions {
+    /// Seed for the random number generator.
+    /// Default: 0
+    pub seed: u64,
+
+    /// Beyond this depth of nested rules, only the alternatives with
+    /// the shallowest derivations are chosen.
+    /// Repetitions also count towards the depth.
+    /// Default: 30
+    pub max_depth: usize,
+
+    /// After this many lexemes (counting the ones already planned),
+    /// only the alternatives with the shallowest derivations are chosen,
+    /// which keeps the size of outputs of recursive grammars in check.
+    /// Default: 100
+    pub max_lexemes: usize,
+
+    /// The weight of an alternative is the product of weights of the symbols in it,
+    /// given by name: Lark rule or token name, or quoted literal (e.g., `"+"`);
+    /// symbols not listed here have weight 1.0.
+    /// Weight 0.0 excludes the alternative (unless all alternatives have weight 0.0).
+    /// Default: empty
+    pub symbol_weights: HashMap<String, f64>,
+
+    /// For lexemes that can only start with a digit, sign or dot.
+    /// Default: mean_len 3, max_len 12, other_weight 0.2, non_ascii_weight 0.0
+    pub number: LexemeDistribution,
+
+    /// For all other lexemes.
+    /// Default: mean_len 8, max_len 64, other_weight 0.1, non_ascii_weight 0.01
+    pub string: LexemeDistribution,
+
+    /// How many times to try generating an output accepted by the grammar.
+    /// Default: 100
+    pub max_attempts: usize,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            max_depth: 30,
+            max_lexemes: 100,
+            symbol_weights: HashMap::default(),
+            number: LexemeDistribution {
+                mean_len: 3.0,
+                max_len: 12,
+                other_weight: 0.2,
+                non_ascii_weight: 0.0,
+            },
+            string: LexemeDistribution {
+                mean_len: 8.0,
+                max_len: 64,
+                other_weight: 0.1,
+                non_ascii_weight: 0.01,
+            },
+            max_attempts: 100,
+        }
+    }
+}
+
+// splitmix64; good enough for test data and avoids a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn choose_weighted(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut r = self.next_f64() * total;
+        let mut last = None;
+        for (i, &w) in weights.iter().enumerate() {
+            if w > 0.0 {
+                if r < w {
+                    return Some(i);
+                }
+                r -= w;
+                last = Some(i);
+            }
+        }
+        // rounding
+        last
+    }
+}
+
+const INF: usize = usize::MAX;
+
+struct Alternative {
+    // empty for the epsilon alternative
+    rhs: Vec<CSymIdx>,
+    weight: f64,
+    height: usize,
+}
+
+#[derive(Default)]
+struct Symbol {
+    lexeme: Option<usize>,
+    alternatives: Vec<Alternative>,
+    // length of the shortest derivation path to lexemes; INF if none
+    height: usize,
+}
+
+struct Lexeme {
+    rx: Regex,
+    class: LexemeClass,
+    lazy: bool,
+    is_number: bool,
+    max_len: usize,
+}
+
+struct Class {
+    // any lexeme of the class used in the grammar
+    all_lexemes: Regex,
+    // shortest non-empty skip string, if any
+    separator: Option<Vec<u8>>,
+}
+
+/// Generates random strings accepted by a grammar (Lark, JSON schema, etc.),
+/// independent of any tokenizer.
+///
+/// The outputs are built top-down from the grammar rules:
+/// alternatives are picked according to [`FuzzOptions::symbol_weights`]
+/// (with the size limited by [`FuzzOptions::max_depth`] and [`FuzzOptions::max_lexemes`]),
+/// and lexemes are generated byte-by-byte from their regexes,
+/// according to [`FuzzOptions::number`] and [`FuzzOptions::string`].
+/// When two lexemes could be lexed as one, they are separated by a string
+/// matching the `%ignore` regex (whitespace in JSON), if any.
+/// Every output is checked with the parser, and generation is retried if it's not accepted
+/// (e.g., because of conditions in parametric grammars, which are otherwise ignored).
+/// Lexemes for special tokens are never generated.
+pub struct GrammarFuzzer {
+    parser: TokenParser,
+    options: FuzzOptions,
+    rng: Rng,
+    start: CSymIdx,
+    symbols: Vec<Symbol>,
+    lexemes: Vec<Lexeme>,
+    classes: HashMap<LexemeClass, Class>,
+    // bytes in order of preference for finishing lexemes
+    byte_order: Vec<u8>,
+}
+
+impl GrammarFuzzer {
+    pub fn new(mut grammar: TopLevelGrammar, options: FuzzOptions) -> Result<Self> {
+        // keep the names of Lark rules and tokens, for symbol_weights
+        for g in grammar.grammars.iter_mut() {
+            if let Some(lark) = g.lark_grammar.as_mut() {
+                lark.push_str("\n%llguidance { \"parse_tree\": true }\n");
+            }
+        }
+
+        let tok_env = ApproximateTokEnv::single_byte_env();
+        let mut factory = ParserFactory::new_simple(&tok_env)?;
+        factory.quiet();
+        let parser = factory.create_parser(grammar)?;
+
+        let mut byte_order = (0..=255u8).collect::<Vec<_>>();
+        byte_order.sort_by_key(|b| {
+            if b.is_ascii_alphanumeric() {
+                0
+            } else if b.is_ascii_graphic() || *b == b' ' {
+                1
+            } else {
+                2
+            }
+        });
+
+        let grammar = parser.parser.grammar();
+        let lexer_spec = grammar.lexer_spec();
+        let mut lexemes = vec![];
+        let mut lexeme_map = HashMap::default();
+        let mut symbols = vec![];
+        for idx in 0..grammar.num_symbols() {
+            let data = grammar.sym_data(CSymIdx::new_checked(idx));
+            let mut sym = Symbol {
+                height: INF,
+                ..Default::default()
+            };
+            if let Some(lx) = data.lexeme {
+                sym.lexeme = *lexeme_map.entry(lx).or_insert_with(|| {
+                    let l = Self::new_lexeme(&options, lexer_spec, lx)?;
+                    lexemes.push(l);
+                    Some(lexemes.len() - 1)
+                });
+                if sym.lexeme.is_some() {
+                    sym.height = 0;
+                }
+            } else if !data.is_terminal {
+                let mut has_nullable_rule = false;
+                for &rule in &data.rules {
+                    let rhs = grammar.rule_rhs(rule).0.to_vec();
+                    has_nullable_rule |= rhs.iter().all(|s| grammar.sym_data(*s).is_nullable);
+                    let weight = rhs
+                        .iter()
+                        .map(|s| Self::symbol_weight(&options, grammar.sym_name(*s)))
+                        .product();
+                    sym.alternatives.push(Alternative {
+                        rhs,
+                        weight,
+                        height: INF,
+                    });
+                }
+                // empty rules are only kept as nullability of the symbol
+                if (data.is_nullable || !data.cond_nullable.is_empty()) && !has_nullable_rule {
+                    sym.alternatives.push(Alternative {
+                        rhs: vec![],
+                        weight: 1.0,
+                        height: 1,
+                    });
+                }
+            }
+            symbols.push(sym);
+        }
+
+        Self::compute_heights(&mut symbols);
+        let start = grammar.start();
+        if symbols[start.as_index()].height == INF {
+            bail!("grammar doesn't generate any strings without special tokens");
+        }
+
+        let grammar_lexemes = lexeme_map.keys().copied().collect::<Vec<_>>();
+        let mut classes = HashMap::default();
+        for lexeme in &lexemes {
+            if let Entry::Vacant(e) = classes.entry(lexeme.class) {
+                e.insert(Self::new_class(
+                    &byte_order,
+                    lexer_spec,
+                    &grammar_lexemes,
+                    lexeme.class,
+                )?);
+            }
+        }
+
+        Ok(GrammarFuzzer {
+            rng: Rng(options.seed),
+            options,
+            start,
+            symbols,
+            lexemes,
+            classes,
+            byte_order,
+            parser,
+        })
+    }
+
+    fn symbol_weight(options: &FuzzOptions, name: &str) -> f64 {
+        let w = options.symbol_weights.get(name);
+        // also try without the suffix added when the name was already taken
+        let w = w.or_else(|| {
+            let (base, suff) = name.rsplit_once('#')?;
+            if suff.chars().all(|c| c.is_ascii_digit()) {
+                options.symbol_weights.get(base)
+            } else {
+                None
+            }
+        });
+        w.copied().unwrap_or(1.0)
+    }
+
+    fn new_lexeme(options: &FuzzOptions, lexer_spec: &LexerSpec, idx: LexemeIdx) -> Option<Lexeme> {
+        let spec = lexer_spec.lexeme_spec(idx);
+        if !spec.token_ranges.is_empty() || spec.is_skip {
+            return None;
+        }
+        let mut rx = lexer_spec.regex_builder.to_regex(spec.compiled_rx);
+        let init = rx.initial_state();
+        let first_bytes = (0..=255u8)
+            .filter(|b| !rx.transition(init, *b).is_dead())
+            .collect::<Vec<_>>();
+        // special tokens start with 0xFF, which is never valid UTF-8
+        if first_bytes.is_empty() || first_bytes.contains(&0xFF) {
+            return None;
+        }
+        let is_number = first_bytes.iter().any(|b| b.is_ascii_digit())
+            && first_bytes.iter().all(|b| b"+-.0123456789".contains(b));
+        let dist = if is_number {
+            &options.number
+        } else {
+            &options.string
+        };
+        Some(Lexeme {
+            rx,
+            class: spec.class(),
+            lazy: spec.is_lazy(),
+            is_number,
+            // with any tokenizer, tokens are at least one byte long
+            max_len: dist.max_len.min(spec.max_tokens()),
+        })
+    }
+
+    fn new_class(
+        byte_order: &[u8],
+        lexer_spec: &LexerSpec,
+        grammar_lexemes: &[LexemeIdx],
+        class: LexemeClass,
+    ) -> Result<Class> {
+        let mut builder = lexer_spec.regex_builder.clone();
+        let all = grammar_lexemes
+            .iter()
+            .map(|idx| lexer_spec.lexeme_spec(*idx))
+            .filter(|l| l.class() == class)
+            .map(|l| RegexAst::ExprRef(l.compiled_rx))
+            .collect::<Vec<_>>();
+        let all = builder.mk(&RegexAst::Or(all))?;
+
+        let skip = lexer_spec.lexeme_spec(lexer_spec.skip_id(class));
+        let mut skip_rx = builder.to_regex(skip.compiled_rx);
+        let init = skip_rx.initial_state();
+        let separator = if skip_rx.is_accepting(init) {
+            None
+        } else {
+            Self::shortest_suffix(byte_order, &mut skip_rx, init)
+        };
+
+        Ok(Class {
+            all_lexemes: builder.to_regex(all),
+            separator,
+        })
+    }
+
+    fn compute_heights(symbols: &mut [Symbol]) {
+        loop {
+            let mut changed = false;
+            for idx in 0..symbols.len() {
+                let mut sym_height = symbols[idx].height;
+                for alt_idx in 0..symbols[idx].alternatives.len() {
+                    let alt = &symbols[idx].alternatives[alt_idx];
+                    if alt.rhs.is_empty() {
+                        sym_height = sym_height.min(alt.height);
+                        continue;
+                    }
+                    let max = alt
+                        .rhs
+                        .iter()
+                        .map(|s| symbols[s.as_index()].height)
+                        .max()
+                        .unwrap();
+                    let height = max.saturating_add(1);
+                    if height < alt.height {
+                        symbols[idx].alternatives[alt_idx].height = height;
+                        changed = true;
+                    }
+                    sym_height = sym_height.min(height);
+                }
+                if sym_height < symbols[idx].height {
+                    symbols[idx].height = sym_height;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Generate a random string accepted by the grammar.
+    pub fn generate(&mut self) -> Result<Vec<u8>> {
+        for _ in 0..self.options.max_attempts {
+            let lexemes = self.gen_lexemes();
+            if let Some(bytes) = self.gen_bytes(&lexemes) {
+                if self.is_accepted(&bytes) {
+                    return Ok(bytes);
+                }
+            }
+        }
+        Err(anyhow!(
+            "no output accepted by the grammar in {} attempts",
+            self.options.max_attempts
+        ))
+    }
+
+    fn gen_lexemes(&mut self) -> Vec<usize> {
+        let mut res = vec![];
+        let mut stack = vec![(self.start, 0)];
+        while let Some((sym, depth)) = stack.pop() {
+            let sym = &self.symbols[sym.as_index()];
+            if let Some(lx) = sym.lexeme {
+                res.push(lx);
+                continue;
+            }
+            let shallow = depth >= self.options.max_depth
+                || res.len() + stack.len() >= self.options.max_lexemes;
+            let usable = |a: &Alternative| {
+                if !shallow {
+                    a.height != INF
+                } else {
+                    a.height == sym.height
+                }
+            };
+            let mut weights = sym
+                .alternatives
+                .iter()
+                .map(|a| if usable(a) { a.weight } else { 0.0 })
+                .collect::<Vec<_>>();
+            if weights.iter().all(|w| *w <= 0.0) {
+                weights = sym
+                    .alternatives
+                    .iter()
+                    .map(|a| if usable(a) { 1.0 } else { 0.0 })
+                    .collect();
+            }
+            // the height of the start symbol is finite, so there's always a usable one
+            let alt = &sym.alternatives[self.rng.choose_weighted(&weights).unwrap()];
+            for s in alt.rhs.iter().rev() {
+                stack.push((*s, depth + 1));
+            }
+        }
+        res
+    }
+
+    fn gen_bytes(&mut self, lexemes: &[usize]) -> Option<Vec<u8>> {
+        let mut res = vec![];
+        let mut prev: Option<(usize, usize)> = None;
+        for &lx in lexemes {
+            let bytes = self.gen_lexeme(lx)?;
+            if bytes.is_empty() {
+                continue;
+            }
+            if let Some((prev_lx, prev_start)) = prev {
+                let class = self.classes.get_mut(&self.lexemes[prev_lx].class).unwrap();
+                let rx = &mut class.all_lexemes;
+                let init = rx.initial_state();
+                let state = rx.transition_bytes(init, &res[prev_start..]);
+                if !rx.transition(state, bytes[0]).is_dead() {
+                    if let Some(sep) = &class.separator {
+                        res.extend_from_slice(sep);
+                    }
+                }
+            }
+            prev = Some((lx, res.len()));
+            res.extend_from_slice(&bytes);
+        }
+        Some(res)
+    }
+
+    fn byte_weight(dist: &LexemeDistribution, is_number: bool, b: u8) -> f64 {
+        let common = if is_number {
+            b.is_ascii_digit()
+        } else {
+            b.is_ascii_alphanumeric() || b == b' '
+        };
+        if common {
+            1.0
+        } else if b >= 0x80 {
+            dist.non_ascii_weight
+        } else {
+            dist.other_weight
+        }
+    }
+
+    fn gen_lexeme(&mut self, lx: usize) -> Option<Vec<u8>> {
+        let lexeme = &mut self.lexemes[lx];
+        let dist = if lexeme.is_number {
+            &self.options.number
+        } else {
+            &self.options.string
+        };
+        let p_finish = 1.0 / (dist.mean_len.max(0.0) + 1.0);
+        let mut state = lexeme.rx.initial_state();
+        let mut bytes = vec![];
+        loop {
+            if lexeme.lazy && lexeme.rx.is_accepting(state) {
+                return Some(bytes);
+            }
+            if bytes.len() >= lexeme.max_len || self.rng.next_f64() < p_finish {
+                let suffix = Self::shortest_suffix(&self.byte_order, &mut lexeme.rx, state)?;
+                bytes.extend_from_slice(&suffix);
+                return Some(bytes);
+            }
+            let mut weights = [0.0; 256];
+            for b in 0..=255u8 {
+                if !lexeme.rx.transition(state, b).is_dead() {
+                    weights[b as usize] = Self::byte_weight(dist, lexeme.is_number, b);
+                }
+            }
+            match self.rng.choose_weighted(&weights) {
+                Some(b) => {
+                    state = lexeme.rx.transition(state, b as u8);
+                    bytes.push(b as u8);
+                }
+                // nothing more can be added (or only with weight 0)
+                None => {
+                    let suffix = Self::shortest_suffix(&self.byte_order, &mut lexeme.rx, state)?;
+                    bytes.extend_from_slice(&suffix);
+                    return Some(bytes);
+                }
+            }
+        }
+    }
+
+    // breadth-first search for the shortest non-empty string leading to an accepting state
+    // (or the empty string if 'state' is already accepting)
+    fn shortest_suffix(byte_order: &[u8], rx: &mut Regex, state: StateID) -> Option<Vec<u8>> {
+        if rx.is_accepting(state) {
+            return Some(vec![]);
+        }
+        let mut parent: HashMap<StateID, (StateID, u8)> = HashMap::default();
+        let mut queue = VecDeque::from([state]);
+        while let Some(s) = queue.pop_front() {
+            for &b in byte_order {
+                let next = rx.transition(s, b);
+                if next.is_dead() || next == state || parent.contains_key(&next) {
+                    continue;
+                }
+                parent.insert(next, (s, b));
+                if rx.is_accepting(next) {
+                    let mut res = vec![];
+                    let mut curr = next;
+                    while curr != state {
+                        let (p, b) = parent[&curr];
+                        res.push(b);
+                        curr = p;
+                    }
+                    res.reverse();
+                    return Some(res);
+                }
+                if parent.len() > 10_000 {
+                    return None;
+                }
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    fn is_accepted(&self, bytes: &[u8]) -> bool {
+        let mut matcher = Matcher::new(Ok(self.parser.deep_clone()));
+        // in the single-byte tokenizer, token ids are byte values
+        let tokens = bytes.iter().map(|b| *b as TokenId).collect::<Vec<_>>();
+        matcher.consume_tokens(&tokens).is_ok() && matcher.is_accepting().unwrap_or(false)
+    }
+}
@@ -5,11 +5,11 @@ use crate::{
         Grammar, ParamCond, ParamExpr, SymIdx, SymbolProps,
     },
     hashcons::{HashCons, HashId},
-    HashMap,
+    HashMap, ImportResolver, RetrieveWrapper,
 };
 use anyhow::{bail, ensure, Result};
-use derivre::{ExprRef, RegexAst};
-use std::ops::RangeInclusive;
+use derivre::{ExprRef, JsonQuoteOptions, RegexAst};
+use std::{ops::RangeInclusive, sync::Arc};
 use toktrie::{bytes::limit_str, TokEnv, INVALID_TOKEN};
 
 use crate::api::{GenGrammarOptions, GenOptions, NodeProps};
@@ -56,10 +56,16 @@ pub struct GrammarBuilder {
     tok_env: Option<TokEnv>,
     limits: ParserLimits,
     warnings: HashMap<String, usize>,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
+    json_retriever: Option<RetrieveWrapper>,
 
     strings: HashMap<String, NodeRef>,
+    // JSON-quote lexemes of the current grammar (for JSON embedded in strings)
+    lexeme_json_quote: Option<JsonQuoteOptions>,
     at_most_cache: HashMap<(NodeRef, usize), NodeRef>,
     repeat_exact_cache: HashMap<(NodeRef, usize), NodeRef>,
+    // set when any grammar has the parse_tree option
+    parse_tree: bool,
 
     params: HashCons<ParamExpr>,
     self_ref: HashId<ParamExpr>,
@@ -173,10 +179,14 @@ impl GrammarBuilder {
             curr_lexeme_class: LexemeClass::ROOT,
             curr_start_idx: NodeRef::BOGUS,
             strings: HashMap::default(),
+            lexeme_json_quote: None,
             regex: RegexBuilder::new(),
             at_most_cache: HashMap::default(),
             repeat_exact_cache: HashMap::default(),
+            parse_tree: false,
             warnings: HashMap::default(),
+            import_resolver: None,
+            json_retriever: None,
             limits,
             tok_env,
             self_ref,
@@ -218,6 +228,7 @@ impl GrammarBuilder {
         self.curr_lexeme_class = self.regex.spec.setup_lexeme_class(skip)?;
 
         self.strings.clear();
+        self.lexeme_json_quote = None;
         self.at_most_cache.clear();
         self.repeat_exact_cache.clear();
 
@@ -236,6 +247,9 @@ impl GrammarBuilder {
         if options.allow_initial_skip {
             self.regex.spec.allow_initial_skip = true;
         }
+        if options.parse_tree {
+            self.parse_tree = true;
+        }
 
         // add root node
         self.curr_start_idx = self.new_node("start");
@@ -256,6 +270,32 @@ impl GrammarBuilder {
         self.grammar.num_symbols()
     }
 
+    /// Resolver for Lark `%import`s of modules other than `common`.
+    pub fn set_import_resolver(&mut self, resolver: Option<Arc<dyn ImportResolver>>) {
+        self.import_resolver = resolver;
+    }
+
+    pub fn import_resolver(&self) -> Option<Arc<dyn ImportResolver>> {
+        self.import_resolver.clone()
+    }
+
+    /// Retriever for external `$ref`s of JSON schemas, unless their
+    /// [`JsonCompileOptions`](crate::JsonCompileOptions) have their own.
+    pub fn set_json_retriever(&mut self, retriever: Option<RetrieveWrapper>) {
+        self.json_retriever = retriever;
+    }
+
+    pub fn json_retriever(&self) -> Option<RetrieveWrapper> {
+        self.json_retriever.clone()
+    }
+
+    /// JSON-quote all lexemes added from now on to the current grammar
+    /// (typically with `raw_mode`, so that they can be concatenated inside a string).
+    pub fn set_lexeme_json_quote(&mut self, options: Option<JsonQuoteOptions>) {
+        self.strings.clear();
+        self.lexeme_json_quote = options;
+    }
+
     pub fn string(&mut self, s: &str) -> NodeRef {
         if let Some(r) = self.strings.get(s) {
             return *r;
@@ -272,10 +312,11 @@ impl GrammarBuilder {
                     limit_str(s, 20),
                     RegexAst::Literal(s.to_string()),
                     false,
-                    None,
+                    self.lexeme_json_quote.clone(),
                     usize::MAX,
                 )
                 .unwrap();
+            self.regex.spec.request_priority(lx_id, 0);
             self.lexeme_to_node(lx_id)
         };
         self.strings.insert(s.to_string(), r);
@@ -454,11 +495,27 @@ impl GrammarBuilder {
         self.lexeme_ext(rx, None, NodeProps::default())
     }
 
+    /// Like [`GrammarBuilder::lexeme`], but when the lexer can match several lexemes
+    /// on the same text, only the ones with the highest `priority` are used (default is 0).
+    pub fn lexeme_with_priority(&mut self, rx: ExprRef, priority: i32) -> NodeRef {
+        self.lexeme_inner(rx, None, NodeProps::default(), priority)
+    }
+
     pub fn lexeme_ext(
         &mut self,
         rx: ExprRef,
         temperature: Option<f32>,
         props: NodeProps,
+    ) -> NodeRef {
+        self.lexeme_inner(rx, temperature, props, 0)
+    }
+
+    fn lexeme_inner(
+        &mut self,
+        rx: ExprRef,
+        temperature: Option<f32>,
+        props: NodeProps,
+        priority: i32,
     ) -> NodeRef {
         let idx = self
             .regex
@@ -467,10 +524,11 @@ impl GrammarBuilder {
                 props.capture_name.clone().unwrap_or_default(),
                 RegexAst::ExprRef(rx),
                 false,
-                None,
+                self.lexeme_json_quote.clone(),
                 props.max_tokens.unwrap_or(usize::MAX),
             )
             .unwrap();
+        self.regex.spec.request_priority(idx, priority);
         let r = self.lexeme_to_node(idx);
         self.grammar.apply_node_props(r.idx, props);
         if let Some(t) = temperature {
@@ -514,6 +572,12 @@ impl GrammarBuilder {
         (opts, needs_param)
     }
 
+    /// Whether named rules should be kept for parse trees
+    /// (`parse_tree` option in this or an earlier grammar).
+    pub fn parse_tree(&self) -> bool {
+        self.parse_tree
+    }
+
     pub fn rename(&mut self, node: NodeRef, name: &str) {
         self.grammar.rename_symbol(node.idx, name);
     }
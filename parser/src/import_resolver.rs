@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::HashMap;
+
+/// Resolves the module path of a Lark `%import` (e.g., `lib.sql` in
+/// `%import lib.sql (query)`) to the source of the grammar module.
+/// The `common` module is built-in and never passed to the resolver.
+pub trait ImportResolver: Send + Sync {
+    /// `module` is absolute (relative imports are already resolved)
+    /// and consists of dot-separated names.
+    fn resolve(&self, module: &str) -> Result<String>;
+}
+
+/// Serves grammar modules from memory, keyed by module path (`lib.sql`).
+#[derive(Debug, Clone, Default)]
+pub struct MapImportResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MapImportResolver {
+    pub fn new(modules: impl IntoIterator<Item = (String, String)>) -> Self {
+        MapImportResolver {
+            modules: modules.into_iter().collect(),
+        }
+    }
+
+    pub fn insert(&mut self, module: &str, source: String) {
+        self.modules.insert(module.to_string(), source);
+    }
+}
+
+impl ImportResolver for MapImportResolver {
+    fn resolve(&self, module: &str) -> Result<String> {
+        self.modules
+            .get(module)
+            .cloned()
+            .ok_or_else(|| anyhow!("module {module:?} not found"))
+    }
+}
+
+/// Serves grammar modules from `.lark` files in a search path of directories;
+/// `lib.sql` maps to `<dir>/lib/sql.lark` in the first directory where it exists.
+#[derive(Debug, Clone)]
+pub struct DirImportResolver {
+    dirs: Vec<PathBuf>,
+}
+
+impl DirImportResolver {
+    pub fn new(dirs: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        DirImportResolver {
+            dirs: dirs.into_iter().map(|d| d.into()).collect(),
+        }
+    }
+}
+
+impl ImportResolver for DirImportResolver {
+    fn resolve(&self, module: &str) -> Result<String> {
+        let parts = module.split('.').collect::<Vec<_>>();
+        ensure!(
+            parts
+                .iter()
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_alphanumeric() || c == '_')),
+            "invalid module path: {module:?}"
+        );
+        let mut rel_path: PathBuf = parts.iter().collect();
+        rel_path.set_extension("lark");
+        for dir in &self.dirs {
+            let path = dir.join(&rel_path);
+            if path.is_file() {
+                return std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("can't read {}: {e}", path.display()));
+            }
+        }
+        Err(anyhow!(
+            "module {module:?} not found; looked for {} in {:?}",
+            rel_path.display(),
+            self.dirs
+        ))
+    }
+}
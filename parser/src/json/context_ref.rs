@@ -101,6 +101,22 @@ impl<'a> Context<'a> {
         let resolved = self.resolver.lookup(reference)?;
         Ok(self.as_resource_ref(resolved.contents()))
     }
+
+    /// Like `lookup_resource()`, but also returns a context that resolves
+    /// references relative to the document the resource was found in.
+    pub fn lookup_resource_with_context(
+        &'a self,
+        reference: &str,
+    ) -> Result<(Context<'a>, ResourceRef<'a>)> {
+        let (contents, resolver, draft) = self.resolver.lookup(reference)?.into_inner();
+        let ctx = Context {
+            resolver,
+            draft,
+            shared: Rc::clone(&self.shared),
+            options: self.options.clone(),
+        };
+        Ok((ctx, self.as_resource_ref(contents)))
+    }
 }
 
 impl referencing::Retrieve for RetrieveWrapper {
@@ -69,6 +69,13 @@ impl<'a> Context<'a> {
         Ok(reference.to_string())
     }
 
+    pub fn lookup_resource_with_context(
+        &'a self,
+        reference: &str,
+    ) -> Result<(Context<'a>, ResourceRef<'a>)> {
+        Ok((self.clone(), self.lookup_resource(reference)?))
+    }
+
     pub fn lookup_resource(&'a self, reference: &str) -> Result<ResourceRef<'a>> {
         if reference == "#" || reference == "#/" {
             return Ok(self.as_resource_ref(self.root_doc.as_ref()));
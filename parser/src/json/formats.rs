@@ -1,3 +1,159 @@
+use anyhow::Result;
+
+use super::numeric::{rx_float_range, rx_int_range};
+
+// Building blocks of RFC 3986 URIs and RFC 3987 IRIs, parametrized by the class of unreserved characters.
+// Unlike "uri" below, they don't use named groups, so they can be repeated in alternatives.
+macro_rules! uri_unreserved {
+    () => {
+        r"a-zA-Z0-9\-._~"
+    };
+}
+// ucschar, without the private-use planes
+macro_rules! iri_unreserved {
+    () => {
+        r"a-zA-Z0-9\-._~\x{A0}-\x{D7FF}\x{F900}-\x{FDCF}\x{FDF0}-\x{FFEF}\x{10000}-\x{EFFFD}"
+    };
+}
+macro_rules! uri_pchar {
+    ($u:expr) => {
+        concat!("(?:[", $u, r"!$&'()*+,;=:@]|%[0-9a-fA-F]{2})")
+    };
+}
+macro_rules! uri_hier_common {
+    ($u:expr) => {
+        concat!(
+            // "//" authority path-abempty
+            r"//(?:(?:[",
+            $u,
+            r"!$&'()*+,;=:]|%[0-9a-fA-F]{2})*@)?",
+            r"(?:\[(?:[0-9a-fA-F:.]+|v[0-9a-fA-F]+\.[",
+            uri_unreserved!(),
+            r"!$&'()*+,;=:]+)\]",
+            r"|(?:[",
+            $u,
+            r"!$&'()*+,;=]|%[0-9a-fA-F]{2})*)",
+            r"(?::[0-9]*)?",
+            "(?:/",
+            uri_pchar!($u),
+            "*)*",
+            // path-absolute
+            "|/(?:",
+            uri_pchar!($u),
+            "+(?:/",
+            uri_pchar!($u),
+            "*)*)?",
+            // path-empty
+            "|",
+        )
+    };
+}
+macro_rules! uri_query_fragment {
+    ($u:expr) => {
+        concat!(
+            r"(?:\?(?:",
+            uri_pchar!($u),
+            r"|[/?])*)?",
+            r"(?:\#(?:",
+            uri_pchar!($u),
+            r"|[/?])*)?",
+        )
+    };
+}
+macro_rules! uri_absolute {
+    ($u:expr) => {
+        concat!(
+            r"[a-zA-Z][a-zA-Z0-9+\-.]*:(?:",
+            uri_hier_common!($u),
+            // path-rootless
+            "|",
+            uri_pchar!($u),
+            "+(?:/",
+            uri_pchar!($u),
+            "*)*",
+            ")",
+            uri_query_fragment!($u),
+        )
+    };
+}
+macro_rules! uri_reference {
+    ($u:expr) => {
+        concat!(
+            r"(?:[a-zA-Z][a-zA-Z0-9+\-.]*:(?:",
+            uri_hier_common!($u),
+            "|",
+            uri_pchar!($u),
+            "+(?:/",
+            uri_pchar!($u),
+            "*)*",
+            // relative-ref: path-noscheme has no ':' in the first segment
+            ")|(?:",
+            uri_hier_common!($u),
+            "|(?:[",
+            $u,
+            r"!$&'()*+,;=@]|%[0-9a-fA-F]{2})+(?:/",
+            uri_pchar!($u),
+            "*)*",
+            "))",
+            uri_query_fragment!($u),
+        )
+    };
+}
+macro_rules! email {
+    ($u:expr) => {
+        concat!(
+            r"[a-zA-Z0-9!#$%&'*+\-/=?\^_`{|}~", $u, r"]+(?:\.[a-zA-Z0-9!#$%&'*+\-/=?\^_`{|}~", $u, r"]+)*",
+            "@(?:",
+            hostname!($u),
+            r"|\[(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\]",
+            ")",
+        )
+    };
+}
+macro_rules! hostname {
+    ($u:expr) => {
+        concat!(
+            "[a-zA-Z0-9",
+            $u,
+            "](?:[a-zA-Z0-9",
+            $u,
+            "-]{0,61}[a-zA-Z0-9",
+            $u,
+            r"])?",
+            r"(?:\.[a-zA-Z0-9",
+            $u,
+            "](?:[a-zA-Z0-9",
+            $u,
+            "-]{0,61}[a-zA-Z0-9",
+            $u,
+            "])?)*",
+        )
+    };
+}
+// ECMA 262 regular expressions, with groups nested up to the number of '+' given
+macro_rules! ecma_regex {
+    () => {
+        concat!("(?:", ecma_atom!(), ecma_quantifier!(), r"|\|)*")
+    };
+    (+ $($rest:tt)*) => {
+        concat!(
+            "(?:(?:", ecma_atom!(),
+            r"|\((?:\?(?:[:=!]|<[=!]|<[a-zA-Z_$][a-zA-Z0-9_$]*>))?", ecma_regex!($($rest)*), r"\)",
+            ")", ecma_quantifier!(), r"|\|)*"
+        )
+    };
+}
+macro_rules! ecma_atom {
+    () => {
+        r"(?:\\(?s:.)|\[\^?\]?(?:[^\]\\]|\\(?s:.))*\]|[^\\\[()|*+?])"
+    };
+}
+macro_rules! ecma_quantifier {
+    () => {
+        r"(?:(?:[*+?]|\{[0-9]+(?:,[0-9]*)?\})\??)?"
+    };
+}
+
 pub fn lookup_format(name: &str) -> Option<&str> {
     let r = match name {
         "date-time" => concat!(
@@ -118,8 +274,80 @@ pub fn lookup_format(name: &str) -> Option<&str> {
             // [ "#" fragment ]
             r"(?:\#(?P<fragment>(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@/?]|%[0-9a-fA-F]{2})*))?"
         ),
+        "uri-reference" => uri_reference!(uri_unreserved!()),
+        "iri" => uri_absolute!(iri_unreserved!()),
+        "iri-reference" => uri_reference!(iri_unreserved!()),
+        // RFC 6570, up to level 4
+        "uri-template" => concat!(
+            r#"(?:[^\x00-\x20"'%<>\\^`{|}\x7F]|%[0-9a-fA-F]{2}"#,
+            r"|\{[+#./;?&=,!@|]?",
+            r"(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?",
+            r"(?:,(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?)*",
+            r"\})*"
+        ),
+        // RFC 6531 - non-ASCII characters allowed in both parts
+        "idn-email" => email!(r"\x{80}-\x{10FFFF}"),
+        "idn-hostname" => hostname!(r"\x{80}-\x{10FFFF}"),
+        // RFC 6901
+        "json-pointer" => r"(?:/(?:[^~/]|~[01])*)*",
+        "relative-json-pointer" => r"(?:0|[1-9][0-9]*)(?:#|(?:/(?:[^~/]|~[01])*)*)",
+        "regex" => ecma_regex!(+ + +),
+        // OpenAPI
+        "byte" => return lookup_content_encoding("base64"),
+        "binary" | "password" => r"(?s:.*)",
         "unknown" => r"(?s:.*)",
         _ => return None,
     };
     Some(r)
 }
+
+/// Regexes for the `contentEncoding` values we can enforce (RFC 4648, with padding).
+pub fn lookup_content_encoding(name: &str) -> Option<&str> {
+    let r = match name {
+        "base64" => r"(?:[A-Za-z0-9+/]{4})*(?:[A-Za-z0-9+/]{2}==|[A-Za-z0-9+/]{3}=)?",
+        "base32" => {
+            r"(?:[A-Z2-7]{8})*(?:[A-Z2-7]{2}={6}|[A-Z2-7]{4}={4}|[A-Z2-7]{5}={3}|[A-Z2-7]{7}=)?"
+        }
+        // RFC 4648 uses upper case, but lower case is common in practice
+        "base16" => r"(?:[0-9A-Fa-f]{2})*",
+        _ => return None,
+    };
+    Some(r)
+}
+
+/// Numeric formats (from OpenAPI).
+#[derive(Debug, Clone, Copy)]
+pub struct NumericFormat {
+    pub integer: bool,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+}
+
+impl NumericFormat {
+    /// Regex for the number as a string (e.g., int64 values are often passed as strings).
+    pub fn regex(&self) -> Result<String> {
+        if self.integer {
+            rx_int_range(
+                self.minimum.map(|v| v as i64),
+                self.maximum.map(|v| v as i64),
+            )
+        } else {
+            rx_float_range(self.minimum, self.maximum, true, true)
+        }
+    }
+}
+
+pub fn lookup_numeric_format(name: &str) -> Option<NumericFormat> {
+    let (integer, minimum, maximum) = match name {
+        "int32" => (true, Some(i32::MIN as f64), Some(i32::MAX as f64)),
+        "int64" => (true, Some(i64::MIN as f64), Some(i64::MAX as f64)),
+        // the range of f32 is too wide for a regex; out-of-range values are rare anyway
+        "float" | "double" => (false, None, None),
+        _ => return None,
+    };
+    Some(NumericFormat {
+        integer,
+        minimum,
+        maximum,
+    })
+}
@@ -1,6 +1,7 @@
 pub mod compiler;
 mod formats;
 mod numeric;
+mod retrievers;
 mod schema;
 mod shared_context;
 
@@ -18,6 +19,8 @@ pub mod context {
 
 use std::{any::type_name_of_val, sync::Arc};
 
+pub use retrievers::{bundle_and_dir_retriever, DirRetriever, MapRetriever};
+
 use serde_json::Value;
 pub fn json_merge(a: &mut Value, b: &Value) {
     match (a, b) {
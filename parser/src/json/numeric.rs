@@ -122,7 +122,13 @@ pub fn rx_int_range(left: Option<i64>, right: Option<i64>) -> Result<String> {
                     right
                 ));
             }
-            if left < 0 {
+            if left == i64::MIN {
+                // -i64::MIN overflows
+                Ok(mk_or(vec![
+                    format!("({left})"),
+                    rx_int_range(Some(left + 1), Some(right))?,
+                ]))
+            } else if left < 0 {
                 if right < 0 {
                     Ok(format!("(-{})", rx_int_range(Some(-right), Some(-left))?))
                 } else {
@@ -729,6 +735,22 @@ mod test_ranges {
         }
     }
 
+    #[test]
+    fn test_int_range_extremes() {
+        let rx = rx_int_range(Some(i64::MIN), Some(i64::MAX)).unwrap();
+        let re = Regex::new(&format!("^{rx}$")).unwrap();
+        for n in [i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX - 1, i64::MAX] {
+            assert!(re.is_match(&n.to_string()), "{n} should match {rx:?}");
+        }
+        for s in [
+            "-9223372036854775809",
+            "9223372036854775808",
+            "10000000000000000000",
+        ] {
+            assert!(!re.is_match(s), "{s} should not match {rx:?}");
+        }
+    }
+
     #[test]
     fn test_float_range() {
         let cases = vec![
@@ -0,0 +1,129 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use super::{Retrieve, RetrieveWrapper};
+use crate::HashMap;
+
+type RetrieveResult = std::result::Result<Value, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Serves external `$ref`s from an in-memory bundle of schemas keyed by URI.
+#[derive(Debug, Clone, Default)]
+pub struct MapRetriever {
+    schemas: HashMap<String, Value>,
+}
+
+impl MapRetriever {
+    pub fn new(schemas: impl IntoIterator<Item = (String, Value)>) -> Self {
+        MapRetriever {
+            schemas: schemas
+                .into_iter()
+                .map(|(uri, schema)| (strip_fragment(&uri).to_string(), schema))
+                .collect(),
+        }
+    }
+
+    pub fn insert(&mut self, uri: &str, schema: Value) {
+        self.schemas.insert(strip_fragment(uri).to_string(), schema);
+    }
+}
+
+impl Retrieve for MapRetriever {
+    fn retrieve(&self, uri: &str) -> RetrieveResult {
+        match self.schemas.get(strip_fragment(uri)) {
+            Some(schema) => Ok(schema.clone()),
+            None => Err(anyhow!("schema not found in bundle: {uri}").into()),
+        }
+    }
+}
+
+/// Serves external `$ref`s from JSON files in a directory.
+/// The path of the URI (ignoring scheme and host) is resolved relative to the directory,
+/// so both `https://example.com/schemas/a.json` and `schemas/a.json` (relative to the
+/// root schema) map to `<dir>/schemas/a.json`.
+#[derive(Debug, Clone)]
+pub struct DirRetriever {
+    root: PathBuf,
+}
+
+impl DirRetriever {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirRetriever { root: root.into() }
+    }
+
+    fn file_path(&self, uri: &str) -> Result<PathBuf> {
+        let path = strip_fragment(uri);
+        let path = path.split_once('?').map_or(path, |(p, _)| p);
+        let path = match path.split_once("://") {
+            Some((_, rest)) => rest.split_once('/').map_or("", |(_, p)| p),
+            None => path.strip_prefix("file:").unwrap_or(path),
+        };
+        let path = Path::new(path.trim_start_matches('/'));
+        if path.as_os_str().is_empty()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!("invalid schema path in URI: {uri}");
+        }
+        Ok(self.root.join(path))
+    }
+
+    fn load(&self, uri: &str) -> Result<Value> {
+        let path = self.file_path(uri)?;
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("can't read schema {}: {e}", path.display()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("invalid JSON in schema {}: {e}", path.display()))
+    }
+}
+
+impl Retrieve for DirRetriever {
+    fn retrieve(&self, uri: &str) -> RetrieveResult {
+        Ok(self.load(uri)?)
+    }
+}
+
+/// Tries the retrievers in order, returning the first schema found.
+pub(crate) struct ChainRetriever(pub Vec<Arc<dyn Retrieve>>);
+
+impl Retrieve for ChainRetriever {
+    fn retrieve(&self, uri: &str) -> RetrieveResult {
+        let mut errors = vec![];
+        for retriever in self.0.iter() {
+            match retriever.retrieve(uri) {
+                Ok(schema) => return Ok(schema),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        Err(anyhow!("can't retrieve {uri}: {}", errors.join("; ")).into())
+    }
+}
+
+/// Build a retriever from a schema bundle and/or a schema directory;
+/// the bundle takes precedence.
+pub fn bundle_and_dir_retriever(
+    bundle: Option<&Map<String, Value>>,
+    dir: Option<&str>,
+) -> Option<RetrieveWrapper> {
+    let mut retrievers: Vec<Arc<dyn Retrieve>> = vec![];
+    if let Some(bundle) = bundle {
+        retrievers.push(Arc::new(MapRetriever::new(
+            bundle.iter().map(|(k, v)| (k.clone(), v.clone())),
+        )));
+    }
+    if let Some(dir) = dir {
+        retrievers.push(Arc::new(DirRetriever::new(dir)));
+    }
+    match retrievers.len() {
+        0 => None,
+        1 => Some(RetrieveWrapper::new(retrievers.pop().unwrap())),
+        _ => Some(RetrieveWrapper::new(Arc::new(ChainRetriever(retrievers)))),
+    }
+}
+
+fn strip_fragment(uri: &str) -> &str {
+    uri.split_once('#').map_or(uri, |(u, _)| u)
+}
@@ -1,19 +1,27 @@
-use crate::{regex_to_lark, HashMap, JsonCompileOptions};
+use crate::{regex_to_ast, regex_to_lark, HashMap, JsonCompileOptions};
 use anyhow::{anyhow, bail, ensure, Result};
 use derivre::RegexAst;
 use indexmap::{IndexMap, IndexSet};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::mem;
 
 use super::context::{Context, Draft, PreContext, ResourceRef};
-use super::formats::lookup_format;
+use super::formats::{lookup_content_encoding, lookup_format, lookup_numeric_format};
 use super::numeric::Decimal;
 use super::shared_context::BuiltSchema;
 
 const TYPES: [&str; 6] = ["null", "boolean", "number", "string", "array", "object"];
 
+// Keywords constraining only strings and only numbers, that can be negated
+const STRING_KEYWORDS: [&str; 4] = ["minLength", "maxLength", "pattern", "format"];
+const NUMBER_KEYWORDS: [&str; 4] = ["minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum"];
+
+// Annotations that constrain strings when `enforce_content` is set
+const CONTENT_KEYWORDS: [&str; 3] = ["contentEncoding", "contentMediaType", "contentSchema"];
+
 // Keywords that are implemented in this module
-pub(crate) const IMPLEMENTED: [&str; 27] = [
+pub(crate) const IMPLEMENTED: [&str; 39] = [
     // Core
     "anyOf",
     "oneOf",
@@ -22,12 +30,20 @@ pub(crate) const IMPLEMENTED: [&str; 27] = [
     "const",
     "enum",
     "type",
+    "if",
+    "then",
+    "else",
+    "not",
     // Array
     "items",
     "additionalItems",
     "prefixItems",
     "minItems",
     "maxItems",
+    "uniqueItems",
+    "contains",
+    "minContains",
+    "maxContains",
     // Object
     "properties",
     "additionalProperties",
@@ -35,6 +51,10 @@ pub(crate) const IMPLEMENTED: [&str; 27] = [
     "required",
     "minProperties",
     "maxProperties",
+    "dependentRequired",
+    "dependentSchemas",
+    "dependencies",
+    "propertyNames",
     // String
     "minLength",
     "maxLength",
@@ -51,7 +71,7 @@ pub(crate) const IMPLEMENTED: [&str; 27] = [
 // Keywords that are used for metadata or annotations, not directly driving validation.
 // Note that some keywords like $id and $schema affect the behavior of other keywords, but
 // they can safely be ignored if other keywords aren't present
-pub(crate) const META_AND_ANNOTATIONS: [&str; 15] = [
+pub(crate) const META_AND_ANNOTATIONS: [&str; 16] = [
     "$anchor",
     "$defs",
     "definitions",
@@ -67,6 +87,7 @@ pub(crate) const META_AND_ANNOTATIONS: [&str; 15] = [
     "examples",
     "contentMediaType",
     "contentEncoding",
+    "contentSchema",
 ];
 
 fn limited_str(node: &Value) -> String {
@@ -86,7 +107,7 @@ pub enum Schema {
     Number(NumberSchema),
     String(StringSchema),
     Array(ArraySchema),
-    Object(ObjectSchema),
+    Object(Box<ObjectSchema>),
     Boolean(Option<bool>),
     AnyOf(Vec<Schema>),
     OneOf(Vec<Schema>),
@@ -140,17 +161,36 @@ pub struct StringSchema {
     pub min_length: usize,
     pub max_length: Option<usize>,
     pub regex: Option<RegexAst>,
+    /// Schema of the JSON document embedded in the string
+    /// (`contentMediaType: application/json` with `contentSchema`).
+    pub json_content: Option<Box<Schema>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ArraySchema {
     pub min_items: usize,
     pub max_items: Option<usize>,
     pub prefix_items: Vec<Schema>,
     pub items: Option<Box<Schema>>,
+    pub unique_items: bool,
+    pub contains: Option<Box<ContainsSchema>>,
 }
 
+/// `contains` together with `minContains` and `maxContains`.
 #[derive(Debug, Clone)]
+pub struct ContainsSchema {
+    pub schema: Schema,
+    /// Negation of `schema`; only needed when `max` is set.
+    pub negated: Option<Schema>,
+    pub min: usize,
+    pub max: Option<usize>,
+    /// Item schemas (one for each of prefixItems, then one for the remaining items)
+    /// intersected with `schema`, and with `negated` (or as is, when `max` is not set).
+    pub matching: Vec<Schema>,
+    pub other: Vec<Schema>,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ObjectSchema {
     pub properties: IndexMap<String, Schema>,
     pub pattern_properties: IndexMap<String, Schema>,
@@ -158,6 +198,10 @@ pub struct ObjectSchema {
     pub required: IndexSet<String>,
     pub min_properties: usize,
     pub max_properties: Option<usize>,
+    /// `dependentRequired`: if the key is present, all the listed properties must be too.
+    pub dependent_required: IndexMap<String, IndexSet<String>>,
+    /// `propertyNames`, already restricted to strings.
+    pub property_names: Option<Box<Schema>>,
 }
 
 pub trait OptSchemaExt {
@@ -341,30 +385,19 @@ impl Schema {
                     (None, Some(r)) | (Some(r), None) => Some(r),
                     (Some(r1), Some(r2)) => Some(RegexAst::And(vec![r1, r2])),
                 },
-            }),
-
-            (Schema::Array(mut a1), Schema::Array(mut a2)) => Schema::Array(ArraySchema {
-                min_items: a1.min_items.max(a2.min_items),
-                max_items: opt_min(a1.max_items, a2.max_items),
-                prefix_items: {
-                    let len = a1.prefix_items.len().max(a2.prefix_items.len());
-                    a1.prefix_items.resize_with(len, || a1.items.schema());
-                    a2.prefix_items.resize_with(len, || a2.items.schema());
-                    a1.prefix_items
-                        .into_iter()
-                        .zip(a2.prefix_items)
-                        .map(|(item1, item2)| item1.intersect(item2, ctx, stack_level + 1))
-                        .collect::<Result<Vec<_>>>()?
-                },
-                items: match (a1.items, a2.items) {
+                json_content: match (s1.json_content, s2.json_content) {
                     (None, None) => None,
-                    (None, Some(item)) | (Some(item), None) => Some(item),
-                    (Some(item1), Some(item2)) => {
-                        Some(Box::new(item1.intersect(*item2, ctx, stack_level + 1)?))
+                    (None, Some(c)) | (Some(c), None) => Some(c),
+                    (Some(c1), Some(c2)) => {
+                        Some(Box::new(c1.intersect(*c2, ctx, stack_level + 1)?))
                     }
                 },
             }),
 
+            (Schema::Array(a1), Schema::Array(a2)) => {
+                Schema::Array(intersect_arrays(a1, a2, ctx, stack_level)?)
+            }
+
             (Schema::Object(mut o1), Schema::Object(o2)) => {
                 let mut properties = IndexMap::new();
                 for (key, prop1) in std::mem::take(&mut o1.properties).into_iter() {
@@ -390,18 +423,24 @@ impl Schema {
                     stack_level,
                 )?;
 
-                let additional_properties =
-                    match (o1.additional_properties, o2.additional_properties) {
-                        (None, None) => None,
-                        (None, Some(p)) | (Some(p), None) => Some(p),
-                        (Some(p1), Some(p2)) => {
-                            Some(Box::new((*p1).intersect(*p2, ctx, stack_level + 1)?))
-                        }
-                    };
+                let additional_properties = intersect_opt_boxed(
+                    o1.additional_properties,
+                    o2.additional_properties,
+                    ctx,
+                    stack_level,
+                )?;
 
                 let min_properties = o1.min_properties.max(o2.min_properties);
                 let max_properties = opt_min(o1.max_properties, o2.max_properties);
 
+                let mut dependent_required = o1.dependent_required;
+                for (key, deps) in o2.dependent_required.into_iter() {
+                    dependent_required.entry(key).or_default().extend(deps);
+                }
+
+                let property_names =
+                    intersect_opt_boxed(o1.property_names, o2.property_names, ctx, stack_level)?;
+
                 mk_object_schema(ObjectSchema {
                     properties,
                     pattern_properties,
@@ -409,6 +448,8 @@ impl Schema {
                     required,
                     min_properties,
                     max_properties,
+                    dependent_required,
+                    property_names,
                 })
             }
 
@@ -538,6 +579,8 @@ pub struct SchemaBuilderOptions {
     pub max_size: usize,
     pub max_stack_level: usize,
     pub lenient: bool,
+    pub enforce_content: bool,
+    pub custom_formats: BTreeMap<String, String>,
 }
 
 impl Default for SchemaBuilderOptions {
@@ -546,6 +589,8 @@ impl Default for SchemaBuilderOptions {
             max_size: 50_000,
             max_stack_level: 128, // consumes ~2.5k of stack per level
             lenient: false,
+            enforce_content: false,
+            custom_formats: BTreeMap::new(),
         }
     }
 }
@@ -560,10 +605,12 @@ pub fn build_schema(contents: Value, options: &JsonCompileOptions) -> Result<Bui
         return Ok(BuiltSchema::simple(s));
     }
 
-    let pre_ctx = PreContext::new(contents, options.retriever.clone())?;
+    let pre_ctx = PreContext::new(contents, options.effective_retriever())?;
     let mut ctx = Context::new(&pre_ctx)?;
 
     ctx.options.lenient = options.lenient;
+    ctx.options.enforce_content = options.enforce_content;
+    ctx.options.custom_formats = options.custom_formats.clone();
 
     let root_resource = ctx.lookup_resource(&pre_ctx.base_uri)?;
     let schema = compile_resource(&ctx, root_resource)?;
@@ -607,10 +654,10 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
     ctx.increment()?;
 
     // We don't need to compile the schema if it's just meta and annotations
-    if schemadict
-        .keys()
-        .all(|k| META_AND_ANNOTATIONS.contains(k) || !ctx.draft.is_known_keyword(k))
-    {
+    if schemadict.keys().all(|k| {
+        !is_enforced_content(ctx, k)
+            && (META_AND_ANNOTATIONS.contains(k) || !ctx.draft.is_known_keyword(k))
+    }) {
         return Ok(Schema::Any);
     }
 
@@ -661,8 +708,9 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
     let mut result = Schema::Any;
     let mut current = HashMap::default();
     let in_place_applicator_kwds = ["const", "enum", "allOf", "anyOf", "oneOf", "$ref"];
+    let is_conditional = |k: &str| k == "if" && ctx.draft.is_known_keyword(k);
     for (k, v) in schemadict.iter() {
-        if in_place_applicator_kwds.contains(k) {
+        if in_place_applicator_kwds.contains(k) || is_conditional(k) || *k == "not" {
             if !current.is_empty() {
                 if let Some(&types) = schemadict.get("type") {
                     // Make sure we always give type information to ensure we get the smallest union we can
@@ -672,8 +720,18 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
                 result = result.intersect(current_schema, ctx, 0)?;
             }
             // Finally apply the applicator
-            result = result.apply((k, v), ctx)?;
-        } else if !META_AND_ANNOTATIONS.contains(k) {
+            if is_conditional(k) {
+                let then_v = schemadict.get("then").copied();
+                let else_v = schemadict.get("else").copied();
+                result = apply_conditional(ctx, result, v, then_v, else_v)?;
+            } else if *k == "not" {
+                result = apply_negation(ctx, result, v)?;
+            } else {
+                result = result.apply((k, v), ctx)?;
+            }
+        } else if *k == "then" || *k == "else" {
+            // handled together with "if"; without "if" they have no effect
+        } else if !META_AND_ANNOTATIONS.contains(k) || is_enforced_content(ctx, k) {
             current.insert(k, v);
             if *k == "additionalProperties" && !current.contains_key("properties") {
                 // additionalProperties needs to know about properties
@@ -704,6 +762,10 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
     Ok(result)
 }
 
+fn is_enforced_content(ctx: &Context, keyword: &str) -> bool {
+    ctx.options.enforce_content && CONTENT_KEYWORDS.contains(&keyword)
+}
+
 fn compile_contents_simple(ctx: &Context, schemadict: HashMap<&str, &Value>) -> Result<Schema> {
     if schemadict.is_empty() {
         Ok(Schema::Any)
@@ -730,11 +792,360 @@ fn compile_contents_simple(ctx: &Context, schemadict: HashMap<&str, &Value>) ->
     }
 }
 
+/// Apply `if`/`then`/`else` as `anyOf(allOf(if, then), allOf(not(if), else))`.
+fn apply_conditional(
+    ctx: &Context,
+    result: Schema,
+    if_v: &Value,
+    then_v: Option<&Value>,
+    else_v: Option<&Value>,
+) -> Result<Schema> {
+    let compile_branch = |v: Option<&Value>| match v {
+        Some(v) => compile_resource(ctx, ctx.as_resource_ref(v)),
+        None => Ok(Schema::Any),
+    };
+    let if_schema = compile_resource(ctx, ctx.as_resource_ref(if_v))?;
+    let then_schema = compile_branch(then_v)?;
+    let else_schema = compile_branch(else_v)?;
+
+    let conditional = match (&if_schema, compile_negation(ctx, if_v)?) {
+        (Schema::Any, _) => then_schema,
+        (Schema::Unsatisfiable(_), _) => else_schema,
+        (_, Some(not_if)) => Schema::AnyOf(vec![
+            if_schema.intersect(then_schema, ctx, 0)?,
+            not_if.intersect(else_schema, ctx, 0)?,
+        ]),
+        (_, None) => {
+            ctx.record_warning(format!(
+                "Unable to negate 'if' schema {}; approximating if/then/else as anyOf(allOf(if, then), else)",
+                limited_str(if_v)
+            ));
+            Schema::AnyOf(vec![if_schema.intersect(then_schema, ctx, 0)?, else_schema])
+        }
+    };
+    result.intersect(conditional.normalize(ctx), ctx, 0)
+}
+
+/// Apply `not` by intersecting with the complement of the negated schema.
+fn apply_negation(ctx: &Context, result: Schema, not_v: &Value) -> Result<Schema> {
+    match compile_negation(ctx, not_v)? {
+        Some(negated) => result.intersect(negated, ctx, 0),
+        None => {
+            let msg = format!("Unable to negate schema {}", limited_str(not_v));
+            if ctx.options.lenient {
+                ctx.record_warning(format!("{msg}; ignoring 'not'"));
+                Ok(result)
+            } else {
+                bail!(msg)
+            }
+        }
+    }
+}
+
+/// Compile the complement of a schema, for the limited cases where it can be
+/// expressed: `const`/`enum` of scalars, `type`, string and number constraints
+/// (except `multipleOf`), nested `not`, and object discriminators
+/// (`properties` with negatable values, `required`).
+/// Returns `None` when the negation can't be expressed.
+fn compile_negation(ctx: &Context, contents: &Value) -> Result<Option<Schema>> {
+    ctx.increment()?;
+    let schemadict = match contents {
+        Value::Bool(true) => return Ok(Some(Schema::false_schema())),
+        Value::Bool(false) => return Ok(Some(Schema::Any)),
+        Value::Object(obj) => obj
+            .iter()
+            .filter(|(k, _)| {
+                is_enforced_content(ctx, k)
+                    || (!META_AND_ANNOTATIONS.contains(&k.as_str())
+                        && ctx.draft.is_known_keyword(k))
+            })
+            .map(|(k, v)| (k.as_str(), v))
+            .collect::<IndexMap<_, _>>(),
+        _ => bail!("schema must be an object or boolean"),
+    };
+
+    let types = match schemadict.get("type") {
+        None => None,
+        Some(Value::String(tp)) => Some(vec![tp.as_str()]),
+        Some(Value::Array(types)) => Some(
+            types
+                .iter()
+                .map(|tp| tp.as_str().ok_or_else(|| anyhow!("type must be a string")))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        Some(_) => bail!("type must be a string or array of strings"),
+    };
+    let has_type = |tp: &str| {
+        types
+            .as_ref()
+            .is_none_or(|t| t.contains(&tp) || (tp == "integer" && t.contains(&"number")))
+    };
+    let only_keys = |keys: &[&str]| schemadict.keys().all(|k| keys.contains(k));
+
+    if schemadict.is_empty() {
+        return Ok(Some(Schema::false_schema()));
+    }
+
+    if only_keys(&["not"]) {
+        let inner = compile_resource(ctx, ctx.as_resource_ref(schemadict["not"]))?;
+        return Ok(Some(inner));
+    }
+
+    if only_keys(&["type", "const"]) || only_keys(&["type", "enum"]) {
+        let values = match (schemadict.get("const"), schemadict.get("enum")) {
+            (Some(v), None) => vec![*v],
+            (None, Some(v)) => v
+                .as_array()
+                .ok_or_else(|| anyhow!("enum must be an array"))?
+                .iter()
+                .collect(),
+            (None, None) => return compile_type_negation(ctx, types.as_deref()),
+            (Some(_), Some(_)) => return Ok(None),
+        };
+        // not(type and values) is not(values restricted to type)
+        let values = values
+            .into_iter()
+            .filter(|v| has_type(json_type_of(v)))
+            .collect::<Vec<_>>();
+        return Ok(compile_values_negation(&values));
+    }
+
+    if schemadict
+        .keys()
+        .all(|k| *k == "type" || STRING_KEYWORDS.contains(k) || NUMBER_KEYWORDS.contains(k))
+    {
+        return compile_scalar_negation(ctx, &schemadict, types.as_deref());
+    }
+
+    if only_keys(&["type", "properties", "required"]) {
+        if types.as_ref().is_some_and(|t| t != &["object"]) {
+            return Ok(None);
+        }
+        let mut options = vec![];
+        if types.is_some() {
+            // anything that is not an object fails the "type" check
+            match compile_type_negation(ctx, Some(&["object"]))? {
+                Some(schema) => options.push(schema),
+                None => return Ok(None),
+            }
+        }
+        let required = match schemadict.get("required") {
+            None => vec![],
+            Some(val) => val
+                .as_array()
+                .ok_or_else(|| anyhow!("Expected array for 'required', got {}", limited_str(val)))?
+                .iter()
+                .map(|item| {
+                    item.as_str().map(|s| s.to_string()).ok_or_else(|| {
+                        anyhow!(
+                            "Expected string for 'required' item, got {}",
+                            limited_str(item)
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+        // a required property is missing
+        for name in required {
+            options.push(mk_object_schema(ObjectSchema {
+                properties: IndexMap::from_iter([(name, Schema::false_schema())]),
+                ..ObjectSchema::default()
+            }));
+        }
+        // a property is present, but fails its schema
+        if let Some(properties) = schemadict.get("properties") {
+            let properties = properties
+                .as_object()
+                .ok_or_else(|| anyhow!("properties must be an object"))?;
+            for (name, prop) in properties {
+                match compile_negation(ctx, prop)? {
+                    Some(negated) => options.push(mk_object_schema(ObjectSchema {
+                        properties: IndexMap::from_iter([(name.clone(), negated)]),
+                        required: IndexSet::from_iter([name.clone()]),
+                        ..ObjectSchema::default()
+                    })),
+                    None => return Ok(None),
+                }
+            }
+        }
+        return Ok(Some(Schema::AnyOf(options).normalize(ctx)));
+    }
+
+    Ok(None)
+}
+
+fn json_type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        // JSON Schema treats integer-valued floats like 2.0 as integers
+        Value::Number(n) if n.as_f64().is_some_and(|f| f.fract() == 0.0) => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// All values of types other than `types`.
+fn compile_type_negation(ctx: &Context, types: Option<&[&str]>) -> Result<Option<Schema>> {
+    let types = types.unwrap_or_default();
+    if types.contains(&"integer") && !types.contains(&"number") {
+        // non-integer numbers can't be expressed
+        return Ok(None);
+    }
+    let options = TYPES
+        .iter()
+        .filter(|tp| !types.contains(tp))
+        .map(|tp| compile_type(ctx, tp, &HashMap::default()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(Schema::AnyOf(options).normalize(ctx)))
+}
+
+/// Strings and numbers failing the given keywords, and values of other types.
+fn compile_scalar_negation(
+    ctx: &Context,
+    schemadict: &IndexMap<&str, &Value>,
+    types: Option<&[&str]>,
+) -> Result<Option<Schema>> {
+    let types = types.unwrap_or(&TYPES);
+    if types.contains(&"integer") && !types.contains(&"number") {
+        // non-integer numbers can't be expressed
+        return Ok(None);
+    }
+    let has_keyword = |keywords: &[&str]| schemadict.keys().any(|k| keywords.contains(k));
+    let simple = schemadict
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .collect::<HashMap<_, _>>();
+
+    let mut options = vec![];
+    for tp in TYPES {
+        if !types.contains(&tp) {
+            options.push(compile_type(ctx, tp, &HashMap::default())?);
+        } else if tp == "string" && has_keyword(&STRING_KEYWORDS) {
+            match compile_string(ctx, &simple)? {
+                Schema::String(string) => options.extend(string_negation(string)),
+                // no string matches the schema, so all of them match the negation
+                _ => options.push(compile_type(ctx, tp, &HashMap::default())?),
+            }
+        } else if tp == "number" && has_keyword(&NUMBER_KEYWORDS) {
+            if let Schema::Number(number) = compile_numeric(&simple, false)? {
+                options.extend(number_negation(&number));
+            }
+        }
+    }
+    Ok(Some(Schema::AnyOf(options).normalize(ctx)))
+}
+
+/// Strings not matching the given schema; `None` if all strings match.
+fn string_negation(string: StringSchema) -> Option<Schema> {
+    let unconstrained = StringSchema {
+        min_length: 0,
+        max_length: None,
+        regex: None,
+        json_content: None,
+    };
+    if string.max_length.is_some_and(|max| max < string.min_length) {
+        // unsatisfiable, e.g. minLength > maxLength
+        return Some(Schema::String(unconstrained));
+    }
+    let mut conditions = vec![];
+    if string.min_length > 0 || string.max_length.is_some() {
+        conditions.push(RegexAst::Regex(format!(
+            "(?s:.{{{},{}}})",
+            string.min_length,
+            string.max_length.map_or(String::new(), |m| m.to_string())
+        )));
+    }
+    conditions.extend(string.regex);
+    if conditions.is_empty() {
+        return None;
+    }
+    Some(Schema::String(StringSchema {
+        regex: Some(RegexAst::Not(Box::new(RegexAst::And(conditions)))),
+        ..unconstrained
+    }))
+}
+
+/// Numbers below the minimum or above the maximum of the given schema.
+fn number_negation(number: &NumberSchema) -> Vec<Schema> {
+    let mut options = vec![];
+    if let (Some(min), exclusive) = number.get_minimum() {
+        options.push(Schema::Number(NumberSchema {
+            maximum: exclusive.then_some(min),
+            exclusive_maximum: (!exclusive).then_some(min),
+            ..NumberSchema::default()
+        }));
+    }
+    if let (Some(max), exclusive) = number.get_maximum() {
+        options.push(Schema::Number(NumberSchema {
+            minimum: exclusive.then_some(max),
+            exclusive_minimum: (!exclusive).then_some(max),
+            ..NumberSchema::default()
+        }));
+    }
+    options
+}
+
+/// All JSON values except the given scalars; `None` if any value is not a scalar.
+fn compile_values_negation(values: &[&Value]) -> Option<Schema> {
+    let mut strings = vec![];
+    let mut numbers = vec![];
+    let mut booleans = vec![];
+    let mut null = false;
+    for v in values {
+        match v {
+            Value::Null => null = true,
+            Value::Bool(b) => booleans.push(*b),
+            Value::Number(n) => numbers.push(n.as_f64()?),
+            Value::String(s) => strings.push(RegexAst::Literal(s.clone())),
+            Value::Array(_) | Value::Object(_) => return None,
+        }
+    }
+
+    let mut options = vec![];
+    if !null {
+        options.push(Schema::Null);
+    }
+    match (booleans.contains(&false), booleans.contains(&true)) {
+        (false, false) => options.push(Schema::Boolean(None)),
+        (true, false) => options.push(Schema::Boolean(Some(true))),
+        (false, true) => options.push(Schema::Boolean(Some(false))),
+        (true, true) => {}
+    }
+    // numbers in the open intervals between the excluded ones
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    numbers.dedup();
+    let mut lower = None;
+    for n in numbers.iter().copied().map(Some).chain([None]) {
+        options.push(Schema::Number(NumberSchema {
+            exclusive_minimum: lower,
+            exclusive_maximum: n,
+            ..NumberSchema::default()
+        }));
+        lower = n;
+    }
+    options.push(Schema::String(StringSchema {
+        min_length: 0,
+        max_length: None,
+        regex: if strings.is_empty() {
+            None
+        } else {
+            Some(RegexAst::Not(Box::new(RegexAst::Or(strings))))
+        },
+        json_content: None,
+    }));
+    options.push(Schema::Array(ArraySchema::default()));
+    options.push(Schema::Object(Box::default()));
+    Some(Schema::AnyOf(options))
+}
+
 fn define_ref(ctx: &Context, ref_uri: &str) -> Result<()> {
     if !ctx.been_seen(ref_uri) {
         ctx.mark_seen(ref_uri);
-        let resource = ctx.lookup_resource(ref_uri)?;
-        let resolved_schema = compile_resource(ctx, resource)?;
+        let (ref_ctx, resource) = ctx.lookup_resource_with_context(ref_uri)?;
+        let resolved_schema = compile_resource(&ref_ctx, resource)?;
         ctx.insert_ref(ref_uri, resolved_schema);
     }
     Ok(())
@@ -836,6 +1247,7 @@ fn compile_const(instance: &Value) -> Result<Schema> {
             min_length: 0,
             max_length: None,
             regex: Some(RegexAst::Literal(s.to_string())),
+            json_content: None,
         })),
         Value::Array(items) => {
             let prefix_items = items
@@ -847,6 +1259,8 @@ fn compile_const(instance: &Value) -> Result<Schema> {
                 max_items: Some(prefix_items.len()),
                 prefix_items,
                 items: Some(Box::new(Schema::false_schema())),
+                unique_items: false,
+                contains: None,
             }))
         }
         Value::Object(mapping) => {
@@ -855,14 +1269,16 @@ fn compile_const(instance: &Value) -> Result<Schema> {
                 .map(|(k, v)| Ok((k.clone(), compile_const(v)?)))
                 .collect::<Result<IndexMap<String, Schema>>>()?;
             let required = properties.keys().cloned().collect();
-            Ok(Schema::Object(ObjectSchema {
+            Ok(Schema::Object(Box::new(ObjectSchema {
                 properties,
                 pattern_properties: IndexMap::default(),
                 additional_properties: Some(Box::new(Schema::false_schema())),
                 required,
                 min_properties: 0,
                 max_properties: None,
-            }))
+                dependent_required: IndexMap::default(),
+                property_names: None,
+            })))
         }
     }
 }
@@ -904,6 +1320,10 @@ fn compile_numeric(schema: &HashMap<&str, &Value>, integer: bool) -> Result<Sche
     let exclusive_minimum = schema.get("exclusiveMinimum").copied();
     let exclusive_maximum = schema.get("exclusiveMaximum").copied();
     let multiple_of = schema.get("multipleOf").copied();
+    let numeric_format = schema
+        .get("format")
+        .and_then(|v| v.as_str())
+        .and_then(lookup_numeric_format);
 
     let minimum = match minimum {
         None => None,
@@ -954,6 +1374,15 @@ fn compile_numeric(schema: &HashMap<&str, &Value>, integer: bool) -> Result<Sche
             Some(Decimal::try_from(f.abs())?)
         }
     };
+    // other formats don't apply to numbers
+    let (minimum, maximum, integer) = match numeric_format {
+        Some(fmt) => (
+            opt_max(minimum, fmt.minimum),
+            opt_min(maximum, fmt.maximum),
+            integer || fmt.integer,
+        ),
+        None => (minimum, maximum, integer),
+    };
     Ok(Schema::Number(NumberSchema {
         minimum,
         maximum,
@@ -978,7 +1407,7 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
                 .as_str()
                 .ok_or_else(|| anyhow!("Expected string for 'pattern', got {}", limited_str(val)))?
                 .to_string();
-            RegexAst::SearchRegex(regex_to_lark(&s, "dw"))
+            regex_to_ast(&regex_to_lark(&s, "dw"), true)?
         }),
     };
     let format_rx = match format {
@@ -989,8 +1418,12 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
                 .ok_or_else(|| anyhow!("Expected string for 'format', got {}", limited_str(val)))?
                 .to_string();
 
-            if let Some(fmt) = lookup_format(&key) {
+            if let Some(rx) = ctx.options.custom_formats.get(&key) {
+                Some(RegexAst::Regex(regex_to_lark(rx, "dw")))
+            } else if let Some(fmt) = lookup_format(&key) {
                 Some(RegexAst::Regex(fmt.to_string()))
+            } else if let Some(fmt) = lookup_numeric_format(&key) {
+                Some(RegexAst::Regex(fmt.regex()?))
             } else {
                 let msg = format!("Unknown format: {key}");
                 if ctx.options.lenient {
@@ -1002,25 +1435,94 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
             }
         }
     };
-    let regex = match (pattern_rx, format_rx) {
-        (None, None) => None,
-        (None, Some(fmt)) => Some(fmt),
-        (Some(pat), None) => Some(pat),
-        (Some(pat), Some(fmt)) => Some(RegexAst::And(vec![pat, fmt])),
+    let (encoding_rx, json_content) = if ctx.options.enforce_content {
+        compile_string_content(ctx, schema)?
+    } else {
+        (None, None)
+    };
+    let mut regexes = [pattern_rx, format_rx, encoding_rx]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    let regex = match regexes.len() {
+        0 => None,
+        1 => regexes.pop(),
+        _ => Some(RegexAst::And(regexes)),
     };
     Ok(Schema::String(StringSchema {
         min_length,
         max_length,
         regex,
+        json_content,
     }))
 }
 
+/// `contentEncoding` as a regex, or `contentMediaType: application/json` (with `contentSchema`)
+/// as the schema of the embedded document.
+/// When the string is encoded, its media type applies to the decoded content and is not checked.
+fn compile_string_content(
+    ctx: &Context,
+    schema: &HashMap<&str, &Value>,
+) -> Result<(Option<RegexAst>, Option<Box<Schema>>)> {
+    let get_str = |name: &str| -> Result<Option<&str>> {
+        match schema.get(name) {
+            None => Ok(None),
+            Some(val) => val
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| anyhow!("Expected string for '{name}', got {}", limited_str(val))),
+        }
+    };
+    let unsupported = |msg: String| -> Result<()> {
+        if ctx.options.lenient {
+            ctx.record_warning(msg);
+            Ok(())
+        } else {
+            Err(anyhow!(msg))
+        }
+    };
+
+    if let Some(encoding) = get_str("contentEncoding")? {
+        return match lookup_content_encoding(&encoding.to_ascii_lowercase()) {
+            Some(rx) => Ok((Some(RegexAst::Regex(rx.to_string())), None)),
+            None => {
+                unsupported(format!("Unsupported contentEncoding: {encoding}"))?;
+                Ok((None, None))
+            }
+        };
+    }
+
+    if let Some(media_type) = get_str("contentMediaType")? {
+        // ignore parameters, like "; charset=utf-8"
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        if essence.eq_ignore_ascii_case("application/json") {
+            let content = match schema.get("contentSchema") {
+                Some(val) => compile_resource(ctx, ctx.as_resource_ref(val))?,
+                None => Schema::Any,
+            };
+            return Ok((None, Some(Box::new(content))));
+        }
+        unsupported(format!("Unsupported contentMediaType: {media_type}"))?;
+    }
+
+    Ok((None, None))
+}
+
 fn compile_array(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schema> {
     let min_items = get_usize(schema, "minItems")?.unwrap_or(0);
     let max_items = get_usize(schema, "maxItems")?;
     let prefix_items = schema.get("prefixItems").copied();
     let items = schema.get("items").copied();
     let additional_items = schema.get("additionalItems").copied();
+    let unique_items = match schema.get("uniqueItems").copied() {
+        None => false,
+        Some(val) => val.as_bool().ok_or_else(|| {
+            anyhow!(
+                "Expected boolean for 'uniqueItems', got {}",
+                limited_str(val)
+            )
+        })?,
+    };
 
     let (prefix_items, items) = {
         // Note that draft detection falls back to Draft202012 if the draft is unknown, so let's relax the draft constraint a bit
@@ -1052,12 +1554,64 @@ fn compile_array(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schema
         None => None,
         Some(val) => Some(Box::new(compile_resource(ctx, ctx.as_resource_ref(val))?)),
     };
-    Ok(Schema::Array(ArraySchema {
+
+    let contains = match schema.get("contains").copied() {
+        Some(val) if ctx.draft.is_known_keyword("contains") => {
+            let known = ctx.draft.is_known_keyword("minContains");
+            let min = if known {
+                get_usize(schema, "minContains")?.unwrap_or(1)
+            } else {
+                1
+            };
+            let mut max = if known {
+                get_usize(schema, "maxContains")?
+            } else {
+                None
+            };
+            let negated = if max.is_some() {
+                let negated = compile_negation(ctx, val)?;
+                if negated.is_none() {
+                    let msg = format!(
+                        "maxContains is only supported when the 'contains' schema can be negated, got {}",
+                        limited_str(val)
+                    );
+                    if ctx.options.lenient {
+                        ctx.record_warning(format!("{msg}; ignoring maxContains"));
+                        max = None;
+                    } else {
+                        bail!(msg);
+                    }
+                }
+                negated
+            } else {
+                None
+            };
+            if min == 0 && max.is_none() {
+                // always satisfied
+                None
+            } else {
+                Some(Box::new(ContainsSchema {
+                    schema: compile_resource(ctx, ctx.as_resource_ref(val))?,
+                    negated,
+                    min,
+                    max,
+                    matching: vec![],
+                    other: vec![],
+                }))
+            }
+        }
+        _ => None,
+    };
+
+    let arr = ArraySchema {
         min_items,
         max_items,
         prefix_items,
         items,
-    }))
+        unique_items,
+        contains,
+    };
+    Ok(Schema::Array(with_contains_items(arr, ctx, 0)?))
 }
 
 fn compile_prop_map(
@@ -1133,31 +1687,195 @@ fn compile_object(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
     };
     let required = match required {
         None => IndexSet::new(),
-        Some(val) => val
-            .as_array()
-            .ok_or_else(|| anyhow!("Expected array for 'required', got {}", limited_str(val)))?
-            .iter()
-            .map(|item| {
-                item.as_str()
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Expected string for 'required' item, got {}",
-                            limited_str(item)
-                        )
-                    })
-                    .map(|s| s.to_string())
-            })
-            .collect::<Result<IndexSet<String>>>()?,
+        Some(val) => compile_string_list("required", val)?,
+    };
+
+    let mut dependent_required = IndexMap::new();
+    let mut dependent_schemas = vec![];
+    for (kw, value) in schema.iter() {
+        if !matches!(
+            *kw,
+            "dependentRequired" | "dependentSchemas" | "dependencies"
+        ) || !ctx.draft.is_known_keyword(kw)
+        {
+            continue;
+        }
+        let deps = value
+            .as_object()
+            .ok_or_else(|| anyhow!("Expected object for '{kw}', got {}", limited_str(value)))?;
+        for (key, dep) in deps.iter() {
+            if *kw != "dependentSchemas" && dep.is_array() {
+                dependent_required
+                    .entry(key.clone())
+                    .or_insert_with(IndexSet::new)
+                    .extend(compile_string_list(kw, dep)?);
+            } else if *kw == "dependentRequired" {
+                bail!("Expected array for '{kw}' item, got {}", limited_str(dep));
+            } else if let Some(names) = flatten_dependent_schema(dep)? {
+                // { "required": [...] } is just dependentRequired in disguise
+                dependent_required
+                    .entry(key.clone())
+                    .or_insert_with(IndexSet::new)
+                    .extend(names);
+            } else {
+                dependent_schemas.push((key.clone(), dep));
+            }
+        }
+    }
+
+    let property_names = match schema.get("propertyNames") {
+        Some(val) if ctx.draft.is_known_keyword("propertyNames") => {
+            // property names are always strings
+            let names = compile_resource(ctx, ctx.as_resource_ref(val))?;
+            let any_string = Schema::String(StringSchema {
+                min_length: 0,
+                max_length: None,
+                regex: None,
+                json_content: None,
+            });
+            let names = names.intersect(any_string, ctx, 0)?;
+            Some(Box::new(names))
+        }
+        _ => None,
     };
 
-    Ok(mk_object_schema(ObjectSchema {
+    let mut result = mk_object_schema(ObjectSchema {
         properties,
         pattern_properties,
         additional_properties,
         required,
         min_properties,
         max_properties,
-    }))
+        dependent_required,
+        property_names,
+    });
+
+    // The remaining dependentSchemas are compiled as
+    // anyOf(key is absent, allOf(key is present, dependent schema)).
+    for (key, dep) in dependent_schemas {
+        let absent = mk_object_schema(ObjectSchema {
+            properties: IndexMap::from_iter([(key.clone(), Schema::false_schema())]),
+            ..ObjectSchema::default()
+        });
+        let present = mk_object_schema(ObjectSchema {
+            required: IndexSet::from_iter([key]),
+            ..ObjectSchema::default()
+        })
+        .intersect(compile_resource(ctx, ctx.as_resource_ref(dep))?, ctx, 0)?;
+        result = result.intersect(Schema::AnyOf(vec![absent, present]), ctx, 0)?;
+    }
+
+    Ok(result)
+}
+
+fn compile_string_list(name: &str, val: &Value) -> Result<IndexSet<String>> {
+    val.as_array()
+        .ok_or_else(|| anyhow!("Expected array for '{name}', got {}", limited_str(val)))?
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Expected string for '{name}' item, got {}",
+                        limited_str(item)
+                    )
+                })
+                .map(|s| s.to_string())
+        })
+        .collect()
+}
+
+/// Returns the list of required properties if the dependent schema
+/// only consists of `required` (and annotations).
+fn flatten_dependent_schema(dep: &Value) -> Result<Option<IndexSet<String>>> {
+    match dep {
+        Value::Bool(true) => Ok(Some(IndexSet::new())),
+        Value::Object(obj)
+            if obj
+                .keys()
+                .all(|k| k == "required" || META_AND_ANNOTATIONS.contains(&k.as_str())) =>
+        {
+            match obj.get("required") {
+                Some(req) => Ok(Some(compile_string_list("required", req)?)),
+                None => Ok(Some(IndexSet::new())),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn intersect_arrays(
+    mut a1: ArraySchema,
+    mut a2: ArraySchema,
+    ctx: &Context,
+    stack_level: usize,
+) -> Result<ArraySchema> {
+    let contains = match (a1.contains.take(), a2.contains.take()) {
+        (None, None) => None,
+        (None, Some(c)) | (Some(c), None) => Some(c),
+        (Some(_), Some(_)) => bail!("intersection of two 'contains' schemas is not supported"),
+    };
+    let arr = ArraySchema {
+        min_items: a1.min_items.max(a2.min_items),
+        max_items: opt_min(a1.max_items, a2.max_items),
+        prefix_items: {
+            let len = a1.prefix_items.len().max(a2.prefix_items.len());
+            a1.prefix_items.resize_with(len, || a1.items.schema());
+            a2.prefix_items.resize_with(len, || a2.items.schema());
+            a1.prefix_items
+                .into_iter()
+                .zip(a2.prefix_items)
+                .map(|(item1, item2)| item1.intersect(item2, ctx, stack_level + 1))
+                .collect::<Result<Vec<_>>>()?
+        },
+        items: intersect_opt_boxed(a1.items, a2.items, ctx, stack_level)?,
+        unique_items: a1.unique_items || a2.unique_items,
+        contains,
+    };
+    with_contains_items(arr, ctx, stack_level)
+}
+
+/// (Re-)compute the item schemas of `contains` for the current `prefixItems` and `items`.
+fn with_contains_items(
+    mut arr: ArraySchema,
+    ctx: &Context,
+    stack_level: usize,
+) -> Result<ArraySchema> {
+    if let Some(contains) = arr.contains.as_mut() {
+        let items = arr
+            .prefix_items
+            .iter()
+            .chain(std::iter::once(arr.items.schema_ref()));
+        contains.matching = vec![];
+        contains.other = vec![];
+        for item in items {
+            let matching = item
+                .clone()
+                .intersect(contains.schema.clone(), ctx, stack_level + 1)?;
+            contains.matching.push(matching);
+            let other = match &contains.negated {
+                Some(negated) => item
+                    .clone()
+                    .intersect(negated.clone(), ctx, stack_level + 1)?,
+                None => item.clone(),
+            };
+            contains.other.push(other);
+        }
+    }
+    Ok(arr)
+}
+
+fn intersect_opt_boxed(
+    s1: Option<Box<Schema>>,
+    s2: Option<Box<Schema>>,
+    ctx: &Context,
+    stack_level: usize,
+) -> Result<Option<Box<Schema>>> {
+    Ok(match (s1, s2) {
+        (None, None) => None,
+        (None, Some(s)) | (Some(s), None) => Some(s),
+        (Some(s1), Some(s2)) => Some(Box::new((*s1).intersect(*s2, ctx, stack_level + 1)?)),
+    })
 }
 
 fn mk_object_schema(obj: ObjectSchema) -> Schema {
@@ -1170,7 +1888,7 @@ fn mk_object_schema(obj: ObjectSchema) -> Schema {
         return Schema::unsat("required > maxProperties");
     }
 
-    Schema::Object(obj)
+    Schema::Object(Box::new(obj))
 }
 
 fn opt_max<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
@@ -1273,6 +1991,7 @@ mod test_retriever {
                 min_length: 0,
                 max_length: None,
                 regex: None,
+                json_content: None,
             }) => {}
             _ => panic!("Unexpected schema: {val:?}"),
         }
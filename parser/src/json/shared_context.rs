@@ -1,4 +1,4 @@
-use crate::{json::schema::OptSchemaExt, regex_to_lark, HashMap, HashSet};
+use crate::{json::schema::OptSchemaExt, regex_to_ast, regex_to_lark, HashMap, HashSet};
 use anyhow::{anyhow, bail, Result};
 use derivre::{Regex, RegexAst, RegexBuilder};
 
@@ -30,13 +30,20 @@ impl PatternPropertyCache {
         }
 
         let mut builder = RegexBuilder::new();
-        let eref = builder.mk_regex_for_serach(lark_regex.as_str())?;
+        let eref = builder.mk(&regex_to_ast(&lark_regex, true)?)?;
         let mut rx = builder.to_regex_limited(eref, CHECK_LIMIT)?;
         let res = rx.is_match(value);
         self.inner.insert(lark_regex, rx);
         Ok(res)
     }
 
+    /// Compile a regex for repeated matching, with the same limits as pattern properties.
+    pub fn mk_matcher(ast: &RegexAst) -> Result<Regex> {
+        let mut builder = RegexBuilder::new();
+        let eref = builder.mk(ast)?;
+        builder.to_regex_limited(eref, CHECK_LIMIT)
+    }
+
     pub fn check_disjoint(&mut self, regexes: &[&String]) -> Result<()> {
         // TODO cache something?
         let mut builder = RegexBuilder::new();
@@ -44,7 +51,7 @@ impl PatternPropertyCache {
             .iter()
             .map(|regex| {
                 let regex = regex_to_lark(regex, "dw");
-                builder.mk_regex_for_serach(regex.as_str())
+                builder.mk(&regex_to_ast(&regex, true)?)
             })
             .collect::<Result<Vec<_>>>()?;
         for (ai, a) in erefs.iter().enumerate() {
@@ -6,7 +6,7 @@ use crate::{
 use super::lexer::Location;
 
 /// Represents an item in the grammar (rule, token, or statement).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum Item {
     Rule(Rule),
@@ -25,7 +25,7 @@ impl Item {
 }
 
 /// Represents a grammar rule.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rule {
     pub name: String,
     #[allow(dead_code)]
@@ -46,7 +46,7 @@ pub struct Rule {
 }
 
 /// Represents a token definition.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenDef {
     pub name: String,
     pub params: Option<TokenParams>,
@@ -55,21 +55,14 @@ pub struct TokenDef {
 }
 
 /// Represents different types of statements.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Ignore(Expansions),
-    Import {
-        path: String,
-        alias: Option<String>,
-    },
-    MultiImport {
-        path: String,
-        names: Vec<String>,
-    },
+    Import { path: String, alias: Option<String> },
+    MultiImport { path: String, names: Vec<String> },
     LLGuidance(serde_json::Value),
-    #[allow(dead_code)]
     OverrideRule(Box<Rule>),
-    #[allow(dead_code)]
+    OverrideToken(Box<TokenDef>),
     Declare(Vec<String>),
 }
 
@@ -84,7 +77,7 @@ pub struct RuleParams(pub Vec<String>);
 pub struct TokenParams(pub Vec<String>);
 
 /// Represents an alternative (OR) of productions in a grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expansions(pub Location, pub Vec<Alias>);
 
 impl Expansions {
@@ -107,11 +100,59 @@ impl Expansions {
             Some(self.1[0].conjuncts.pop().unwrap().0.pop().unwrap().atom)
         }
     }
+
+    /// Call `f` on every rule/token name referenced (not descending into nested grammars).
+    pub fn for_each_name_mut(&mut self, f: &mut dyn FnMut(&mut String)) {
+        self.for_each_value_mut(&mut |v| v.for_each_name_mut(f));
+    }
+
+    /// Call `f` on every value of the expansions (but not on template arguments).
+    pub fn for_each_value_mut(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        for alias in self.1.iter_mut() {
+            for expansion in alias.conjuncts.iter_mut() {
+                for expr in expansion.0.iter_mut() {
+                    expr.atom.for_each_value_mut(f);
+                }
+            }
+        }
+    }
+}
+
+impl Atom {
+    pub fn for_each_value_mut(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        match self {
+            Atom::Group(expansions) | Atom::Maybe(expansions) => expansions.for_each_value_mut(f),
+            Atom::Value(value) => f(value),
+            Atom::Not(atom) => atom.for_each_value_mut(f),
+        }
+    }
+}
+
+impl Value {
+    pub fn for_each_name_mut(&mut self, f: &mut dyn FnMut(&mut String)) {
+        match self {
+            Value::Name(name) | Value::NameParam(name, _) => f(name),
+            Value::TemplateUsage { name, values } => {
+                f(name);
+                for v in values.iter_mut() {
+                    v.for_each_name_mut(f);
+                }
+            }
+            Value::LiteralRange(_, _, _)
+            | Value::LiteralString(_, _)
+            | Value::LiteralRegex(_, _)
+            | Value::GrammarRef(_)
+            | Value::SpecialToken(_)
+            | Value::Json(_)
+            | Value::NestedLark(_)
+            | Value::RegexExt(_) => {}
+        }
+    }
 }
 
 /// Represents an alias in the grammar.
 /// Each alias consists of possibly multiple conjuncts (AND).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Alias {
     pub conjuncts: Vec<Expansion>,
     pub param_cond: ParamCond,
@@ -120,11 +161,11 @@ pub struct Alias {
 }
 
 /// Represents a concatenation of expressions in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expansion(pub Vec<Expr>);
 
 /// Represents an expression.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expr {
     pub atom: Atom,
     pub op: Option<Op>,
@@ -132,7 +173,7 @@ pub struct Expr {
 }
 
 /// Represents an atom in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Atom {
     Group(Expansions),
     Maybe(Expansions),
@@ -141,9 +182,9 @@ pub enum Atom {
 }
 
 /// Represents different values in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Value {
-    LiteralRange(String, String),
+    LiteralRange(String, String, String),
     Name(String),
     NameParam(String, ParamExpr),
     LiteralString(String, String),
@@ -152,7 +193,7 @@ pub enum Value {
     SpecialToken(String),
     Json(serde_json::Value),
     NestedLark(Vec<Item>),
-    RegexExt(RegexExt),
+    RegexExt(Box<RegexExt>),
     #[allow(dead_code)]
     TemplateUsage {
         name: String,
@@ -168,6 +209,12 @@ impl Rule {
     pub fn stop_like(&self) -> Option<&Value> {
         self.stop.as_ref().or(self.suffix.as_ref())
     }
+    pub fn for_each_name_mut(&mut self, f: &mut dyn FnMut(&mut String)) {
+        self.expansions.for_each_name_mut(f);
+        for v in [&mut self.stop, &mut self.suffix].into_iter().flatten() {
+            v.for_each_name_mut(f);
+        }
+    }
     pub fn take_stop_like(&mut self) -> Option<Value> {
         self.stop.take().or_else(|| self.suffix.take())
     }
@@ -2,16 +2,18 @@ use crate::{
     earley::{ParamCond, ParamExpr},
     grammar_builder::{GrammarResult, RegexId},
     substring::substring,
-    HashMap,
+    HashMap, HashSet,
 };
 use anyhow::{anyhow, bail, ensure, Result};
 use derivre::RegexAst;
+use std::sync::Arc;
 
 use crate::{
     api::{GenGrammarOptions, GenOptions, GrammarId, LLGuidanceOptions, NodeProps, RegexExt},
-    json::json_merge,
+    json::{compiler::primitive_schema_regex, json_merge},
+    regex_to_ast,
     substring::{chunk_into_chars, chunk_into_words},
-    GrammarBuilder, JsonCompileOptions, NodeRef,
+    GrammarBuilder, ImportResolver, JsonCompileOptions, NodeRef,
 };
 
 use super::{
@@ -22,6 +24,7 @@ use super::{
 };
 
 const DEBUG: bool = false;
+const MAX_TEMPLATE_DEPTH: usize = 50;
 macro_rules! debug {
     ($($arg:tt)*) => {
         if cfg!(feature = "logging") && DEBUG {
@@ -35,6 +38,8 @@ macro_rules! debug {
 struct Grammar {
     rules: HashMap<String, Rule>,
     tokens: HashMap<String, TokenDef>,
+    // %declare'd names without definition; they never match
+    declared: HashSet<String>,
     ignore: Vec<Expansions>,
     llguidance_options: serde_json::Value,
 }
@@ -44,12 +49,22 @@ impl Default for Grammar {
         Self {
             rules: HashMap::default(),
             tokens: HashMap::default(),
+            declared: HashSet::default(),
             ignore: vec![],
             llguidance_options: serde_json::Value::Object(serde_json::Map::new()),
         }
     }
 }
 
+/// State for loading the modules of `%import` statements.
+struct Imports {
+    resolver: Option<Arc<dyn ImportResolver>>,
+    /// Modules being loaded, outermost first.
+    stack: Vec<String>,
+    /// Modules inherit python_compat of the main grammar.
+    python_compat: bool,
+}
+
 enum PendingGrammar {
     Json(serde_json::Value),
     Lark(Vec<Item>),
@@ -62,6 +77,10 @@ struct Compiler {
     node_ids: HashMap<String, NodeRef>,
     regex_ids: HashMap<String, RegexId>,
     in_progress: HashMap<String, bool>,
+    // priorities of TOKEN.N definitions
+    token_priority: HashMap<String, i32>,
+    // nesting of template instantiations
+    template_depth: usize,
     pending_grammars: Vec<(NodeRef, Location, PendingGrammar)>,
 }
 
@@ -73,13 +92,15 @@ fn compile_lark(builder: GrammarBuilder, parsed: ParsedLark) -> Result<GrammarRe
         node_ids: HashMap::default(),
         regex_ids: HashMap::default(),
         in_progress: HashMap::default(),
+        token_priority: HashMap::default(),
+        template_depth: 0,
         pending_grammars: vec![],
     };
     c.execute()
 }
 
 pub fn lark_to_llguidance(mut builder: GrammarBuilder, lark: &str) -> Result<GrammarResult> {
-    let parsed = parse_lark(lark)?;
+    let parsed = parse_lark(lark, false)?;
 
     let n = std::cmp::min(lark.len() / 8, 1_000_000);
     builder.regex.spec.regex_builder.reserve(n);
@@ -95,12 +116,20 @@ impl Compiler {
         if self.in_progress.contains_key(name) {
             bail!("circular reference in token {:?} definition", name);
         }
+        if self.grammar.declared.contains(name) {
+            let id = self.builder.regex.add_ast(RegexAst::NoMatch)?;
+            self.regex_ids.insert(name.to_string(), id);
+            return Ok(id);
+        }
         self.in_progress.insert(name.to_string(), false);
         let token = self
             .grammar
             .tokens
             .remove(name)
             .ok_or_else(|| anyhow!("unknown name: {:?}", name))?;
+        if let Some(priority) = token.priority {
+            self.token_priority.insert(name.to_string(), priority);
+        }
         let id = self.do_token_expansions(token.expansions)?;
         self.regex_ids.insert(name.to_string(), id);
         self.in_progress.remove(name);
@@ -108,9 +137,8 @@ impl Compiler {
     }
 
     fn mk_regex(&mut self, info: &str, rx: String) -> Result<RegexId> {
-        self.builder
-            .regex
-            .regex(&rx)
+        regex_to_ast(&rx, false)
+            .and_then(|ast| self.builder.regex.add_ast(ast))
             .map_err(|e| anyhow!("invalid regex {rx:?} (in {info}): {e}"))
     }
 
@@ -127,7 +155,7 @@ impl Compiler {
                 Ok(self.builder.regex.not(id))
             }
             Atom::Value(value) => match value {
-                Value::LiteralRange(a, b) => {
+                Value::LiteralRange(a, b, flags) => {
                     ensure!(
                         a.chars().count() == 1,
                         "range start must be a single character"
@@ -139,10 +167,11 @@ impl Compiler {
                     let a = a.chars().next().unwrap();
                     let b = b.chars().next().unwrap();
                     if a <= b {
+                        let flags = if flags.contains("i") { "(?i)" } else { "" };
                         self.mk_regex(
                             "range",
                             format!(
-                                "[{}-{}]",
+                                "{flags}[{}-{}]",
                                 regex_syntax::escape(&a.to_string()),
                                 regex_syntax::escape(&b.to_string())
                             ),
@@ -164,6 +193,9 @@ impl Compiler {
                 }
                 Value::LiteralRegex(val, flags) => {
                     ensure!(!flags.contains("l"), "l-flag is not supported in regexes");
+                    // terminals are always matched in full, so ^ and $ can only appear
+                    // at the ends of the regex, where multi-line mode makes no difference
+                    let flags = flags.replace('m', "");
                     let rx = if flags.is_empty() {
                         val
                     } else {
@@ -171,7 +203,7 @@ impl Compiler {
                     };
                     self.mk_regex("regex", rx)
                 }
-                Value::RegexExt(s) => compile_lark_regex(&mut self.builder, s),
+                Value::RegexExt(s) => compile_lark_regex(&mut self.builder, *s),
                 Value::SpecialToken(s) => {
                     bail!("special tokens (like {:?}) cannot be used in terminals", s);
                 }
@@ -190,7 +222,9 @@ impl Compiler {
                 Value::NameParam(_, _) => {
                     bail!("name::param cannot be used in terminals");
                 }
-                Value::TemplateUsage { .. } => bail!("template usage not supported yet"),
+                Value::TemplateUsage { name, .. } => {
+                    bail!("templates (like {:?}) cannot be used in terminals", name);
+                }
             },
         }
     }
@@ -363,17 +397,34 @@ impl Compiler {
                     // special case "" literal, so it doesn't pollute grammar with epsilon regex
                     Value::LiteralString(s, _) if s.is_empty() => return Ok(self.builder.empty()),
                     Value::RegexExt(_)
-                    | Value::LiteralRange(_, _)
+                    | Value::LiteralRange(_, _, _)
                     | Value::LiteralString(_, _)
                     | Value::LiteralRegex(_, _) => {
                         // treat as token
                     }
-                    Value::TemplateUsage { .. } => {
-                        bail!("template usage not supported yet");
+                    Value::TemplateUsage { name, values } => {
+                        return self.do_template(name, values);
                     }
                 };
+                let token_name = match &value {
+                    Value::Name(n) => Some(n.clone()),
+                    _ => None,
+                };
+                // name of the lexeme in parse trees
+                let display_name = match &value {
+                    Value::Name(n) => Some(n.clone()),
+                    Value::LiteralString(s, flags) => Some(format!("{s:?}{flags}")),
+                    _ => None,
+                };
                 let rx = self.do_token_atom(Atom::Value(value))?;
-                Ok(self.lift_regex(rx)?)
+                let node = match token_name.and_then(|n| self.token_priority.get(&n)) {
+                    Some(&priority) => self.builder.lexeme_with_priority(rx, priority),
+                    None => self.lift_regex(rx)?,
+                };
+                if let Some(name) = display_name.filter(|_| self.builder.parse_tree()) {
+                    self.builder.rename(node, &name);
+                }
+                Ok(node)
             }
         }
     }
@@ -469,6 +520,60 @@ impl Compiler {
         self.builder.apply(id, param)
     }
 
+    /// Instantiate template `name` with `args` as a rule named after the arguments
+    /// (e.g., `_separated{item, ","}`), so instances are shared and can be recursive.
+    fn do_template(&mut self, name: &str, args: &[Value]) -> Result<NodeRef> {
+        let inst_name = format!(
+            "{name}{{{}}}",
+            args.iter()
+                .map(template_arg_key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if self.is_rule(&inst_name) {
+            return self.do_rule(&inst_name, None);
+        }
+
+        let template = self
+            .grammar
+            .rules
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown template: {:?}", name))?;
+        let params = &template
+            .params
+            .as_ref()
+            .ok_or_else(|| anyhow!("{:?} is not a template", name))?
+            .0;
+        ensure!(
+            params.len() == args.len(),
+            "template {:?} expects {} argument(s), got {}",
+            name,
+            params.len(),
+            args.len()
+        );
+        ensure!(
+            self.template_depth < MAX_TEMPLATE_DEPTH,
+            "template instantiation too deep at {}; template recursion with growing arguments?",
+            inst_name
+        );
+
+        let args: HashMap<&str, &Value> = params.iter().map(|p| p.as_str()).zip(args).collect();
+        let mut rule = template.clone();
+        rule.name = inst_name.clone();
+        rule.params = None;
+        rule.expansions
+            .for_each_value_mut(&mut |v| substitute_value(v, &args));
+        for v in [&mut rule.stop, &mut rule.suffix].into_iter().flatten() {
+            substitute_value(v, &args);
+        }
+        self.grammar.rules.insert(inst_name.clone(), rule);
+
+        self.template_depth += 1;
+        let r = self.do_rule(&inst_name, None);
+        self.template_depth -= 1;
+        r
+    }
+
     fn gen_grammar(
         &mut self,
         name: &str,
@@ -498,6 +603,11 @@ impl Compiler {
             .rules
             .remove(name)
             .ok_or_else(|| anyhow!("rule {:?} not found", name))?;
+        ensure!(
+            rule.params.is_none(),
+            "template {:?} used without arguments",
+            name
+        );
 
         self.in_progress
             .insert(name.to_string(), rule.is_parametric);
@@ -505,6 +615,8 @@ impl Compiler {
         let props = NodeProps {
             max_tokens: rule.max_tokens,
             capture_name: rule.capture_name.clone(),
+            priority: rule.priority.filter(|&p| p != 0),
+            named: self.builder.parse_tree(),
             ..Default::default()
         };
 
@@ -609,7 +721,10 @@ impl Compiler {
                         ..Default::default()
                     },
                 )
-            } else if rule.capture_name.is_some() || (inner.is_parametric() && !rule.is_parametric)
+            } else if props.capture_name.is_some()
+                || props.priority.is_some()
+                || props.named
+                || (inner.is_parametric() && !rule.is_parametric)
             {
                 self.builder.join_props(&[inner], props)
             } else {
@@ -621,9 +736,15 @@ impl Compiler {
 
     fn execute(mut self) -> Result<GrammarResult> {
         let mut grm = Grammar::default();
+        let mut imports = Imports {
+            resolver: self.builder.import_resolver(),
+            stack: vec![],
+            python_compat: self.parsed.python_compat,
+        };
         for item in std::mem::take(&mut self.parsed.items) {
             let loc = item.location().clone();
-            grm.process_item(item).map_err(|e| loc.augment(e))?;
+            grm.process_item(item, &mut imports)
+                .map_err(|e| loc.augment(e))?;
         }
         let start_name = "start";
         ensure!(
@@ -647,13 +768,20 @@ impl Compiler {
         let start = self.do_rule(start_name, None)?;
         self.builder.set_start_node(start);
 
+        let python_compat = self.parsed.python_compat;
         let mut builder = self.builder;
         for (gg, loc, grm) in self.pending_grammars {
             let res = match grm {
                 PendingGrammar::Json(json_schema) => JsonCompileOptions::default()
                     .json_to_llg_with_overrides(builder, json_schema)
                     .map_err(|e| loc.augment(anyhow!("failed to compile JSON schema: {}", e)))?,
-                PendingGrammar::Lark(items) => compile_lark(builder, ParsedLark { items })?,
+                PendingGrammar::Lark(items) => compile_lark(
+                    builder,
+                    ParsedLark {
+                        items,
+                        python_compat,
+                    },
+                )?,
             };
             builder = res.builder;
             builder.link_gen_grammar(gg, res.start_node)?;
@@ -692,22 +820,165 @@ impl Grammar {
         Ok(())
     }
 
-    fn do_statement(&mut self, loc: &Location, statement: Statement) -> Result<()> {
+    /// Load the grammar module (`lib.sql` or relative `.sql`) with its own imports resolved.
+    fn load_module(imports: &mut Imports, module: &str) -> Result<(String, Grammar)> {
+        let module = if let Some(rel) = module.strip_prefix('.') {
+            // relative to the package of the importing module
+            match imports.stack.last().and_then(|m| m.rsplit_once('.')) {
+                Some((package, _)) => format!("{package}.{rel}"),
+                None => rel.to_string(),
+            }
+        } else {
+            module.to_string()
+        };
+        if imports.stack.contains(&module) {
+            bail!("import cycle: {} -> {}", imports.stack.join(" -> "), module);
+        }
+        let resolver = imports.resolver.clone().ok_or_else(|| {
+            anyhow!("can't import {module:?}: no import resolver set (only common.* is built-in)")
+        })?;
+        let source = resolver
+            .resolve(&module)
+            .map_err(|e| anyhow!("can't import {module:?}: {e}"))?;
+        let parsed = parse_lark(&source, imports.python_compat)
+            .map_err(|e| anyhow!("in module {module:?}: {e}"))?;
+
+        imports.stack.push(module.clone());
+        let mut grm = Grammar::default();
+        for item in parsed.items {
+            let loc = item.location().clone();
+            grm.process_item(item, imports)
+                .map_err(|e| anyhow!("in module {module:?}: {}", loc.augment(e)))?;
+        }
+        imports.stack.pop();
+
+        Ok((module, grm))
+    }
+
+    /// Import `names` (pairs of name in module and local name) from `module`,
+    /// together with the rules and tokens they depend on.
+    /// The dependencies are namespaced by the module path, e.g., `ws` of `lib.sql`
+    /// becomes `lib__sql__ws` (and `_ws` becomes `_lib__sql__ws`).
+    fn import_module(
+        &mut self,
+        imports: &mut Imports,
+        module: &str,
+        names: Vec<(String, String)>,
+    ) -> Result<()> {
+        let (module, mut grm) = Self::load_module(imports, module)?;
+        let prefix = module.replace('.', "__");
+        let mangle = |name: &str| match name.strip_prefix('_') {
+            Some(name) => format!("_{prefix}__{name}"),
+            None => format!("{prefix}__{name}"),
+        };
+
+        let mut renames: HashMap<String, String> = HashMap::default();
+        for (name, local_name) in &names {
+            if let Some(prev) = renames.insert(name.clone(), local_name.clone()) {
+                ensure!(
+                    prev == *local_name,
+                    "{name:?} imported twice from {module:?}"
+                );
+            }
+        }
+        let mut todo: Vec<String> = renames.keys().cloned().collect();
+
+        while let Some(name) = todo.pop() {
+            let local_name = renames[&name].clone();
+            let is_dep = !names.iter().any(|(n, _)| *n == name);
+
+            let (mut rule, mut token) = (grm.rules.remove(&name), grm.tokens.remove(&name));
+            let is_declared = grm.declared.remove(&name);
+            let template_params = rule
+                .as_ref()
+                .and_then(|r| r.params.as_ref())
+                .map_or(vec![], |p| p.0.clone());
+            let mut unknown = None;
+            let mut rename = |n: &mut String| {
+                if template_params.contains(n) {
+                    // not a reference
+                } else if let Some(r) = renames.get(n.as_str()) {
+                    *n = r.clone();
+                } else if grm.rules.contains_key(n.as_str())
+                    || grm.tokens.contains_key(n.as_str())
+                    || grm.declared.contains(n.as_str())
+                {
+                    let r = mangle(n);
+                    renames.insert(n.clone(), r.clone());
+                    todo.push(std::mem::replace(n, r));
+                } else {
+                    unknown = Some(n.clone());
+                }
+            };
+
+            if let Some(rule) = rule.as_mut() {
+                rule.for_each_name_mut(&mut rename);
+                rule.name = local_name.clone();
+            } else if let Some(token) = token.as_mut() {
+                token.expansions.for_each_name_mut(&mut rename);
+                token.name = local_name.clone();
+            } else if !is_declared {
+                bail!("{name:?} not found in module {module:?}");
+            }
+            if let Some(n) = unknown {
+                bail!("unknown name: {n:?} (in module {module:?})");
+            }
+
+            if is_dep
+                && (self.rules.contains_key(&local_name)
+                    || self.tokens.contains_key(&local_name)
+                    || self.declared.contains(&local_name))
+            {
+                // already imported along with an earlier import from this module
+                continue;
+            }
+            self.check_undefined(&local_name)?;
+            if let Some(rule) = rule {
+                self.rules.insert(local_name, rule);
+            } else if let Some(token) = token {
+                self.tokens.insert(local_name, token);
+            } else {
+                self.declared.insert(local_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn do_statement(
+        &mut self,
+        loc: &Location,
+        statement: Statement,
+        imports: &mut Imports,
+    ) -> Result<()> {
         match statement {
             Statement::Ignore(exp) => {
+                // like in Lark, %ignore in imported modules has no effect
                 self.ignore.push(exp);
             }
             Statement::Import { path, alias } => {
-                let regex = lookup_common_regex(&path)?;
-                let local_name =
-                    alias.unwrap_or_else(|| path.split('.').next_back().unwrap().to_string());
-                self.add_token_def(loc, local_name, regex)?;
+                let (module, name) = path
+                    .rsplit_once('.')
+                    .filter(|(m, _)| !m.is_empty())
+                    .ok_or_else(|| anyhow!("expecting %import module.NAME, got {path:?}"))?;
+                let local_name = alias.unwrap_or_else(|| name.to_string());
+                if module == "common" {
+                    let regex = lookup_common_regex(&path)?;
+                    self.add_token_def(loc, local_name, regex)?;
+                } else {
+                    self.import_module(imports, module, vec![(name.to_string(), local_name)])?;
+                }
             }
             Statement::MultiImport { path, names } => {
-                for n in names {
-                    let qname = format!("{path}.{n}");
-                    let regex = lookup_common_regex(&qname)?;
-                    self.add_token_def(loc, n.to_string(), regex)?;
+                if path == "common" {
+                    for n in names {
+                        let qname = format!("{path}.{n}");
+                        let regex = lookup_common_regex(&qname)?;
+                        self.add_token_def(loc, n.to_string(), regex)?;
+                    }
+                } else {
+                    let names = names.into_iter().map(|n| (n.clone(), n)).collect();
+                    self.import_module(imports, &path, names)?;
                 }
             }
             Statement::LLGuidance(json_value) => {
@@ -717,46 +988,120 @@ impl Grammar {
                 let _v: LLGuidanceOptions = serde_json::from_value(json_value)
                     .map_err(|e| anyhow!("failed to parse %llguidance declaration: {}", e))?;
             }
-            Statement::OverrideRule(_) => {
-                bail!("override statement not supported yet");
+            Statement::OverrideRule(rule) => {
+                self.add_rule(*rule, true)?;
+            }
+            Statement::OverrideToken(token_def) => {
+                self.add_token(*token_def, true)?;
             }
-            Statement::Declare(_) => {
-                bail!("declare statement not supported yet");
+            Statement::Declare(names) => {
+                for name in names {
+                    self.check_undefined(&name)?;
+                    self.declared.insert(name);
+                }
             }
         }
         Ok(())
     }
 
-    fn process_item(&mut self, item: Item) -> Result<()> {
+    /// Fail if `name` is already defined or declared.
+    fn check_undefined(&self, name: &str) -> Result<()> {
+        ensure!(!self.rules.contains_key(name), "duplicate rule: {:?}", name);
+        ensure!(
+            !self.tokens.contains_key(name),
+            "duplicate token: {:?}",
+            name
+        );
+        ensure!(!self.declared.contains(name), "{:?} already declared", name);
+        Ok(())
+    }
+
+    /// Define `rule`; with `is_override` (`%override`) it replaces an existing
+    /// (possibly imported or declared) definition, otherwise it must be new.
+    fn add_rule(&mut self, rule: Rule, is_override: bool) -> Result<()> {
+        if is_override {
+            ensure!(
+                self.rules.contains_key(&rule.name) || self.declared.remove(&rule.name),
+                "cannot override a nonexisting rule: {:?}",
+                rule.name
+            );
+        } else {
+            self.check_undefined(&rule.name)?;
+        }
+        self.rules.insert(rule.name.clone(), rule);
+        Ok(())
+    }
+
+    /// Define `token_def`, see [`Grammar::add_rule`].
+    fn add_token(&mut self, token_def: TokenDef, is_override: bool) -> Result<()> {
+        ensure!(
+            token_def.params.is_none(),
+            "templates are only supported for rules, not terminals"
+        );
+        if is_override {
+            ensure!(
+                self.tokens.contains_key(&token_def.name) || self.declared.remove(&token_def.name),
+                "cannot override a nonexisting token: {:?}",
+                token_def.name
+            );
+        } else {
+            self.check_undefined(&token_def.name)?;
+        }
+        self.tokens.insert(token_def.name.clone(), token_def);
+        Ok(())
+    }
+
+    fn process_item(&mut self, item: Item, imports: &mut Imports) -> Result<()> {
         match item {
             Item::Rule(rule) => {
-                ensure!(rule.params.is_none(), "params not supported yet");
-                ensure!(rule.priority.is_none(), "priority not supported yet");
-                ensure!(
-                    !self.rules.contains_key(&rule.name),
-                    "duplicate rule: {:?}",
-                    rule.name
-                );
-                self.rules.insert(rule.name.clone(), rule);
+                self.add_rule(rule, false)?;
             }
             Item::Token(token_def) => {
-                ensure!(token_def.params.is_none(), "params not supported yet");
-                ensure!(token_def.priority.is_none(), "priority not supported yet");
-                ensure!(
-                    !self.tokens.contains_key(&token_def.name),
-                    "duplicate token: {:?}",
-                    token_def.name
-                );
-                self.tokens.insert(token_def.name.clone(), token_def);
+                self.add_token(token_def, false)?;
             }
             Item::Statement(loc, statement) => {
-                self.do_statement(&loc, statement)?;
+                self.do_statement(&loc, statement, imports)?;
             }
         }
         Ok(())
     }
 }
 
+/// Replace template parameters with their arguments.
+fn substitute_value(v: &mut Value, args: &HashMap<&str, &Value>) {
+    match v {
+        Value::Name(n) => {
+            if let Some(arg) = args.get(n.as_str()) {
+                *v = (*arg).clone();
+            }
+        }
+        Value::TemplateUsage { values, .. } => {
+            for v in values.iter_mut() {
+                substitute_value(v, args);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Lark-like rendering of a template argument, used to name template instances.
+fn template_arg_key(v: &Value) -> String {
+    match v {
+        Value::Name(n) => n.clone(),
+        Value::LiteralString(s, flags) => format!("{}{flags}", serde_json::to_string(s).unwrap()),
+        Value::LiteralRegex(rx, flags) => format!("/{rx}/{flags}"),
+        Value::TemplateUsage { name, values } => format!(
+            "{name}{{{}}}",
+            values
+                .iter()
+                .map(template_arg_key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => format!("{v:?}"),
+    }
+}
+
 fn compile_lark_regex(builder: &mut GrammarBuilder, l: RegexExt) -> Result<RegexId> {
     let mut fields_set = vec![];
     if l.substring_chunks.is_some() {
@@ -768,6 +1113,41 @@ fn compile_lark_regex(builder: &mut GrammarBuilder, l: RegexExt) -> Result<Regex
     if l.substring_chars.is_some() {
         fields_set.push("substring_chars");
     }
+
+    let numeric_fields = [
+        ("minimum", l.minimum),
+        ("maximum", l.maximum),
+        ("exclusiveMinimum", l.exclusive_minimum),
+        ("exclusiveMaximum", l.exclusive_maximum),
+        ("multipleOf", l.multiple_of),
+    ];
+    let string_fields = [
+        ("format", l.format.map(serde_json::Value::String)),
+        ("pattern", l.pattern.map(serde_json::Value::String)),
+        ("minLength", l.min_length.map(serde_json::Value::from)),
+        ("maxLength", l.max_length.map(serde_json::Value::from)),
+    ];
+    let numeric_fields_set = numeric_fields
+        .iter()
+        .filter(|(_, v)| v.is_some())
+        .map(|(k, _)| *k)
+        .collect::<Vec<_>>();
+    let mut tp = l.tp;
+    if tp.is_none() {
+        if !numeric_fields_set.is_empty() {
+            bail!(
+                "{:?} on %regex require \"type\" to be set",
+                numeric_fields_set
+            );
+        }
+        if string_fields.iter().any(|(_, v)| v.is_some()) {
+            tp = Some("string".to_string());
+        }
+    }
+    if tp.is_some() {
+        fields_set.push("type");
+    }
+
     if fields_set.is_empty() {
         bail!("no fields set on %regex");
     }
@@ -775,6 +1155,29 @@ fn compile_lark_regex(builder: &mut GrammarBuilder, l: RegexExt) -> Result<Regex
         bail!("only one field can be set on %regex; got {:?}", fields_set);
     }
 
+    if let Some(tp) = tp {
+        match tp.as_str() {
+            "number" | "integer" | "string" => {}
+            _ => bail!(
+                "unsupported %regex type: {tp:?}; expecting \"number\", \"integer\" or \"string\""
+            ),
+        }
+        let mut schema = serde_json::Map::new();
+        schema.insert("type".to_string(), serde_json::Value::String(tp));
+        for (k, v) in numeric_fields {
+            if let Some(v) = v {
+                schema.insert(k.to_string(), serde_json::json!(v));
+            }
+        }
+        for (k, v) in string_fields {
+            if let Some(v) = v {
+                schema.insert(k.to_string(), v);
+            }
+        }
+        let ast = primitive_schema_regex(serde_json::Value::Object(schema))?;
+        return builder.regex.add_ast(ast);
+    }
+
     let bld = &mut builder.regex.spec.regex_builder;
 
     let eref = if let Some(s) = l.substring_words {
@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{api::RegexExt, HashMap};
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use derivre::RegexAst;
 use serde::de;
 use serde_json::{Deserializer, Value};
@@ -215,10 +215,10 @@ impl Token {
         (Token::Op, r"[+*?]"),
         (Token::Rule, r"!?[_?]?[a-z][_a-z0-9\-]*"),
         (Token::Token, r"_?[A-Z][_A-Z0-9\-]*"),
-        // use JSON string syntax
+        // JSON string syntax, but escapes are checked when parsing (to allow python_compat)
         (
             Token::String,
-            r#""(\\([\"\\\/bfnrt]|u[a-fA-F0-9]{4})|[^\"\\\x00-\x1F\x7F])*"(i|)"#,
+            r#"[rR]?"(\\[^\x00-\x1F\x7F]|[^\"\\\x00-\x1F\x7F])*"(i|)"#,
         ),
         (Token::Regexp, r#"/(\\.|[^/\\])+/[imslux]*"#),
         (Token::Number, r#"[+-]?[0-9]+(\.[0-9]*)?([eE][+-]?[0-9]+)?"#),
@@ -398,3 +398,93 @@ where
         Err(anyhow::anyhow!("empty json"))
     }
 }
+
+/// Parses a string literal with Python syntax (as used by Python Lark),
+/// including the quotes and an optional `r` (raw) prefix.
+pub fn parse_python_string(s: &str) -> Result<String> {
+    let (raw, s) = match s.strip_prefix(['r', 'R']) {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("expecting a quoted string"))?;
+    if raw {
+        return Ok(inner.to_string());
+    }
+
+    let chars = inner.chars().collect::<Vec<_>>();
+    let hex = |i: usize, n: usize| -> Result<char> {
+        let digits = chars
+            .get(i..i + n)
+            .ok_or_else(|| anyhow!("truncated escape in {s}"))?
+            .iter()
+            .collect::<String>();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| anyhow!("invalid escape \\{digits} in {s}"))
+    };
+    let mut res = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        let e = chars[i];
+        i += 1;
+        match e {
+            'a' => res.push('\x07'),
+            'b' => res.push('\x08'),
+            'f' => res.push('\x0C'),
+            'n' => res.push('\n'),
+            'r' => res.push('\r'),
+            't' => res.push('\t'),
+            'v' => res.push('\x0B'),
+            '\\' | '\'' | '"' => res.push(e),
+            'x' | 'u' | 'U' => {
+                let n = match e {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                res.push(hex(i, n)?);
+                i += n;
+            }
+            '0'..='7' => {
+                let mut code = e.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.get(i).and_then(|d| d.to_digit(8)) {
+                        Some(d) => code = code * 8 + d,
+                        None => break,
+                    }
+                    i += 1;
+                }
+                res.push(char::from_u32(code).unwrap());
+            }
+            'N' => {
+                let end = chars[i..].iter().position(|&c| c == '}');
+                ensure!(
+                    chars.get(i) == Some(&'{') && end.is_some(),
+                    "expecting \\N{{...}} in {s}"
+                );
+                let name = chars[i + 1..i + end.unwrap()].iter().collect::<String>();
+                res.push(
+                    unicode_names2::character(&name)
+                        .ok_or_else(|| anyhow!("unknown character name: \\N{{{name}}}"))?,
+                );
+                i += end.unwrap() + 1;
+            }
+            // Python keeps unknown escapes as is
+            _ => {
+                res.push('\\');
+                res.push(e);
+            }
+        }
+    }
+    Ok(res)
+}
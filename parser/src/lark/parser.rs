@@ -1,13 +1,15 @@
 use std::rc::Rc;
 
 use crate::{
+    api::LLGuidanceOptions,
     earley::{BitIdx, ParamCond, ParamExpr, ParamRef, ParamValue},
     lark::lexer::highlight_location,
+    python_regex_to_rust,
 };
 
 use super::{
     ast::*,
-    lexer::{lex_lark, Lexeme, LexemeValue, Location, Token},
+    lexer::{lex_lark, parse_python_string, Lexeme, LexemeValue, Location, Token},
 };
 use anyhow::{anyhow, bail, ensure, Result};
 
@@ -19,16 +21,19 @@ pub struct Parser {
     src: Rc<String>,
     pos: usize,
     nesting_level: usize,
+    // string and regex literals use Python syntax
+    python_compat: bool,
 }
 
 impl Parser {
     /// Creates a new parser instance.
-    pub fn new(src: Rc<String>, tokens: Vec<Lexeme>, nesting: usize) -> Self {
+    pub fn new(src: Rc<String>, tokens: Vec<Lexeme>, nesting: usize, python_compat: bool) -> Self {
         Parser {
             tokens,
             src,
             pos: 0,
             nesting_level: nesting,
+            python_compat,
         }
     }
 
@@ -66,7 +71,10 @@ impl Parser {
             items.push(self.parse_item()?);
             self.consume_newlines();
         }
-        Ok(ParsedLark { items })
+        Ok(ParsedLark {
+            items,
+            python_compat: self.python_compat,
+        })
     }
 
     /// Parses an item (rule, token, or statement).
@@ -281,8 +289,13 @@ impl Parser {
                 })
             }
         } else if self.match_token(Token::KwOverride) {
-            let rule = self.parse_rule()?;
-            Ok(Statement::OverrideRule(Box::new(rule)))
+            if self.has_token(Token::Token) {
+                let token_def = self.parse_token_def()?;
+                Ok(Statement::OverrideToken(Box::new(token_def)))
+            } else {
+                let rule = self.parse_rule()?;
+                Ok(Statement::OverrideRule(Box::new(rule)))
+            }
         } else if self.match_token(Token::KwDeclare) {
             let mut names = Vec::new();
             while let Ok(name) = self.parse_name() {
@@ -512,8 +525,15 @@ impl Parser {
         } else {
             (s, "")
         };
-        let inner =
-            serde_json::from_str(inner).map_err(|e| anyhow!("error parsing string: {e}"))?;
+        let inner = if self.python_compat {
+            parse_python_string(inner)?
+        } else {
+            ensure!(
+                inner.starts_with('"'),
+                "string prefixes are only supported with python_compat"
+            );
+            serde_json::from_str(inner).map_err(|e| anyhow!("error parsing string: {e}"))?
+        };
         Ok((inner, flags.to_string()))
     }
 
@@ -528,9 +548,12 @@ impl Parser {
         if let Some(string1) = self.match_token_with_value(Token::String) {
             if self.match_token(Token::DotDot) {
                 let string2 = self.expect_token_val(Token::String)?;
+                // the i-flag, if any, goes after the end of the range: "a".."z"i
+                let (end, flags) = self.parse_string(&string2)?;
                 Ok(Value::LiteralRange(
                     self.parse_simple_string(&string1)?,
-                    self.parse_simple_string(&string2)?,
+                    end,
+                    flags,
                 ))
             } else {
                 let (inner, flags) = self.parse_string(&string1)?;
@@ -541,7 +564,12 @@ impl Parser {
             let last_slash_idx = inner.rfind('/').unwrap();
             let flags = inner[last_slash_idx + 1..].to_string();
             let regex = inner[1..last_slash_idx].to_string();
-            Ok(Value::LiteralRegex(regex, flags))
+            if self.python_compat {
+                let (regex, flags) = python_regex_to_rust(&regex, &flags)?;
+                Ok(Value::LiteralRegex(regex, flags))
+            } else {
+                Ok(Value::LiteralRegex(regex, flags))
+            }
         } else if let Some(grammar_ref) = self.match_token_with_value(Token::GrammarRef) {
             Ok(Value::GrammarRef(grammar_ref))
         } else if let Some(special_token) = self.match_token_with_value(Token::SpecialToken) {
@@ -553,7 +581,7 @@ impl Parser {
             }
         } else if self.has_token(Token::KwRegex) {
             match self.take_token_value() {
-                LexemeValue::Regex(v) => Ok(Value::RegexExt(v)),
+                LexemeValue::Regex(v) => Ok(Value::RegexExt(Box::new(v))),
                 v => bail!("expected regex JSON value, got {}", v),
             }
         } else if self.match_token(Token::KwLark) {
@@ -583,8 +611,13 @@ impl Parser {
             }
             self.pos = endp + 1;
 
-            let inner =
-                Parser::new(self.src.clone(), inner, self.nesting_level + 1).parse_start()?;
+            let inner = Parser::new(
+                self.src.clone(),
+                inner,
+                self.nesting_level + 1,
+                self.python_compat,
+            )
+            .parse_start()?;
             Ok(Value::NestedLark(inner.items))
         } else if let Some(name_token) = self
             .match_token_with_value(Token::Rule)
@@ -967,9 +1000,20 @@ impl Parser {
 
 pub struct ParsedLark {
     pub items: Vec<Item>,
+    pub python_compat: bool,
 }
 
-pub fn parse_lark(input: &str) -> Result<ParsedLark> {
+/// Parses a Lark grammar; `python_compat` is also enabled by
+/// `%llguidance { "python_compat": true }` anywhere in the `input`.
+pub fn parse_lark(input: &str, python_compat: bool) -> Result<ParsedLark> {
     let tokens = lex_lark(input)?;
-    Parser::new(Rc::new(input.to_string()), tokens, 0).parse_start()
+    let python_compat = python_compat
+        || tokens.iter().any(|t| match (&t.token, &t.value) {
+            (Token::KwLLGuidance, LexemeValue::Json(v)) => {
+                serde_json::from_value::<LLGuidanceOptions>(v.clone())
+                    .is_ok_and(|opts| opts.python_compat)
+            }
+            _ => false,
+        });
+    Parser::new(Rc::new(input.to_string()), tokens, 0, python_compat).parse_start()
 }
@@ -48,6 +48,12 @@ mod tokenizer_json;
 pub use constraint::{CommitResult, Constraint};
 pub use matcher::Matcher;
 
+mod enumerator;
+pub use enumerator::{Completion, EnumerateOptions, Enumerator};
+
+mod fuzzer;
+pub use fuzzer::{FuzzOptions, GrammarFuzzer, LexemeDistribution};
+
 mod factory;
 pub use factory::ParserFactory;
 
@@ -62,21 +68,24 @@ pub mod ffi;
 mod ffi_par;
 
 mod grammar_builder;
+mod import_resolver;
 mod json;
 #[cfg(feature = "jsonschema_validation")]
 mod json_validation;
 mod regex_rewrite;
 pub mod substring;
 pub use grammar_builder::{GrammarBuilder, NodeRef};
+pub use import_resolver::{DirImportResolver, ImportResolver, MapImportResolver};
 pub use json::compiler::JsonCompileOptions;
 pub use json::json_merge;
+pub use json::{bundle_and_dir_retriever, DirRetriever, MapRetriever, Retrieve, RetrieveWrapper};
 pub use stop_controller::StopController;
 pub use tokenizer_json::token_bytes_from_tokenizer_json;
 
 #[cfg(feature = "lark")]
 mod lark;
 
-pub use regex_rewrite::regex_to_lark;
+pub use regex_rewrite::{python_regex_to_rust, regex_to_ast, regex_to_lark};
 
 #[cfg(feature = "wasm")]
 pub use instant::Instant;
@@ -1,7 +1,13 @@
+use std::ops::Range;
+
 use anyhow::{anyhow, bail, ensure, Result};
 use toktrie::{SimpleVob, TokEnv, TokenId};
 
-use crate::{api::StopReason, earley::ParserStats, panic_utils, TokenParser};
+use crate::{
+    api::StopReason,
+    earley::{CaptureNode, ParseNode, ParserStats},
+    panic_utils, TokenParser,
+};
 
 #[derive(Clone)]
 struct MatcherInner {
@@ -86,6 +92,21 @@ impl Matcher {
         self.consume_tokens(&[token])
     }
 
+    /// Like [`Matcher::consume_token()`], but also records the log-probability of the token;
+    /// see [`Matcher::bytes_logprob()`].
+    pub fn consume_token_with_logprob(
+        &mut self,
+        token: TokenId,
+        logprob: Option<f64>,
+    ) -> Result<()> {
+        self.with_inner(|inner| {
+            let bt = inner.parser.consume_token_with_logprob(token, logprob)?;
+            ensure!(bt == 0, "unexpected backtracking");
+            let _ = inner.parser.check_stop()?;
+            Ok(())
+        })
+    }
+
     pub fn test_trigger_lexer_error(&mut self) -> Result<()> {
         self.with_inner(|inner| inner.parser.parser.test_trigger_lexer_error())
     }
@@ -230,4 +251,53 @@ impl Matcher {
             MatcherState::Error(_) => &[],
         }
     }
+
+    /// Bytes of the consumed output; offsets in [`Matcher::capture_tree()`] refer to these.
+    pub fn final_bytes(&self) -> &[u8] {
+        match &self.0 {
+            MatcherState::Normal(inner) => inner.parser.final_bytes(),
+            MatcherState::Error(_) => &[],
+        }
+    }
+
+    /// Captures nested according to their byte spans in the consumed output.
+    /// Unlike [`Matcher::captures()`], repeated and nested captures are all kept.
+    pub fn capture_tree(&self) -> Vec<CaptureNode> {
+        match &self.0 {
+            MatcherState::Normal(inner) => inner.parser.capture_tree(),
+            MatcherState::Error(_) => vec![],
+        }
+    }
+
+    /// Sum of log-probabilities of the bytes in `range` of [`Matcher::final_bytes()`]
+    /// (e.g., of a capture from [`Matcher::capture_tree()`]).
+    /// Each token's log-probability, as passed to [`Matcher::consume_token_with_logprob()`],
+    /// is spread evenly over its bytes.
+    pub fn bytes_logprob(&self, range: Range<usize>) -> f64 {
+        match &self.0 {
+            MatcherState::Normal(inner) => inner.parser.bytes_logprob(range),
+            MatcherState::Error(_) => 0.0,
+        }
+    }
+
+    /// Parse tree (rules and lexemes) of the consumed output, with offsets into
+    /// [`Matcher::final_bytes()`]; `None` if the matcher is in error state.
+    ///
+    /// If the output is not a complete match of the grammar, the tree covers
+    /// the lexemes read so far (without the one still being lexed),
+    /// and only has the rules that are started.
+    /// When the grammar is ambiguous, earlier symbols in a rule get the longest
+    /// possible span, and then a rule with higher priority (`rule.N` in Lark)
+    /// or one listed first in the grammar is preferred.
+    ///
+    /// With `named_only`, the nodes of helper symbols generated by the grammar
+    /// compiler are replaced by their children.
+    /// Lark rules are only kept as named nodes when the `parse_tree` option is set
+    /// (`%llguidance { "parse_tree": true }`), otherwise most of them are inlined.
+    pub fn parse_tree(&self, named_only: bool) -> Option<ParseNode> {
+        match &self.0 {
+            MatcherState::Normal(inner) => Some(inner.parser.parse_tree(named_only)),
+            MatcherState::Error(_) => None,
+        }
+    }
 }
@@ -97,20 +97,23 @@ impl Reporter {
 
         // start with captures
         let captures = &tok_parser.parser.captures()[self.reported_captures..];
+        let spans = tok_parser.capture_spans();
+        let spans = &spans[self.reported_captures..];
         self.reported_captures += captures.len();
 
         // remove duplicate names
         let mut seen = HashSet::default();
         let captures = captures
             .iter()
+            .zip(spans.iter())
             .rev()
-            .filter(|(name, _)| seen.insert(name))
+            .filter(|((name, _), _)| seen.insert(name))
             .collect::<Vec<_>>();
-        for (name, val) in captures.iter().rev() {
+        for ((name, val), span) in captures.iter().rev() {
             res.push(ParserOutput::Capture {
                 name: name.clone(),
                 bytes: val.as_slice().into(),
-                log_prob: 0.0, // TODO
+                log_prob: tok_parser.bytes_logprob((*span).clone()),
             });
         }
 
@@ -128,7 +131,7 @@ impl Reporter {
         let new_text = tok_parser.bytes_since(self.text_ptr);
         res.push(ParserOutput::Text {
             bytes: new_text.into(),
-            log_prob: 0.0, // TODO
+            log_prob: tok_parser.bytes_logprob(self.text_ptr..self.text_ptr + new_text.len()),
             num_tokens: num_tokens.saturating_sub(self.token_ptr),
             is_generated: self.is_generated,
             stats,
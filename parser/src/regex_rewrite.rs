@@ -1,3 +1,6 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use derivre::RegexAst;
+
 fn class_for(c: char) -> Option<&'static str> {
     match c {
         'd' => Some("0-9"),
@@ -60,6 +63,469 @@ pub fn regex_to_lark(rx: &str, use_ascii: &str) -> String {
     res
 }
 
+/// If a quantifier like `{n}`, `{n,}`, `{n,m}`, or `{,m}` starts at `i`,
+/// returns its bounds and the index just past it.
+fn brace_quantifier(rx: &[char], i: usize) -> Option<(String, Option<String>, usize)> {
+    let digits = |mut j: usize| {
+        let start = j;
+        while j < rx.len() && rx[j].is_ascii_digit() {
+            j += 1;
+        }
+        (rx[start..j].iter().collect::<String>(), j)
+    };
+    let (min, j) = digits(i + 1);
+    let (max, j) = if rx.get(j) == Some(&',') {
+        let (max, j) = digits(j + 1);
+        (Some(max), j)
+    } else {
+        (None, j)
+    };
+    let valid = !min.is_empty() || max.as_ref().is_some_and(|m| !m.is_empty());
+    if valid && rx.get(j) == Some(&'}') {
+        Some((min, max, j + 1))
+    } else {
+        None
+    }
+}
+
+/// Translates a regex in Python `re` syntax, with Lark `flags` (`/.../imslux`),
+/// to the Rust `regex` syntax, returning the new regex and flags.
+///
+/// This handles verbose mode (`x`), the ASCII flag (`(?a)`), `\Z`, octal and `\N{...}` escapes,
+/// `{,m}` quantifiers, literal `{`, `}`, and `[` (in classes), inline comments `(?#...)`,
+/// and escapes of characters that are not special in Rust.
+/// Backreferences result in an error.
+pub fn python_regex_to_rust(rx: &str, flags: &str) -> Result<(String, String)> {
+    let chars = rx.chars().collect::<Vec<_>>();
+    let mut flags = flags.to_string();
+    let mut i = inline_flags_len(&chars);
+    if i > 0 {
+        flags.extend(chars[2..i - 1].iter());
+    }
+    ensure!(
+        !flags.contains('L'),
+        "locale-dependent matching (L flag) is not supported"
+    );
+    let verbose = flags.contains('x');
+    let ascii = flags.contains('a');
+    let mut new_flags = String::new();
+    for c in flags.chars() {
+        if !"xa".contains(c) && !new_flags.contains(c) {
+            new_flags.push(c);
+        }
+    }
+
+    let mut res = String::new();
+    let mut in_class = false;
+    let mut class_start = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c == '\\' {
+            let e = *chars
+                .get(i)
+                .ok_or_else(|| anyhow!("regex ends with a backslash"))?;
+            i += 1;
+            match e {
+                'Z' => res.push_str(r"\z"),
+                'b' if in_class => res.push_str(r"\x08"),
+                'N' => {
+                    let end = chars[i..].iter().position(|&c| c == '}');
+                    ensure!(
+                        chars.get(i) == Some(&'{') && end.is_some(),
+                        "expecting \\N{{...}}"
+                    );
+                    let name = chars[i + 1..i + end.unwrap()].iter().collect::<String>();
+                    let ch = unicode_names2::character(&name)
+                        .ok_or_else(|| anyhow!("unknown character name: \\N{{{name}}}"))?;
+                    res.push_str(&regex_syntax::escape(&ch.to_string()));
+                    i += end.unwrap() + 1;
+                }
+                '0'..='9' => {
+                    let mut digits = e.to_string();
+                    while digits.len() < 3 && chars.get(i).is_some_and(|d| ('0'..='7').contains(d))
+                    {
+                        digits.push(chars[i]);
+                        i += 1;
+                    }
+                    let is_octal =
+                        e == '0' || (in_class && e <= '7') || (digits.len() == 3 && e <= '3');
+                    ensure!(is_octal, "backreferences (like \\{e}) are not supported");
+                    let code = u32::from_str_radix(&digits, 8).unwrap();
+                    res.push_str(&format!("\\x{{{code:x}}}"));
+                }
+                'd' | 'w' | 's' if ascii => {
+                    let class = class_for(e).unwrap();
+                    if in_class {
+                        res.push_str(class);
+                    } else {
+                        res.push_str(&format!("[{class}]"));
+                    }
+                }
+                'D' | 'W' | 'S' if ascii => {
+                    ensure!(
+                        !in_class,
+                        "\\{e} inside a character class is not supported with the ASCII flag"
+                    );
+                    let class = class_for(e.to_ascii_lowercase()).unwrap();
+                    res.push_str(&format!("[^{class}]"));
+                }
+                _ if e.is_ascii_alphanumeric() => {
+                    res.push('\\');
+                    res.push(e);
+                }
+                _ => res.push_str(&regex_syntax::escape(&e.to_string())),
+            }
+        } else if in_class {
+            match c {
+                ']' if i - 1 > class_start => in_class = false,
+                '[' | '&' | '~' => res.push('\\'),
+                _ => {}
+            }
+            res.push(c);
+        } else {
+            match c {
+                '[' => {
+                    in_class = true;
+                    res.push(c);
+                    if chars.get(i) == Some(&'^') {
+                        res.push('^');
+                        i += 1;
+                    }
+                    class_start = i;
+                }
+                '(' if chars[i..].starts_with(&['?', '#']) => {
+                    let end = chars[i..].iter().position(|&c| c == ')');
+                    i += end.ok_or_else(|| anyhow!("unterminated comment"))? + 1;
+                }
+                '(' if chars[i..].starts_with(&['?', 'P', '=']) => {
+                    bail!("backreferences (like (?P=...)) are not supported")
+                }
+                '{' => {
+                    if let Some((min, max, end)) = brace_quantifier(&chars, i - 1) {
+                        let min = if min.is_empty() { "0" } else { min.as_str() };
+                        match max {
+                            Some(max) => res.push_str(&format!("{{{min},{max}}}")),
+                            None => res.push_str(&format!("{{{min}}}")),
+                        }
+                        i = end;
+                    } else {
+                        res.push_str("\\{");
+                    }
+                }
+                '}' => res.push_str("\\}"),
+                ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C' if verbose => {}
+                '#' if verbose => {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                }
+                _ => res.push(c),
+            }
+        }
+    }
+    Ok((res, new_flags))
+}
+
+/// Returns the index just past the element of `rx` starting at `start`:
+/// an escape sequence, a character class (possibly nested), a group, or a single character.
+fn element_end(rx: &[char], start: usize) -> Result<usize> {
+    let mut stack = vec![];
+    let mut i = start;
+    loop {
+        let in_class = stack.last() == Some(&'[');
+        match rx.get(i) {
+            None if in_class => bail!("unclosed character class"),
+            None if !stack.is_empty() => bail!("unclosed group"),
+            None => return Ok(i),
+            Some('\\') => i += 2,
+            Some('[') => {
+                stack.push('[');
+                i += 1;
+                if rx.get(i) == Some(&'^') {
+                    i += 1;
+                }
+                if rx.get(i) == Some(&']') {
+                    i += 1;
+                }
+            }
+            Some(']') if in_class => {
+                stack.pop();
+                i += 1;
+            }
+            Some('(') if !in_class => {
+                stack.push('(');
+                i += 1;
+            }
+            Some(')') if !in_class => {
+                stack.pop();
+                i += 1;
+            }
+            Some(_) => i += 1,
+        }
+        if stack.is_empty() {
+            return Ok(i.min(rx.len()));
+        }
+    }
+}
+
+const LOOKAHEADS: &[&str] = &["(?=", "(?!"];
+const LOOKBEHINDS: &[&str] = &["(?<=", "(?<!"];
+
+/// If a group of one of the `kinds` starts at `i`, returns its opening (e.g., `(?=`).
+fn lookaround_at(rx: &[char], i: usize, kinds: &[&'static str]) -> Option<&'static str> {
+    kinds.iter().copied().find(|p| {
+        p.chars()
+            .enumerate()
+            .all(|(k, c)| rx.get(i + k) == Some(&c))
+    })
+}
+
+/// Finds the first group of one of the `kinds` (outside of escapes and classes) in `rx`.
+fn find_lookaround(rx: &[char], kinds: &[&'static str]) -> Result<Option<&'static str>> {
+    let mut i = 0;
+    while i < rx.len() {
+        match rx[i] {
+            '\\' | '[' => i = element_end(rx, i)?,
+            '(' => {
+                if let Some(p) = lookaround_at(rx, i, kinds) {
+                    return Ok(Some(p));
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    Ok(None)
+}
+
+/// Splits `rx` on `|` that are not nested in groups or classes.
+fn split_alternatives(rx: &[char]) -> Result<Vec<String>> {
+    let mut res = vec![];
+    let mut curr = 0;
+    let mut i = 0;
+    while i < rx.len() {
+        if rx[i] == '|' {
+            res.push(rx[curr..i].iter().collect());
+            curr = i + 1;
+            i += 1;
+        } else {
+            i = element_end(rx, i)?;
+        }
+    }
+    res.push(rx[curr..].iter().collect());
+    Ok(res)
+}
+
+/// Length of the inline flags group (like `(?i)`) at the start of `rx`, if any.
+fn inline_flags_len(rx: &[char]) -> usize {
+    if rx.starts_with(&['(', '?']) {
+        if let Ok(end) = element_end(rx, 0) {
+            if rx[2..end - 1]
+                .iter()
+                .all(|c| c.is_ascii_alphabetic() || *c == '-')
+            {
+                return end;
+            }
+        }
+    }
+    0
+}
+
+/// Returns the index just past the quantifier (`*`, `+`, `?`, `{n,m}`) starting at `i`,
+/// or `i` if there is none.
+fn quantifier_end(rx: &[char], i: usize) -> usize {
+    match rx.get(i) {
+        Some('*' | '+' | '?') => i + 1,
+        Some('{') => {
+            let mut j = i + 1;
+            while j < rx.len() && (rx[j].is_ascii_digit() || rx[j] == ',') {
+                j += 1;
+            }
+            if j > i + 1 && rx.get(j) == Some(&'}') {
+                j + 1
+            } else {
+                i
+            }
+        }
+        _ => i,
+    }
+}
+
+/// If `rx` matches a fixed string, optionally followed by `$`, returns `rx` without the `$`.
+fn literal_regex(rx: &[char]) -> Option<String> {
+    let mut i = 0;
+    while i < rx.len() {
+        match rx[i] {
+            '\\' => match rx.get(i + 1) {
+                Some(c) if !c.is_alphanumeric() || "nrt".contains(*c) => i += 2,
+                _ => return None,
+            },
+            '$' if i + 1 == rx.len() => return Some(rx[..i].iter().collect()),
+            c if ".^$*+?()[]{}|".contains(c) => return None,
+            _ => i += 1,
+        }
+    }
+    Some(rx.iter().collect())
+}
+
+/// Rewrites `A X*? L`, where `L` is a literal, into `A ((X* L) & ~(.* L .+))`,
+/// so that `X*` stops at the first occurrence of `L` (same for `+?`, `??`, and `{n,m}?`).
+/// Other uses of lazy quantifiers are left as is, that is treated as greedy.
+fn rewrite_lazy(rx: String) -> RegexAst {
+    if !rx.contains('?') {
+        return RegexAst::Regex(rx);
+    }
+    let chars = rx.chars().collect::<Vec<_>>();
+    let flags_len = inline_flags_len(&chars);
+
+    let mut lazy = None;
+    let mut i = flags_len;
+    while i < chars.len() {
+        if chars[i] == '|' {
+            return RegexAst::Regex(rx);
+        }
+        // malformed regexes are left for the regex parser to report
+        let Ok(end) = element_end(&chars, i) else {
+            return RegexAst::Regex(rx);
+        };
+        let q_end = quantifier_end(&chars, end);
+        if q_end > end && chars.get(q_end) == Some(&'?') {
+            if lazy.is_some() {
+                return RegexAst::Regex(rx);
+            }
+            lazy = Some((i, q_end));
+            i = q_end + 1;
+        } else {
+            i = q_end;
+        }
+    }
+
+    let Some((start, q_end)) = lazy else {
+        return RegexAst::Regex(rx);
+    };
+    let suffix = match literal_regex(&chars[q_end + 1..]) {
+        Some(s) if !s.is_empty() => s,
+        _ => return RegexAst::Regex(rx),
+    };
+    let flags = chars[..flags_len].iter().collect::<String>();
+    let prefix = chars[flags_len..start].iter().collect::<String>();
+    let greedy = chars[start..q_end].iter().collect::<String>();
+
+    let mut args = vec![];
+    if !prefix.is_empty() {
+        args.push(RegexAst::Regex(format!("{flags}{prefix}")));
+    }
+    args.push(RegexAst::And(vec![
+        RegexAst::Regex(format!("{flags}{greedy}{suffix}")),
+        RegexAst::Not(Box::new(RegexAst::Regex(format!(
+            "{flags}(?s:.*){suffix}(?s:.+)"
+        )))),
+    ]));
+    RegexAst::Concat(args)
+}
+
+/// Compiles `rx` to `RegexAst::Regex(rx)` (or `RegexAst::SearchRegex(rx)` when `for_search`),
+/// rewriting constructs the regex parser doesn't support.
+///
+/// Lookaheads at the start of the regex are rewritten into intersections:
+/// `(?=A)(?!B)R` becomes `R & A.* & ~(B.*)`.
+/// Lookaheads may be preceded by inline flags (`(?i)`) and `^`;
+/// for search (e.g., JSON schema `pattern`) the `^` is required.
+/// Other lookarounds, including all lookbehinds, result in an error.
+///
+/// Unless `for_search` (where laziness doesn't matter), a lazy quantifier followed by
+/// a literal suffix is rewritten to stop at the first occurrence of the suffix.
+pub fn regex_to_ast(rx: &str, for_search: bool) -> Result<RegexAst> {
+    let mk = |s: String| {
+        if for_search {
+            RegexAst::SearchRegex(s)
+        } else {
+            rewrite_lazy(s)
+        }
+    };
+
+    if !rx.contains("(?") {
+        return Ok(mk(rx.to_string()));
+    }
+    let chars = rx.chars().collect::<Vec<_>>();
+    // malformed regexes are left for the regex parser to report
+    let has_lookaround = |kinds| matches!(find_lookaround(&chars, kinds), Ok(Some(_)));
+    ensure!(
+        !has_lookaround(LOOKBEHINDS),
+        "lookbehind ((?<=...) or (?<!...)) is not supported"
+    );
+    if !has_lookaround(LOOKAHEADS) {
+        return Ok(mk(rx.to_string()));
+    }
+
+    let mut pos = inline_flags_len(&chars);
+    let flags = chars[0..pos].iter().collect::<String>();
+    let anchored = chars.get(pos) == Some(&'^');
+    if anchored {
+        pos += 1;
+    }
+
+    let mut lookaheads = vec![];
+    while let Some(p) = lookaround_at(&chars, pos, LOOKAHEADS) {
+        let end = element_end(&chars, pos)?;
+        lookaheads.push((p == "(?!", &chars[pos + p.len()..end - 1]));
+        pos = end;
+    }
+
+    let misplaced = if for_search {
+        "lookahead ((?=...) or (?!...)) is only supported at the start of a pattern, right after ^"
+    } else {
+        "lookahead ((?=...) or (?!...)) is only supported at the start of a regex"
+    };
+    ensure!(
+        !lookaheads.is_empty() && (anchored || !for_search),
+        misplaced
+    );
+    ensure!(
+        find_lookaround(&chars[pos..], LOOKAHEADS)?.is_none(),
+        misplaced
+    );
+    for (_, inner) in &lookaheads {
+        ensure!(
+            find_lookaround(inner, LOOKAHEADS)?.is_none(),
+            "nested lookarounds are not supported"
+        );
+    }
+
+    let anchor = if anchored { "^" } else { "" };
+    let mut alternatives = split_alternatives(&chars[pos..])?.into_iter();
+    let mut args = vec![mk(format!(
+        "{flags}{anchor}{}",
+        alternatives.next().unwrap()
+    ))];
+    for (negative, inner) in lookaheads {
+        let inner = inner.iter().collect::<String>();
+        let ast = if for_search {
+            RegexAst::SearchRegex(format!("{flags}^(?:{inner})"))
+        } else {
+            RegexAst::Regex(format!("{flags}(?:{inner})(?s:.*)"))
+        };
+        args.push(if negative {
+            RegexAst::Not(Box::new(ast))
+        } else {
+            ast
+        });
+    }
+    let res = RegexAst::And(args);
+
+    // (?=A)R|S only applies the lookahead to R
+    let rest = alternatives.collect::<Vec<_>>();
+    if rest.is_empty() {
+        Ok(res)
+    } else {
+        Ok(RegexAst::Or(vec![
+            res,
+            mk(format!("{flags}(?:{})", rest.join("|"))),
+        ]))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,4 +593,88 @@ mod tests {
         assert_eq!(regex_to_lark("\"", ""), "\"");
         assert_eq!(regex_to_lark(r#"a"b"#, ""), r#"a"b"#);
     }
+
+    #[test]
+    fn test_element_end() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(element_end(&chars(r"\(a"), 0).unwrap(), 2);
+        assert_eq!(element_end(&chars(r"[])(]a"), 0).unwrap(), 5);
+        assert_eq!(element_end(&chars(r"[^a[b]\]]a"), 0).unwrap(), 9);
+        assert_eq!(element_end(&chars(r"(a(b)[)]\))c"), 0).unwrap(), 11);
+        assert!(element_end(&chars(r"(a"), 0).is_err());
+        assert!(element_end(&chars(r"[a"), 0).is_err());
+    }
+
+    #[test]
+    fn test_split_alternatives() {
+        let split = |s: &str| split_alternatives(&s.chars().collect::<Vec<_>>()).unwrap();
+        assert_eq!(split("a|b"), vec!["a", "b"]);
+        assert_eq!(split(r"(a|b)[|]\||c|"), vec![r"(a|b)[|]\|", "c", ""]);
+    }
+
+    #[test]
+    fn test_regex_to_ast() {
+        assert!(matches!(
+            regex_to_ast(r"\(?=a", true).unwrap(),
+            RegexAst::SearchRegex(_)
+        ));
+        assert!(matches!(
+            regex_to_ast(r"[(?=]a", false).unwrap(),
+            RegexAst::Regex(_)
+        ));
+        assert!(matches!(
+            regex_to_ast(r"(?i)^(?=a)(?!b)c", true).unwrap(),
+            RegexAst::And(v) if v.len() == 3
+        ));
+        assert!(matches!(
+            regex_to_ast(r"(?=a)b|c|d", false).unwrap(),
+            RegexAst::Or(v) if v.len() == 2
+        ));
+        assert!(regex_to_ast(r"(?=a)b", true).is_err());
+        assert!(regex_to_ast(r"^b(?!a)", true).is_err());
+        assert!(regex_to_ast(r"b(?<=a)", false).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_lazy() {
+        let is_rewritten = |s: &str| matches!(rewrite_lazy(s.to_string()), RegexAst::Concat(_));
+        assert!(is_rewritten(r"<!--.*?-->"));
+        assert!(is_rewritten(r#"".*?""#));
+        assert!(is_rewritten(r"(?i)a(b|c)+?\.x$"));
+        assert!(is_rewritten(r"a[0-9]{2,}?b"));
+        assert!(is_rewritten(r"a??b"));
+        assert!(!is_rewritten(r"a.*b"));
+        assert!(!is_rewritten(r"a.*?"));
+        assert!(!is_rewritten(r"a.*?b+"));
+        assert!(!is_rewritten(r"a.*?b|c"));
+        assert!(!is_rewritten(r"a.*?b.*?c"));
+        assert!(!is_rewritten(r"(a.*?b)"));
+        assert_eq!(
+            literal_regex(&r"\.\nx$".chars().collect::<Vec<_>>()).unwrap(),
+            r"\.\nx"
+        );
+        assert_eq!(literal_regex(&r"\d".chars().collect::<Vec<_>>()), None);
+    }
+
+    #[test]
+    fn test_python_regex_to_rust() {
+        let tr = |rx: &str, flags: &str| python_regex_to_rust(rx, flags).unwrap();
+        assert_eq!(tr(r"a\Z", ""), (r"a\z".to_string(), "".to_string()));
+        assert_eq!(tr("a b # c\n d", "x").0, "abd");
+        assert_eq!(tr(r"(?xi)a\ [ ]", "").0, r"a [ ]");
+        assert_eq!(tr(r"(?xi)a", "").1, "i");
+        assert_eq!(
+            tr(r"(?a)\d\W[\s]", "").0,
+            r"[0-9][^0-9a-zA-Z_][ \t\n\r\f\v]"
+        );
+        assert_eq!(tr(r"a{,3}b{2}c{1,}{d}", "").0, r"a{0,3}b{2}c{1,}\{d\}");
+        assert_eq!(tr(r"[[&&~\b]", "").0, r"[\[\&\&\~\x08]");
+        assert_eq!(tr(r"[]a]", "").0, r"[]a]");
+        assert_eq!(tr(r"\0\101[\7]", "").0, r"\x{0}\x{41}[\x{7}]");
+        assert_eq!(tr(r"\N{hyphen-minus}\'", "").0, r"\-'");
+        assert_eq!(tr(r"(?P<x>a)(?#c)", "").0, r"(?P<x>a)");
+        assert!(python_regex_to_rust(r"(a)\1", "").is_err());
+        assert!(python_regex_to_rust(r"a", "L").is_err());
+        assert!(python_regex_to_rust(r"\N{FOO}", "").is_err());
+    }
 }
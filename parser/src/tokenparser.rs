@@ -1,8 +1,10 @@
-use std::{fmt::Display, hint::black_box, panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use std::{
+    fmt::Display, hint::black_box, ops::Range, panic::AssertUnwindSafe, sync::Arc, time::Duration,
+};
 
 use crate::{
     api::{GrammarInit, ParserLimits, StopReason},
-    earley::{BiasComputer, Parser, ParserError, ParserStats},
+    earley::{BiasComputer, CaptureNode, ParseNode, Parser, ParserError, ParserStats},
     infoln, panic_utils, warn, Instant, Logger, ParserFactory,
 };
 use anyhow::{ensure, Result};
@@ -40,6 +42,8 @@ pub struct TokenParser {
     // tokens currently in KV cache
     llm_tokens: Vec<TokenId>,
     llm_bytes: Vec<u8>,
+    // token log-probabilities spread evenly over the token bytes; same length as llm_bytes
+    llm_byte_logprobs: Vec<f64>,
 
     grm_prefix: Vec<u8>,
     is_fresh: bool,
@@ -88,6 +92,8 @@ impl TokenParser {
             &mut logger,
             limits.clone(),
             factory.extra_lexemes(),
+            factory.import_resolver(),
+            factory.json_retriever(),
         )?;
         let parser = Parser::new(
             token_env.clone(),
@@ -115,6 +121,7 @@ impl TokenParser {
             eos_tokens,
             llm_tokens: Vec::new(),
             llm_bytes: Vec::new(),
+            llm_byte_logprobs: Vec::new(),
             grm_prefix: Vec::new(),
             max_tokens_total: max_tokens,
             last_bias_time: Duration::from_secs(0),
@@ -136,6 +143,45 @@ impl TokenParser {
         self.parser.captures()
     }
 
+    /// Captures nested by their spans; offsets are into [`TokenParser::final_bytes()`].
+    pub fn capture_tree(&self) -> Vec<CaptureNode> {
+        let mut tree = self.parser.capture_tree().to_vec();
+        for node in tree.iter_mut() {
+            node.shift_down(self.grm_prefix.len());
+        }
+        tree
+    }
+
+    /// Byte spans in [`TokenParser::final_bytes()`] of [`TokenParser::captures()`],
+    /// in the same order.
+    pub fn capture_spans(&self) -> Vec<Range<usize>> {
+        let off = self.grm_prefix.len();
+        self.parser
+            .capture_spans()
+            .iter()
+            .map(|r| r.start.saturating_sub(off)..r.end.saturating_sub(off))
+            .collect()
+    }
+
+    /// Sum of log-probabilities of the bytes in `range` of [`TokenParser::final_bytes()`].
+    /// The log-probability of each token (as passed to
+    /// [`TokenParser::consume_token_with_logprob()`], otherwise `0.0`)
+    /// is spread evenly over its bytes, so tokens partially inside of `range` count partially.
+    pub fn bytes_logprob(&self, range: Range<usize>) -> f64 {
+        let off = self.grm_prefix.len();
+        let end = (range.end + off).min(self.llm_byte_logprobs.len());
+        let start = (range.start + off).min(end);
+        self.llm_byte_logprobs[start..end].iter().sum()
+    }
+
+    /// Parse tree of the output so far; offsets are into [`TokenParser::final_bytes()`].
+    /// See [`crate::Matcher::parse_tree()`].
+    pub fn parse_tree(&self, named_only: bool) -> ParseNode {
+        let mut tree = self.parser.parse_tree(named_only);
+        tree.shift_down(self.grm_prefix.len());
+        tree
+    }
+
     // regular .clone() uses a shared lexer state
     pub fn deep_clone(&self) -> Self {
         let mut copy = self.clone();
@@ -270,6 +316,7 @@ impl TokenParser {
                 self.grm_prefix = decoded[0..1].to_vec();
                 self.llm_bytes = decoded;
             }
+            self.llm_byte_logprobs = vec![0.0; self.llm_bytes.len()];
             infoln!(self, "ini_tokens: {}", trie.tokens_dbg(&self.llm_tokens));
         } else {
             // pretend the final bit of prompt was the prefix of the grammar
@@ -417,6 +464,7 @@ impl TokenParser {
         self.llm_tokens.truncate(new_len);
         self.llm_bytes
             .truncate(self.llm_bytes.len() - bytes_to_drop);
+        self.llm_byte_logprobs.truncate(self.llm_bytes.len());
         self.clear_caches();
 
         Ok(())
@@ -522,7 +570,7 @@ impl TokenParser {
         self.stop(&format!("{}{}", pref, err.message()), err.stop_reason())
     }
 
-    fn apply_token(&mut self, tok_id: TokenId) -> Result<usize> {
+    fn apply_token(&mut self, tok_id: TokenId, logprob: f64) -> Result<usize> {
         self.clear_caches();
 
         let trie = self.token_env.tok_trie();
@@ -537,6 +585,7 @@ impl TokenParser {
         self.llm_tokens.push(tok_id);
 
         let tok_bytes = trie.decode_raw(&[tok_id]);
+        let byte_logprob = logprob / tok_bytes.len().max(1) as f64;
 
         // first, check we're still in grm_prefix
         let prefix_len = self.grm_prefix.len().saturating_sub(self.llm_bytes.len());
@@ -552,6 +601,8 @@ impl TokenParser {
         let tok_bytes = if prefix_len > 0 {
             let to_apply = &tok_bytes[0..std::cmp::min(tok_bytes.len(), prefix_len)];
             self.llm_bytes.extend_from_slice(to_apply);
+            self.llm_byte_logprobs
+                .resize(self.llm_bytes.len(), byte_logprob);
 
             if self.grm_prefix[0..self.llm_bytes.len()] != self.llm_bytes {
                 return Err(self.stop(
@@ -589,6 +640,8 @@ impl TokenParser {
             }
             Ok(backtrack_bytes0) => {
                 self.llm_bytes.extend_from_slice(tok_bytes);
+                self.llm_byte_logprobs
+                    .resize(self.llm_bytes.len(), byte_logprob);
 
                 if backtrack_bytes0 != 0 {
                     self.had_backtrack = true;
@@ -617,6 +670,7 @@ impl TokenParser {
                         String::from_utf8_lossy(&self.llm_bytes[byte_ptr..])
                     );
                     self.llm_bytes.truncate(byte_ptr);
+                    self.llm_byte_logprobs.truncate(byte_ptr);
 
                     let token_ptr = self.llm_tokens.len() - backtrack_tokens;
                     if !self.inference_caps.backtrack {
@@ -802,6 +856,16 @@ impl TokenParser {
     /// Extend the current state of the parser with given token.
     /// Returns number of tokens to backtrack if any.
     pub fn consume_token(&mut self, token: TokenId) -> Result<usize> {
+        self.consume_token_with_logprob(token, None)
+    }
+
+    /// Like [`TokenParser::consume_token()`], but also records the log-probability
+    /// of the token, see [`TokenParser::bytes_logprob()`].
+    pub fn consume_token_with_logprob(
+        &mut self,
+        token: TokenId,
+        logprob: Option<f64>,
+    ) -> Result<usize> {
         self.check_initialized("consume_token")?;
 
         if self.max_tokens_total == 0 {
@@ -836,7 +900,7 @@ impl TokenParser {
             }
         }
 
-        let apply_res = self.apply_token(token);
+        let apply_res = self.apply_token(token, logprob.unwrap_or(0.0));
         self.parser.log_row_infos("post-apply");
         match apply_res {
             Err(_) => Err(self.anyhow_error()),
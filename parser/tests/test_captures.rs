@@ -0,0 +1,370 @@
+use llg_test_utils::{get_no_backtrack_parser_factory, get_tok_env};
+use llguidance::{
+    api::TopLevelGrammar,
+    earley::{CaptureNode, ParseNode},
+    output::ParserOutput,
+    Constraint, Matcher,
+};
+
+fn make_matcher(lark: &str) -> Matcher {
+    let grm = TopLevelGrammar::from_lark(lark.to_string());
+    Matcher::new(get_no_backtrack_parser_factory().create_parser(grm))
+}
+
+fn capture_tree_str(bytes: &[u8], nodes: &[CaptureNode]) -> String {
+    nodes
+        .iter()
+        .map(|n| {
+            let text = String::from_utf8_lossy(&bytes[n.start..n.end]);
+            if n.children.is_empty() {
+                format!("{}:{:?}", n.name, text)
+            } else {
+                format!(
+                    "{}:{:?}[{}]",
+                    n.name,
+                    text,
+                    capture_tree_str(bytes, &n.children)
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn check_capture_tree(lark: &str, input: &str, expected: &str) -> Matcher {
+    let mut matcher = make_matcher(lark);
+    matcher
+        .consume_tokens(&get_tok_env().tokenize(input))
+        .unwrap();
+    let tree = matcher.capture_tree();
+    assert_eq!(capture_tree_str(matcher.final_bytes(), &tree), expected);
+    matcher
+}
+
+const CALLS: &str = r#"
+    start: call ("," call)*
+    call[capture]: name "(" (arg ("," arg)*)? ")"
+    name[capture]: /[a-z_]+/
+    arg[capture]: /[0-9]+/ | call
+"#;
+
+#[test]
+fn test_capture_tree_nested() {
+    check_capture_tree(
+        CALLS,
+        "foo(1,22),bar(),baz(3,qux(4))",
+        concat!(
+            r#"call:"foo(1,22)"[name:"foo" arg:"1" arg:"22"] "#,
+            r#"call:"bar()"[name:"bar"] "#,
+            r#"call:"baz(3,qux(4))"[name:"baz" arg:"3" "#,
+            r#"arg:"qux(4)"[call:"qux(4)"[name:"qux" arg:"4"]]]"#
+        ),
+    );
+}
+
+#[test]
+fn test_capture_tree_lexemes() {
+    // adjacent lexemes, nullable captures
+    check_capture_tree(
+        r#"
+            start: w n e "."
+            w[capture]: /[a-z]+/
+            n[capture]: /[0-9]+/
+            e[capture]: "x"?
+        "#,
+        "abc123.",
+        r#"w:"abc" n:"123" e:"""#,
+    );
+
+    check_capture_tree(
+        r#"
+            start: "<" body tail
+            body[capture, stop="</x>", stop_capture="stop"]: /[a-z ]*/
+            tail[capture]: /[0-9]+/ "."
+        "#,
+        "<ab c</x>12.",
+        // the stop string is not part of the output
+        r#"body:"ab c"[stop:""] tail:"12.""#,
+    );
+
+    check_capture_tree(
+        r#"
+            start: call
+            call[capture]: name "(" arg ")"
+            name[capture]: /[a-z]+/
+            arg[capture]: /[0-9]+/
+            %ignore /[ \t]+/
+        "#,
+        "foo ( 1 )",
+        // same as in captures(), ignored white space before a rule is included
+        r#"call:"foo ( 1 )"[name:"foo" arg:" 1"]"#,
+    );
+}
+
+#[test]
+fn test_capture_tree_rollback() {
+    let mut matcher = make_matcher(CALLS);
+    let tok_env = get_tok_env();
+    matcher.consume_tokens(&tok_env.tokenize("foo(1)")).unwrap();
+    let tokens = tok_env.tokenize(",bar(2)");
+    matcher.consume_tokens(&tokens).unwrap();
+    assert_eq!(
+        capture_tree_str(matcher.final_bytes(), &matcher.capture_tree()),
+        r#"call:"foo(1)"[name:"foo" arg:"1"] call:"bar(2)"[name:"bar" arg:"2"]"#
+    );
+    matcher.rollback(tokens.len()).unwrap();
+    assert_eq!(
+        capture_tree_str(matcher.final_bytes(), &matcher.capture_tree()),
+        r#"call:"foo(1)"[name:"foo" arg:"1"]"#
+    );
+}
+
+fn parse_tree_str(bytes: &[u8], node: &ParseNode) -> String {
+    let text = String::from_utf8_lossy(&bytes[node.start..node.end]);
+    if node.is_lexeme {
+        format!("{}:{:?}", node.name, text)
+    } else {
+        let children = node
+            .children
+            .iter()
+            .map(|c| parse_tree_str(bytes, c))
+            .collect::<Vec<_>>();
+        format!("{}[{}]", node.name, children.join(" "))
+    }
+}
+
+fn check_parse_tree(lark: &str, input: &str, named_only: bool, expected: &str) -> ParseNode {
+    let lark = format!("%llguidance {{ \"parse_tree\": true }}\n{lark}");
+    let mut matcher = make_matcher(&lark);
+    matcher
+        .consume_tokens(&get_tok_env().tokenize(input))
+        .unwrap();
+    let tree = matcher.parse_tree(named_only).unwrap();
+    assert_eq!(parse_tree_str(matcher.final_bytes(), &tree), expected);
+    tree
+}
+
+const PAIRS: &str = r#"
+    start: pair ("," pair)*
+    pair: KEY "=" VALUE
+    KEY: /[a-z]+/
+    VALUE: /[0-9]+/
+"#;
+
+#[test]
+fn test_parse_tree_named() {
+    check_parse_tree(
+        PAIRS,
+        "a=1,bc=22,",
+        true,
+        concat!(
+            r#"start[pair[KEY:"a" "=":"=" VALUE:"1"] ",":"," "#,
+            r#"pair[KEY:"bc" "=":"=" VALUE:"22"] ",":","]"#
+        ),
+    );
+
+    // the lexeme "3" is not finished yet, so only the "=" is there
+    check_parse_tree(
+        CALLS,
+        "foo(1,bar(2,3",
+        true,
+        concat!(
+            r#"start[call[name[[1]:"foo"] "(":"(" arg[[3]:"1"] ",":"," "#,
+            r#"arg[call[name[[1]:"bar"] "(":"(" arg[[3]:"2"] ",":","]]]]"#
+        ),
+    );
+
+    // without the parse_tree option, rules get inlined
+    let mut matcher = make_matcher(PAIRS);
+    matcher
+        .consume_tokens(&get_tok_env().tokenize("a=1,"))
+        .unwrap();
+    let tree = matcher.parse_tree(true).unwrap();
+    assert_eq!(
+        parse_tree_str(matcher.final_bytes(), &tree),
+        r#"start[[1]:"a" [2]:"=" [3]:"1" [4]:","]"#
+    );
+}
+
+#[test]
+fn test_parse_tree_full() {
+    let tree = check_parse_tree(
+        r#"
+            start: a b "."
+            a: "x"
+            b: "y"?
+            %ignore " "
+        "#,
+        "x  .",
+        false,
+        r#"start[start[a["x":"x"] b[] ".":"."]]"#,
+    );
+    // ignored lexemes are not part of any node; empty nodes are placed
+    // after the preceding lexeme
+    let inner = &tree.children[0];
+    assert_eq!((inner.start, inner.end), (0, 4));
+    let b = &inner.children[1];
+    assert_eq!((b.start, b.end), (1, 1));
+}
+
+#[test]
+fn test_parse_tree_ambiguous() {
+    // earlier symbols get the longest span
+    check_parse_tree(
+        r#"
+            start: x x "."
+            x: "a"*
+        "#,
+        "aa.",
+        true,
+        r#"start[x["a":"a" "a":"a"] x[] ".":"."]"#,
+    );
+
+    // then the rule with higher priority
+    check_parse_tree(
+        r#"
+            start: (a | b) "."
+            a: "x" "y"
+            b.2: "x" "y"
+        "#,
+        "xy.",
+        true,
+        r#"start[b["x":"x" "y":"y"] ".":"."]"#,
+    );
+
+    // then the first rule
+    check_parse_tree(
+        r#"
+            start: (a | b) "."
+            a: "x" "y"
+            b: "x" "y"
+        "#,
+        "xy.",
+        true,
+        r#"start[a["x":"x" "y":"y"] ".":"."]"#,
+    );
+}
+
+#[test]
+fn test_parse_tree_cycles() {
+    // nullable symbols re-deriving themselves over an empty span
+    check_parse_tree(
+        r#"start: ("a"?)* ".""#,
+        "a.",
+        false,
+        r#"start[start[star[star[] #2["a":"a"]] ".":"."]]"#,
+    );
+    check_parse_tree(
+        r#"
+            start: item* "."
+            item: "a"?
+        "#,
+        "a",
+        true,
+        r#"start[item["a":"a"] item[]]"#,
+    );
+
+    // a symbol deriving itself
+    let cyclic = r#"
+        start: a
+        a: b | "x"
+        b: a?
+    "#;
+    check_parse_tree(cyclic, "x", true, r#"start[a["x":"x"]]"#);
+    let mut matcher = make_matcher(cyclic);
+    matcher
+        .consume_tokens(&get_tok_env().tokenize("x"))
+        .unwrap();
+    // without the parse_tree option, 'a' is merged into 'start'
+    let tree = matcher.parse_tree(false).unwrap();
+    assert_eq!(
+        parse_tree_str(matcher.final_bytes(), &tree),
+        r#"start[start#2[b[start#2[[1]:"x"]]]]"#
+    );
+}
+
+// log-probability of each token is minus its length, so every byte gets -1.0
+fn token_logprob(tok: u32) -> f64 {
+    -(get_tok_env().tok_trie().token(tok).len() as f64)
+}
+
+#[test]
+fn test_capture_logprob() {
+    let mut matcher = make_matcher(CALLS);
+    let tokens = get_tok_env().tokenize("foo(1,22),bar()");
+    for &t in &tokens {
+        matcher
+            .consume_token_with_logprob(t, Some(token_logprob(t)))
+            .unwrap();
+    }
+    let len = matcher.final_bytes().len();
+    assert_eq!(matcher.bytes_logprob(0..len), -(len as f64));
+    assert_eq!(matcher.bytes_logprob(3..3), 0.0);
+    assert_eq!(matcher.bytes_logprob(len..len + 10), 0.0);
+
+    let tree = matcher.capture_tree();
+    assert_eq!(tree.len(), 2);
+    for n in tree.iter().chain(tree[0].children.iter()) {
+        assert_eq!(
+            matcher.bytes_logprob(n.start..n.end),
+            -((n.end - n.start) as f64)
+        );
+    }
+
+    // tokens without log-probability count as zero
+    let mut matcher = make_matcher(CALLS);
+    matcher.consume_tokens(&tokens).unwrap();
+    assert_eq!(matcher.bytes_logprob(0..len), 0.0);
+}
+
+#[test]
+fn test_capture_logprob_progress() {
+    let grm = TopLevelGrammar::from_lark(
+        r#"
+            start: word (" " word)*
+            word[capture]: /[a-z]+/
+        "#
+        .to_string(),
+    );
+    let mut constraint = Constraint::new(
+        get_no_backtrack_parser_factory()
+            .create_parser(grm)
+            .unwrap(),
+    );
+    constraint.start_without_prompt();
+    let mut captures = vec![];
+    let mut text_len = 0;
+    let mut text_logprob = 0.0;
+    for t in get_tok_env().tokenize("hello big world") {
+        let r = constraint.compute_mask().unwrap();
+        assert!(r.sample_mask.as_ref().unwrap().is_allowed(t));
+        let res = constraint
+            .commit_token_with_logprob(Some(t), Some(token_logprob(t)))
+            .unwrap();
+        assert_eq!(res.backtrack, 0);
+        assert_eq!(res.ff_tokens, vec![t]);
+        for out in constraint.flush_progress() {
+            match out {
+                ParserOutput::Capture {
+                    name,
+                    bytes,
+                    log_prob,
+                } => {
+                    assert_eq!(name, "word");
+                    assert_eq!(log_prob, -(bytes.str.len() as f64));
+                    captures.push(bytes.str);
+                }
+                ParserOutput::Text {
+                    bytes, log_prob, ..
+                } => {
+                    text_len += bytes.str.len();
+                    text_logprob += log_prob;
+                }
+                ParserOutput::FinalText { .. } => {}
+            }
+        }
+    }
+    assert_eq!(captures, vec!["hello", "big"]);
+    assert_eq!(text_len, "hello big world".len());
+    assert_eq!(text_logprob, -(text_len as f64));
+}
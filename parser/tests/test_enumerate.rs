@@ -0,0 +1,84 @@
+use llg_test_utils::{get_no_backtrack_parser_factory, get_tok_env};
+use llguidance::{api::TopLevelGrammar, Completion, EnumerateOptions, Enumerator, Matcher};
+
+fn enumerate(lark: &str, options: EnumerateOptions) -> Vec<Completion> {
+    let grm = TopLevelGrammar::from_lark(lark.to_string());
+    let matcher = Matcher::new(get_no_backtrack_parser_factory().create_parser(grm));
+    Enumerator::new(matcher, options)
+        .unwrap()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap()
+}
+
+fn strings(completions: &[Completion]) -> Vec<String> {
+    let mut res = completions
+        .iter()
+        .map(|c| String::from_utf8(c.bytes.clone()).unwrap())
+        .collect::<Vec<_>>();
+    res.sort();
+    res
+}
+
+#[test]
+fn test_enumerate_canonical() {
+    let res = enumerate(
+        r#"start: "yes" | "no" | "maybe" | "yes, really""#,
+        EnumerateOptions::default(),
+    );
+    assert_eq!(strings(&res), vec!["maybe", "no", "yes", "yes, really"]);
+    let tok_env = get_tok_env();
+    for c in &res {
+        assert_eq!(c.tokens, tok_env.tokenize_bytes(&c.bytes));
+    }
+}
+
+#[test]
+fn test_enumerate_all_tokenizations() {
+    let res = enumerate(
+        r#"start: "yes" | "no" | "maybe""#,
+        EnumerateOptions {
+            canonical_only: false,
+            ..Default::default()
+        },
+    );
+    let trie = get_tok_env().tok_trie();
+    assert!(res.len() > 3);
+    for c in &res {
+        assert_eq!(trie.decode_raw(&c.tokens), c.bytes);
+    }
+    let mut strs = strings(&res);
+    strs.dedup();
+    assert_eq!(strs, vec!["maybe", "no", "yes"]);
+    // one-byte tokens only
+    assert!(res.iter().any(|c| c.tokens.len() == c.bytes.len()));
+}
+
+#[test]
+fn test_enumerate_limits() {
+    let res = enumerate(
+        r#"start: /a+/"#,
+        EnumerateOptions {
+            max_tokens: 3,
+            canonical_only: false,
+            ..Default::default()
+        },
+    );
+    assert!(!res.is_empty());
+    for c in &res {
+        assert!(!c.tokens.is_empty() && c.tokens.len() <= 3);
+        assert!(c.bytes.iter().all(|&b| b == b'a'));
+    }
+
+    let res = enumerate(
+        r#"start: /[ab]+/"#,
+        EnumerateOptions {
+            max_count: 5,
+            ..Default::default()
+        },
+    );
+    assert_eq!(res.len(), 5);
+
+    // the empty string is a completion too
+    let res = enumerate(r#"start: "x"?"#, EnumerateOptions::default());
+    assert_eq!(strings(&res), vec!["", "x"]);
+}
@@ -0,0 +1,200 @@
+use llg_test_utils::{get_no_backtrack_parser_factory, get_tok_env};
+use llguidance::{api::TopLevelGrammar, FuzzOptions, GrammarFuzzer, HashMap, Matcher};
+use serde_json::{json, Value};
+
+fn fuzz(grammar: &TopLevelGrammar, options: FuzzOptions, n: usize) -> Vec<String> {
+    let mut fuzzer = GrammarFuzzer::new(grammar.clone(), options).unwrap();
+    (0..n)
+        .map(|_| {
+            let bytes = fuzzer.generate().unwrap();
+            let s = String::from_utf8(bytes).unwrap();
+
+            // check with a regular tokenizer
+            let mut matcher =
+                Matcher::new(get_no_backtrack_parser_factory().create_parser(grammar.clone()));
+            let tokens = get_tok_env().tokenize(&s);
+            matcher.consume_tokens(&tokens).unwrap();
+            assert!(matcher.is_accepting().unwrap(), "not accepted: {s:?}");
+
+            s
+        })
+        .collect()
+}
+
+fn lark(s: &str) -> TopLevelGrammar {
+    TopLevelGrammar::from_lark(s.to_string())
+}
+
+#[test]
+fn test_fuzz_lark() {
+    let res = fuzz(
+        &lark(
+            r#"
+                start: expr
+                expr: term (("+" | "-") term)*
+                term: NUMBER | "(" expr ")" | NAME "(" expr ")"
+                NUMBER: /[0-9]+(\.[0-9]+)?/
+                NAME: /[a-z_]+/
+            "#,
+        ),
+        FuzzOptions::default(),
+        20,
+    );
+    assert!(res.iter().any(|s| s.contains('(')));
+    assert!(res.iter().any(|s| s.contains('+') || s.contains('-')));
+
+    // lexemes that would be lexed as one are separated
+    let res = fuzz(
+        &lark(
+            r#"
+                start: NUMBER+ WORD?
+                NUMBER: /[0-9]+/
+                WORD: /[a-z]+/
+                %ignore " "
+            "#,
+        ),
+        FuzzOptions::default(),
+        20,
+    );
+    assert!(res.iter().any(|s| s.contains(' ')));
+
+    // lazy lexemes and JSON inside Lark
+    fuzz(
+        &lark(
+            r#"
+                start: "<a>" text "</a>" obj
+                text[lazy]: /.*/ "</"
+                obj: %json { "type": "object", "properties": { "x": { "type": "integer" } } }
+            "#,
+        ),
+        FuzzOptions::default(),
+        10,
+    );
+}
+
+#[test]
+fn test_fuzz_json_schema() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "name": {"type": "string", "minLength": 2, "maxLength": 5},
+            "age": {"type": "integer", "minimum": 18, "maximum": 99},
+            "kind": {"enum": ["a", "b", "c"]},
+            "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3},
+            "score": {"type": "number"},
+        },
+        "required": ["name", "age", "kind"],
+        "additionalProperties": false
+    });
+    let res = fuzz(
+        &TopLevelGrammar::from_json_schema(schema),
+        FuzzOptions::default(),
+        20,
+    );
+    for s in &res {
+        let v: Value = serde_json::from_str(s).unwrap();
+        let name = v["name"].as_str().unwrap();
+        assert!((2..=5).contains(&name.chars().count()));
+        assert!((18..=99).contains(&v["age"].as_i64().unwrap()));
+        assert!(["a", "b", "c"].contains(&v["kind"].as_str().unwrap()));
+        if let Some(tags) = v.get("tags") {
+            assert!(tags.as_array().unwrap().len() <= 3);
+        }
+        if let Some(score) = v.get("score") {
+            assert!(score.is_number());
+        }
+    }
+    assert!(res.iter().any(|s| s.contains("\"tags\"")));
+}
+
+#[test]
+fn test_fuzz_seed() {
+    let grm = lark(r#"start: /[a-z]{5,10}/ | /[0-9]+/"#);
+    let a = fuzz(&grm, FuzzOptions::default(), 10);
+    let b = fuzz(&grm, FuzzOptions::default(), 10);
+    assert_eq!(a, b);
+    let c = fuzz(
+        &grm,
+        FuzzOptions {
+            seed: 42,
+            ..Default::default()
+        },
+        10,
+    );
+    assert_ne!(a, c);
+}
+
+#[test]
+fn test_fuzz_depth_and_weights() {
+    let res = fuzz(
+        &lark(r#"start: "(" start ")" | "x""#),
+        FuzzOptions {
+            max_depth: 5,
+            ..Default::default()
+        },
+        50,
+    );
+    assert!(res.iter().all(|s| s.len() <= 2 * 5 + 1));
+    assert!(res.iter().any(|s| s.len() > 1));
+
+    let grm = lark(
+        r#"
+            start: a | b | c
+            a: "a"
+            b: "b"
+            c: C | "d"
+            C: "c"
+        "#,
+    );
+    let res = fuzz(
+        &grm,
+        FuzzOptions {
+            symbol_weights: HashMap::from_iter([("a".to_string(), 0.0), ("C".to_string(), 0.0)]),
+            ..Default::default()
+        },
+        20,
+    );
+    assert!(res.iter().all(|s| s == "b" || s == "d"));
+    assert!(res.iter().any(|s| s == "d"));
+
+    let res = fuzz(
+        &grm,
+        FuzzOptions {
+            symbol_weights: HashMap::from_iter([("b".to_string(), 100.0)]),
+            ..Default::default()
+        },
+        100,
+    );
+    assert!(res.iter().filter(|s| *s == "b").count() > 80);
+}
+
+#[test]
+fn test_fuzz_lexeme_distribution() {
+    let grm = TopLevelGrammar::from_json_schema(json!({
+        "type": "array",
+        "items": {"type": ["string", "integer"]},
+        "minItems": 5
+    }));
+
+    let mut options = FuzzOptions::default();
+    options.string.mean_len = 0.0;
+    options.number.max_len = 1;
+    for s in fuzz(&grm, options, 10) {
+        let v: Value = serde_json::from_str(&s).unwrap();
+        for e in v.as_array().unwrap() {
+            match e {
+                Value::String(s) => assert_eq!(s, ""),
+                Value::Number(n) => assert!((-9..=9).contains(&n.as_i64().unwrap())),
+                _ => panic!("unexpected {e}"),
+            }
+        }
+    }
+
+    // only letters (other bytes have weight 0)
+    let mut options = FuzzOptions::default();
+    options.string.other_weight = 0.0;
+    options.string.non_ascii_weight = 0.0;
+    for s in fuzz(&lark(r#"start: /[^\n]{3}/"#), options, 10) {
+        assert!(s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b' '));
+    }
+}
@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 use rstest::*;
 use serde_json::{json, Value};
 
-use llg_test_utils::{json_err_test, json_schema_check};
+use llg_test_utils::{json_err_test, json_schema_check, json_test_many};
 
 lazy_static! {
     static ref INTEGER_ARRAY: Value = json!({"type":"array", "items": {"type":"integer"}});
@@ -213,3 +213,266 @@ fn array_with_prefix_items_length_constrained_failures(#[case] sample_array: &Va
         false,
     );
 }
+
+lazy_static! {
+    static ref UNIQUE_ENUM_ARRAY: Value = json!({
+        "type": "array",
+        "items": {"enum": ["read", "write", "admin", 7, null]},
+        "uniqueItems": true
+    });
+}
+
+#[rstest]
+#[case::empty_list(&json!([]))]
+#[case::single_item(&json!(["read"]))]
+#[case(&json!(["write", "read"]))]
+#[case(&json!(["admin", 7, null, "read", "write"]))]
+fn array_unique_items(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_ENUM_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case(&json!(["read", "read"]))]
+#[case(&json!(["write", 7, "write"]))]
+#[case(&json!([null, null]))]
+#[case(&json!(["execute"]))]
+fn array_unique_items_failures(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_ENUM_ARRAY, sample_array, false);
+}
+
+#[test]
+fn array_unique_items_one_of() {
+    // "a" is listed twice (not consecutively) in the first option, and "c" in both
+    let schema = json!({
+        "type": "array",
+        "items": {"oneOf": [{"enum": ["a", "b", "a"]}, {"enum": ["c", "d"]}, {"const": "c"}]},
+        "uniqueItems": true
+    });
+    json_test_many(
+        &schema,
+        &[json!(["a", "b"]), json!(["b", "a", "d"])],
+        &[json!(["a", "a"]), json!(["c"]), json!(["a", "b", "a"])],
+    );
+}
+
+#[test]
+fn array_unique_items_bounds() {
+    let schema = json!({
+        "type": "array",
+        "prefixItems": [{"const": "a"}],
+        "items": {"enum": ["a", "b", "c", "d"]},
+        "uniqueItems": true,
+        "minItems": 2,
+        "maxItems": 3
+    });
+    json_test_many(
+        &schema,
+        &[json!(["a", "b"]), json!(["a", "d", "c"])],
+        &[
+            json!(["a"]),
+            json!(["b", "a"]),
+            json!(["a", "a"]),
+            json!(["a", "b", "c", "d"]),
+        ],
+    );
+
+    json_test_many(
+        &json!({"type": "array", "items": {"type": "boolean"}, "uniqueItems": true}),
+        &[json!([true, false]), json!([false])],
+        &[json!([true, true]), json!([false, true, false])],
+    );
+}
+
+#[test]
+fn array_unique_items_errors() {
+    json_err_test(
+        &json!({"type": "array", "items": {"type": "string"}, "uniqueItems": true}),
+        "uniqueItems is only supported when items are a finite set of scalar const/enum values",
+    );
+    json_err_test(
+        &json!({"type": "array", "items": {"enum": ["a", "b"]}, "uniqueItems": true, "minItems": 3}),
+        "Unsatisfiable schema: minItems (3) is greater than number of distinct items (2)",
+    );
+    json_err_test(
+        &json!({"type": "array", "items": {"type": "integer", "minimum": 0, "maximum": 100}, "uniqueItems": true}),
+        "uniqueItems is only supported when items are a finite set",
+    );
+}
+
+#[test]
+fn array_unique_items_lenient() {
+    // not enumerable, so uniqueness is dropped with a warning
+    json_test_many(
+        &json!({
+            "x-guidance": {"lenient": true},
+            "type": "array",
+            "items": {"type": "string"},
+            "uniqueItems": true
+        }),
+        &[json!(["a", "a"])],
+        &[json!([1])],
+    );
+    // trivially unique
+    json_test_many(
+        &json!({"type": "array", "items": {"type": "string"}, "uniqueItems": true, "maxItems": 1}),
+        &[json!(["a"]), json!([])],
+        &[json!(["a", "b"])],
+    );
+}
+
+lazy_static! {
+    static ref CONTAINS_MESSAGES: Value = json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {"role": {"enum": ["system", "user", "assistant"]}},
+            "required": ["role"],
+            "additionalProperties": false
+        },
+        "contains": {
+            "type": "object",
+            "properties": {"role": {"const": "system"}},
+            "required": ["role"]
+        }
+    });
+}
+
+#[rstest]
+#[case(&json!([{"role": "system"}]))]
+#[case(&json!([{"role": "user"}, {"role": "system"}]))]
+#[case(&json!([{"role": "system"}, {"role": "user"}, {"role": "system"}]))]
+fn array_contains(#[case] obj: &Value) {
+    json_schema_check(&CONTAINS_MESSAGES, obj, true);
+}
+
+#[rstest]
+#[case(&json!([]))]
+#[case(&json!([{"role": "user"}]))]
+#[case(&json!([{"role": "user"}, {"role": "assistant"}]))]
+fn array_contains_failures(#[case] obj: &Value) {
+    json_schema_check(&CONTAINS_MESSAGES, obj, false);
+}
+
+#[test]
+fn array_contains_bounds() {
+    json_test_many(
+        &json!({
+            "type": "array",
+            "items": {"type": "integer"},
+            "contains": {"const": 0},
+            "minContains": 2,
+            "maxContains": 3
+        }),
+        &[
+            json!([0, 0]),
+            json!([1, 0, 2, 0]),
+            json!([0, 0, 0, 5]),
+            json!([7, 0, 8, 0, 9, 0, 10]),
+        ],
+        &[json!([0]), json!([0, 1]), json!([0, 0, 0, 0]), json!([])],
+    );
+    json_test_many(
+        &json!({
+            "type": "array",
+            "items": {"type": "integer"},
+            "contains": {"const": 0},
+            "minContains": 0,
+            "maxContains": 1
+        }),
+        &[json!([]), json!([1, 2]), json!([1, 0, 2])],
+        &[json!([0, 0]), json!([0, 1, 0])],
+    );
+    json_test_many(
+        &json!({
+            "type": "array",
+            "items": {"type": "string"},
+            "contains": {"const": "x"},
+            "minItems": 3,
+            "maxItems": 4
+        }),
+        &[json!(["a", "b", "x"]), json!(["x", "x", "x", "x"])],
+        &[
+            json!(["a", "x"]),
+            json!(["a", "b", "c"]),
+            json!(["x", "a", "b", "c", "d"]),
+        ],
+    );
+    // minContains of 0 without maxContains is a no-op
+    json_test_many(
+        &json!({"type": "array", "contains": {"const": 0}, "minContains": 0}),
+        &[json!([]), json!([1])],
+        &[],
+    );
+}
+
+#[test]
+fn array_contains_prefix_items() {
+    json_test_many(
+        &json!({
+            "type": "array",
+            "prefixItems": [{"type": "string"}, {"type": "integer"}],
+            "items": {"type": ["boolean", "string"]},
+            "contains": {"type": "string"},
+            "maxContains": 1
+        }),
+        &[json!(["a", 1]), json!(["a", 1, true, false])],
+        &[
+            json!([1]),
+            json!([]),
+            json!(["a", 1, "b"]),
+            json!(["a", 1, true, "b"]),
+        ],
+    );
+    json_test_many(
+        &json!({
+            "type": "array",
+            "prefixItems": [{"type": "string"}],
+            "items": {"type": ["boolean", "string"]},
+            "contains": {"type": "boolean"},
+            "minContains": 2
+        }),
+        &[
+            json!(["a", true, false]),
+            json!(["a", "b", true, "c", true]),
+        ],
+        &[
+            json!([true, true]),
+            json!(["a", true]),
+            json!(["a", "b", true]),
+        ],
+    );
+}
+
+#[test]
+fn array_contains_errors() {
+    json_err_test(
+        &json!({
+            "type": "array",
+            "contains": {"const": 0},
+            "minContains": 3,
+            "maxContains": 2
+        }),
+        "minContains (3) is greater than maxContains (2)",
+    );
+    json_err_test(
+        &json!({
+            "type": "array",
+            "contains": {"const": 0},
+            "minContains": 3,
+            "maxItems": 2
+        }),
+        "minContains (3) is greater than maxItems (2)",
+    );
+    json_err_test(
+        &json!({"type": "array", "contains": {"const": 0}, "minContains": 100000}),
+        "minContains/maxContains larger than 65535 are not supported",
+    );
+    json_err_test(
+        &json!({"type": "array", "contains": {"type": "integer", "minimum": 3}, "maxContains": 2}),
+        "maxContains is only supported when the 'contains' schema can be negated",
+    );
+    json_err_test(
+        &json!({"type": "array", "items": {"type": "string"}, "contains": {"type": "integer"}}),
+        "no array item can match 'contains'",
+    );
+}
@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 use rstest::*;
 use serde_json::{json, Value};
 
-use llg_test_utils::{json_err_test, json_schema_check};
+use llg_test_utils::{json_err_test, json_schema_check, lark_str_test_many};
 
 lazy_static! {
     static ref SINGLE_PROPERTY_SCHEMA: Value =
@@ -200,3 +200,364 @@ fn linked_list(#[case] obj: &Value) {
 fn linked_list_failures(#[case] obj: &Value) {
     json_schema_check(&LINKED_LIST, obj, false);
 }
+
+lazy_static! {
+    static ref DEPENDENT_REQUIRED_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "credit_card": {"type": "integer"},
+            "billing_address": {"type": "string"},
+            "cvv": {"type": "integer"}
+        },
+        "dependentRequired": {
+            "credit_card": ["billing_address"],
+            "cvv": ["credit_card"]
+        },
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({}))]
+#[case(&json!({"name": "A"}))]
+#[case(&json!({"billing_address": "B"}))]
+#[case(&json!({"credit_card": 1, "billing_address": "B"}))]
+#[case(&json!({"name": "A", "credit_card": 1, "billing_address": "B", "cvv": 123}))]
+fn dependent_required(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_REQUIRED_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case::missing_later(&json!({"credit_card": 1}))]
+#[case::missing_earlier(&json!({"billing_address": "B", "cvv": 123}))]
+#[case::transitive(&json!({"name": "A", "cvv": 123}))]
+fn dependent_required_failures(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_REQUIRED_SCHEMA, obj, false);
+}
+
+#[rstest]
+#[case::required_trigger(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+    "required": ["a"],
+    "dependentRequired": {"a": ["b"]}
+}), &json!({"a": 1}), false)]
+#[case::required_trigger_ok(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+    "required": ["a"],
+    "dependentRequired": {"a": ["b"]}
+}), &json!({"a": 1, "b": 2}), true)]
+#[case::unsatisfiable_dependency(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "dependentRequired": {"a": ["z"]},
+    "additionalProperties": false
+}), &json!({"a": 1}), false)]
+#[case::unsatisfiable_dependency_ok(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "dependentRequired": {"a": ["z"]},
+    "additionalProperties": false
+}), &json!({}), true)]
+#[case::dependency_from_additional(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "dependentRequired": {"a": ["z"]},
+    "additionalProperties": {"type": "string"}
+}), &json!({"a": 1, "z": "x"}), true)]
+#[case::dependency_from_additional_missing(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "dependentRequired": {"a": ["z"]},
+    "additionalProperties": {"type": "string"}
+}), &json!({"a": 1, "y": "x"}), false)]
+fn dependent_required_cases(#[case] schema: &Value, #[case] obj: &Value, #[case] valid: bool) {
+    json_schema_check(schema, obj, valid);
+}
+
+lazy_static! {
+    static ref DEPENDENT_SCHEMAS_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "kind": {"type": "string"},
+            "size": {"type": "integer"},
+            "unit": {"type": "string"}
+        },
+        "dependentSchemas": {
+            "size": {"required": ["unit"]},
+            "kind": {"properties": {"size": {"maximum": 10}}}
+        },
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({}))]
+#[case(&json!({"size": 100, "unit": "cm"}))]
+#[case(&json!({"kind": "box", "size": 10, "unit": "cm"}))]
+#[case(&json!({"kind": "box"}))]
+fn dependent_schemas(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_SCHEMAS_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case(&json!({"size": 100}))]
+#[case(&json!({"kind": "box", "size": 11, "unit": "cm"}))]
+fn dependent_schemas_failures(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_SCHEMAS_SCHEMA, obj, false);
+}
+
+lazy_static! {
+    static ref DEPENDENCIES_DRAFT7_SCHEMA: Value = json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "properties": {
+            "a": {"type": "integer"},
+            "b": {"type": "integer"},
+            "c": {"type": "integer"}
+        },
+        "dependencies": {
+            "a": ["b"],
+            "c": {"properties": {"a": {"const": 1}}, "required": ["a"]}
+        },
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({}), true)]
+#[case(&json!({"a": 2, "b": 1}), true)]
+#[case(&json!({"a": 1, "b": 1, "c": 1}), true)]
+#[case(&json!({"a": 2}), false)]
+#[case(&json!({"b": 1, "c": 1}), false)]
+#[case(&json!({"a": 2, "b": 1, "c": 1}), false)]
+fn dependencies_draft7(#[case] obj: &Value, #[case] valid: bool) {
+    json_schema_check(&DEPENDENCIES_DRAFT7_SCHEMA, obj, valid);
+}
+
+#[test]
+fn dependent_required_errors() {
+    json_err_test(
+        &json!({"type": "object", "dependentRequired": {"a": "b"}}),
+        "Expected array for 'dependentRequired' item",
+    );
+    json_err_test(
+        &json!({"type": "object", "dependentRequired": {"a": [1]}}),
+        "Expected string for 'dependentRequired' item",
+    );
+
+    // every subset of the second half of properties can be forced
+    let properties = (0..40)
+        .map(|i| (format!("p{i}"), json!({"type": "integer"})))
+        .collect::<serde_json::Map<_, _>>();
+    let dependent_required = (0..20)
+        .map(|i| (format!("p{i}"), json!([format!("p{}", i + 20)])))
+        .collect::<serde_json::Map<_, _>>();
+    json_err_test(
+        &json!({
+            "type": "object",
+            "properties": properties,
+            "dependentRequired": dependent_required
+        }),
+        "dependentRequired too complex",
+    );
+}
+
+lazy_static! {
+    static ref PROPERTY_NAMES_PATTERN_SCHEMA: Value = json!({
+        "type": "object",
+        "additionalProperties": {"type": "integer"},
+        "propertyNames": {"pattern": "^[a-z_]+$"}
+    });
+    static ref PROPERTY_NAMES_ENUM_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {"id": {"type": "integer"}},
+        "additionalProperties": {"type": "string"},
+        "propertyNames": {"enum": ["id", "name", "title"]}
+    });
+}
+
+#[rstest]
+#[case(&json!({}))]
+#[case(&json!({"a": 1}))]
+#[case(&json!({"foo_bar": 1, "baz": 2}))]
+fn property_names_pattern(#[case] obj: &Value) {
+    json_schema_check(&PROPERTY_NAMES_PATTERN_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case(&json!({"A": 1}))]
+#[case(&json!({"a1": 1}))]
+#[case(&json!({"": 1}))]
+#[case(&json!({"ok": 1, "not-ok": 2}))]
+fn property_names_pattern_failures(#[case] obj: &Value) {
+    json_schema_check(&PROPERTY_NAMES_PATTERN_SCHEMA, obj, false);
+}
+
+#[rstest]
+#[case(&json!({"id": 1}), true)]
+#[case(&json!({"id": 1, "name": "x", "title": "y"}), true)]
+#[case(&json!({"title": "y"}), true)]
+#[case(&json!({"id": 1, "other": "x"}), false)]
+#[case(&json!({"name": 1}), false)]
+fn property_names_enum(#[case] obj: &Value, #[case] valid: bool) {
+    json_schema_check(&PROPERTY_NAMES_ENUM_SCHEMA, obj, valid);
+}
+
+#[rstest]
+#[case::max_length(&json!({
+    "type": "object",
+    "patternProperties": {"^x": {"type": "integer"}},
+    "additionalProperties": false,
+    "propertyNames": {"maxLength": 3}
+}), &json!({"xyz": 1}), true)]
+#[case::max_length_too_long(&json!({
+    "type": "object",
+    "patternProperties": {"^x": {"type": "integer"}},
+    "additionalProperties": false,
+    "propertyNames": {"maxLength": 3}
+}), &json!({"xyzw": 1}), false)]
+#[case::optional_property_excluded(&json!({
+    "type": "object",
+    "properties": {"Bad": {"type": "integer"}, "good": {"type": "integer"}},
+    "propertyNames": {"pattern": "^[a-z]+$"}
+}), &json!({"Bad": 1}), false)]
+#[case::optional_property_included(&json!({
+    "type": "object",
+    "properties": {"Bad": {"type": "integer"}, "good": {"type": "integer"}},
+    "propertyNames": {"pattern": "^[a-z]+$"}
+}), &json!({"good": 1}), true)]
+#[case::false_schema(&json!({
+    "type": "object",
+    "propertyNames": false
+}), &json!({}), true)]
+#[case::false_schema_non_empty(&json!({
+    "type": "object",
+    "propertyNames": false
+}), &json!({"a": 1}), false)]
+#[case::intersected(&json!({
+    "type": "object",
+    "propertyNames": {"pattern": "^[a-z]+$"},
+    "allOf": [{"propertyNames": {"maxLength": 2}}]
+}), &json!({"ab": 1}), true)]
+#[case::intersected_too_long(&json!({
+    "type": "object",
+    "propertyNames": {"pattern": "^[a-z]+$"},
+    "allOf": [{"propertyNames": {"maxLength": 2}}]
+}), &json!({"abc": 1}), false)]
+fn property_names_cases(#[case] schema: &Value, #[case] obj: &Value, #[case] valid: bool) {
+    json_schema_check(schema, obj, valid);
+}
+
+#[test]
+fn property_names_errors() {
+    json_err_test(
+        &json!({
+            "type": "object",
+            "properties": {"A": {"type": "integer"}},
+            "required": ["A"],
+            "propertyNames": {"pattern": "^[a-z]+$"}
+        }),
+        "required property 'A' doesn't match propertyNames",
+    );
+}
+
+lazy_static! {
+    static ref FLEXIBLE_ORDER_SCHEMA: Value = json!({
+        "x-guidance": {"flexible_property_order": true},
+        "type": "object",
+        "properties": {
+            "a": {"type": "integer"},
+            "b": {"type": "string"},
+            "c": {"type": "boolean"}
+        },
+        "required": ["a", "b"],
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({"a": 1, "b": "x"}))]
+#[case(&json!({"b": "x", "a": 1}))]
+#[case(&json!({"c": true, "b": "x", "a": 1}))]
+#[case(&json!({"b": "x", "c": false, "a": 1}))]
+fn flexible_order(#[case] obj: &Value) {
+    json_schema_check(&FLEXIBLE_ORDER_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case(&json!({}))]
+#[case(&json!({"b": "x"}))]
+#[case(&json!({"c": true, "a": 1}))]
+#[case(&json!({"a": 1, "b": "x", "d": 1}))]
+fn flexible_order_failures(#[case] obj: &Value) {
+    json_schema_check(&FLEXIBLE_ORDER_SCHEMA, obj, false);
+}
+
+#[test]
+fn flexible_order_duplicates() {
+    lark_str_test_many(
+        &format!("start: %json {}", *FLEXIBLE_ORDER_SCHEMA),
+        &[r#"{"b":"x","a":1}"#],
+        &[r#"{"b":"x","a":1,"b":"y"}"#, r#"{"a":1,"a":2,"b":"x"}"#],
+    );
+}
+
+#[rstest]
+#[case::additional(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "required": ["a"],
+    "additionalProperties": {"type": "string"}
+}), &json!({"x": "1", "a": 1, "y": "2"}), true)]
+#[case::additional_missing_required(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "required": ["a"],
+    "additionalProperties": {"type": "string"}
+}), &json!({"x": "1"}), false)]
+#[case::additional_max(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}},
+    "required": ["a"],
+    "additionalProperties": {"type": "string"},
+    "maxProperties": 2
+}), &json!({"x": "1", "a": 1, "y": "2"}), false)]
+#[case::min_properties(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}, "c": {"type": "integer"}},
+    "additionalProperties": false,
+    "minProperties": 2,
+    "maxProperties": 2
+}), &json!({"c": 1, "a": 2}), true)]
+#[case::min_properties_too_few(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}, "c": {"type": "integer"}},
+    "additionalProperties": false,
+    "minProperties": 2,
+    "maxProperties": 2
+}), &json!({"c": 1}), false)]
+#[case::max_properties_too_many(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}, "c": {"type": "integer"}},
+    "additionalProperties": false,
+    "minProperties": 2,
+    "maxProperties": 2
+}), &json!({"c": 1, "a": 2, "b": 3}), false)]
+#[case::dependent_required(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+    "dependentRequired": {"a": ["b"]}
+}), &json!({"b": 1, "a": 2}), true)]
+#[case::dependent_required_missing(&json!({
+    "type": "object",
+    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+    "dependentRequired": {"a": ["b"]}
+}), &json!({"a": 2}), false)]
+fn flexible_order_cases(#[case] schema: &Value, #[case] obj: &Value, #[case] valid: bool) {
+    let mut schema = schema.clone();
+    schema["x-guidance"] = json!({"flexible_property_order": true});
+    json_schema_check(&schema, obj, valid);
+}
@@ -1,7 +1,7 @@
 use rstest::*;
 use serde_json::{json, Value};
 
-use llg_test_utils::{json_err_test, json_schema_check, NumericBounds};
+use llg_test_utils::{json_err_test, json_schema_check, json_test_many, NumericBounds};
 
 #[test]
 fn null_schema() {
@@ -486,6 +486,44 @@ fn string_regex_failures(#[case] sample_value: &Value) {
     json_schema_check(schema, sample_value, false);
 }
 
+#[rstest]
+#[case(&json!("abcdefg1"), true)]
+#[case(&json!("1234567a"), true)]
+#[case(&json!("abc1"), false)]
+#[case(&json!("abcdefgh"), false)]
+#[case(&json!("12345678"), false)]
+fn string_regex_lookahead(#[case] sample_value: &Value, #[case] expect_valid: bool) {
+    let schema = &json!({"type":"string", "pattern": r"^(?=.*\d)(?=.*[a-z]).{8,}$"});
+    json_schema_check(schema, sample_value, expect_valid);
+}
+
+#[rstest]
+#[case(&json!("foo"), true)]
+#[case(&json!("foo bar"), true)]
+#[case(&json!("admin"), false)]
+#[case(&json!("admins"), false)]
+#[case(&json!("x-admin"), true)]
+fn string_regex_negative_lookahead(#[case] sample_value: &Value, #[case] expect_valid: bool) {
+    let schema = &json!({"type":"string", "pattern": r"^(?!admin)"});
+    json_schema_check(schema, sample_value, expect_valid);
+}
+
+#[test]
+fn string_regex_lookaround_errors() {
+    json_err_test(
+        &json!({"type":"string", "pattern": r"(?=.*\d).{8,}"}),
+        "lookahead ((?=...) or (?!...)) is only supported at the start of a pattern, right after ^",
+    );
+    json_err_test(
+        &json!({"type":"string", "pattern": r"^a(?=b)"}),
+        "lookahead ((?=...) or (?!...)) is only supported at the start of a pattern",
+    );
+    json_err_test(
+        &json!({"type":"string", "pattern": r"^(?<=a)b"}),
+        "lookbehind ((?<=...) or (?<!...)) is not supported",
+    );
+}
+
 #[rstest]
 #[case(&json!("abc"))]
 #[case(&json!("abcd"))]
@@ -543,3 +581,133 @@ fn string_length_unsatisfiable() {
         "Unsatisfiable schema: minLength (2) is greater than maxLength (1)",
     );
 }
+
+#[test]
+fn string_content_encoding() {
+    let schema = &json!({
+        "x-guidance": {"enforce_content": true},
+        "type": "string",
+        "contentEncoding": "base64"
+    });
+    json_test_many(
+        schema,
+        &[
+            json!(""),
+            json!("aGVsbG8="),
+            json!("aGk="),
+            json!("YWJj"),
+            json!("+/+/"),
+        ],
+        &[
+            json!("aGVsbG8"),
+            json!("aGk=="),
+            json!("a==="),
+            json!("hello!"),
+        ],
+    );
+
+    let schema = &json!({
+        "x-guidance": {"enforce_content": true},
+        "type": "string",
+        "contentEncoding": "base16",
+        "maxLength": 4
+    });
+    json_test_many(
+        schema,
+        &[json!(""), json!("00ff"), json!("A0")],
+        &[json!("0"), json!("0g"), json!("00ff00")],
+    );
+}
+
+#[test]
+fn string_content_not_enforced() {
+    let schema = &json!({
+        "type": "string",
+        "contentEncoding": "base64",
+        "contentMediaType": "application/json",
+        "contentSchema": {"type": "object"}
+    });
+    json_test_many(schema, &[json!("hello!"), json!("[1]")], &[json!(1)]);
+}
+
+#[test]
+fn string_content_json() {
+    let schema = &json!({
+        "x-guidance": {"enforce_content": true},
+        "type": "object",
+        "properties": {
+            "payload": {
+                "type": "string",
+                "contentMediaType": "application/json",
+                "contentSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "tags": {"type": "array", "items": {"type": "string"}}
+                    },
+                    "required": ["name"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["payload"]
+    });
+    json_test_many(
+        schema,
+        &[
+            json!({"payload": r#"{"name":"a"}"#}),
+            json!({"payload": r#"{"name":"a\"b\\c","tags":["x","y\nz"]}"#}),
+            json!({"payload": r#" { "name" : "a" } "#}),
+        ],
+        &[
+            json!({"payload": r#"{"tags":[]}"#}),
+            json!({"payload": r#"{"name":1}"#}),
+            json!({"payload": r#"{"name":"a"} x"#}),
+            json!({"payload": "{\n\"name\":\"a\"}"}),
+            json!({"payload": {"name": "a"}}),
+        ],
+    );
+}
+
+#[test]
+fn string_content_json_any() {
+    let schema = &json!({
+        "x-guidance": {"enforce_content": true},
+        "type": "string",
+        "contentMediaType": "application/json; charset=utf-8"
+    });
+    json_test_many(
+        schema,
+        &[json!("1"), json!("null"), json!(r#"["a",{"b":false}]"#)],
+        &[json!(""), json!("[1,"), json!("hello")],
+    );
+}
+
+#[test]
+fn string_content_errors() {
+    json_err_test(
+        &json!({
+            "x-guidance": {"enforce_content": true},
+            "type": "string",
+            "contentEncoding": "quoted-printable"
+        }),
+        "Unsupported contentEncoding: quoted-printable",
+    );
+    json_err_test(
+        &json!({
+            "x-guidance": {"enforce_content": true},
+            "type": "string",
+            "contentMediaType": "text/html"
+        }),
+        "Unsupported contentMediaType: text/html",
+    );
+    json_err_test(
+        &json!({
+            "x-guidance": {"enforce_content": true},
+            "type": "string",
+            "contentMediaType": "application/json",
+            "maxLength": 10
+        }),
+        "String constraints can't be combined with contentMediaType: application/json",
+    );
+}
@@ -369,3 +369,201 @@ fn allof_anyof_oneof_combined() {
         json_schema_check(schema, &value, expected_pass);
     }
 }
+
+lazy_static! {
+    static ref IF_THEN_ELSE_DISCRIMINATOR: Value = json!({
+        "type": "object",
+        "properties": {
+            "kind": {"enum": ["card", "bank"]},
+        },
+        "required": ["kind"],
+        "if": {"properties": {"kind": {"const": "card"}}},
+        "then": {
+            "properties": {"kind": true, "card_number": {"type": "string"}},
+            "required": ["card_number"],
+            "additionalProperties": false
+        },
+        "else": {
+            "properties": {"kind": true, "iban": {"type": "string"}},
+            "required": ["iban"],
+            "additionalProperties": false
+        }
+    });
+}
+
+#[rstest]
+#[case(&json!({"kind": "card", "card_number": "4111"}), true)]
+#[case(&json!({"kind": "bank", "iban": "DE89"}), true)]
+#[case(&json!({"kind": "card", "iban": "DE89"}), false)]
+#[case(&json!({"kind": "bank", "card_number": "4111"}), false)]
+#[case(&json!({"kind": "cash"}), false)]
+fn if_then_else_discriminator(#[case] value: &Value, #[case] expected_pass: bool) {
+    json_schema_check(&IF_THEN_ELSE_DISCRIMINATOR, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!("admin"), true)]
+#[case(&json!("guest"), false)]
+#[case(&json!(3), true)]
+#[case(&json!(-1), false)]
+#[case(&json!(null), true)]
+fn if_then_else_scalar(#[case] value: &Value, #[case] expected_pass: bool) {
+    // then applies to "admin" only, else to everything else
+    let schema = json!({
+        "if": {"const": "admin"},
+        "then": {"type": "string"},
+        "else": {"anyOf": [{"type": "integer", "minimum": 0}, {"type": "null"}]}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"a": 1}), true)]
+#[case(&json!({"a": "x"}), false)]
+#[case(&json!({"b": "x"}), true)]
+#[case(&json!({"b": 1}), false)]
+#[case(&json!("str"), true)]
+#[case(&json!(5), false)]
+fn if_then_else_required_and_type(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "if": {"type": "object", "required": ["a"]},
+        "then": {"properties": {"a": {"type": "integer"}}},
+        "else": {"anyOf": [
+            {"type": "object", "properties": {"b": {"type": "string"}}},
+            {"type": "string"}
+        ]}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(1), true)]
+#[case(&json!("x"), true)]
+#[case(&json!(true), false)]
+fn if_then_without_else(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": ["integer", "string", "boolean"],
+        "if": {"type": "boolean"},
+        "then": false
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(12), true)]
+#[case(&json!("abc"), true)]
+#[case(&json!(4), false)]
+#[case(&json!(true), false)]
+fn if_then_else_not_negatable(#[case] value: &Value, #[case] expected_pass: bool) {
+    // negation of multipleOf can't be expressed, so this is approximated
+    // as anyOf(allOf(if, then), else) with a warning
+    let schema = json!({
+        "if": {"type": "integer", "multipleOf": 2},
+        "then": {"minimum": 10},
+        "else": {"type": "string"}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!("alice"), true)]
+#[case(&json!("administrator"), true)]
+#[case(&json!("admin"), false)]
+#[case(&json!("root"), false)]
+#[case(&json!(1), false)]
+fn not_enum(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({"type": "string", "not": {"enum": ["admin", "root"]}});
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(1), true)]
+#[case(&json!(-0.5), true)]
+#[case(&json!("0"), true)]
+#[case(&json!(null), true)]
+#[case(&json!(0), false)]
+fn not_const(#[case] value: &Value, #[case] expected_pass: bool) {
+    json_schema_check(&json!({"not": {"const": 0}}), value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(1), true)]
+#[case(&json!(2.5), true)]
+#[case(&json!("x"), true)]
+#[case(&json!(2), false)]
+fn not_integer_const_float(#[case] value: &Value, #[case] expected_pass: bool) {
+    json_schema_check(
+        &json!({"not": {"type": "integer", "const": 2.0}}),
+        value,
+        expected_pass,
+    );
+}
+
+#[rstest]
+#[case(&json!(1), true)]
+#[case(&json!(true), true)]
+#[case(&json!("x"), false)]
+#[case(&json!(null), false)]
+fn not_type(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({"not": {"type": ["string", "null"]}});
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!("b"), true)]
+#[case(&json!("abc"), true)]
+#[case(&json!("ab"), false)]
+#[case(&json!("a"), false)]
+#[case(&json!(""), false)]
+fn not_string_constraints(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "string",
+        "minLength": 1,
+        "not": {"pattern": "^a", "maxLength": 2}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(""), true)]
+#[case(&json!("abcd"), true)]
+#[case(&json!("abcdefgh"), true)]
+#[case(&json!(1), false)]
+fn not_unsatisfiable_string(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "string",
+        "not": {"minLength": 5, "maxLength": 3}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!("x"), true)]
+#[case(&json!(1), false)]
+fn not_not(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({"not": {"not": {"type": "string"}}});
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(-1), true)]
+#[case(&json!(10), true)]
+#[case(&json!(100), true)]
+#[case(&json!(0), false)]
+#[case(&json!(5), false)]
+#[case(&json!(9.5), false)]
+fn not_number_range(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "number",
+        "not": {"minimum": 0, "exclusiveMaximum": 10}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[test]
+fn not_unsupported() {
+    json_err_test(
+        &json!({"type": "integer", "not": {"multipleOf": 3}}),
+        "Unable to negate schema {\"multipleOf\":3}",
+    );
+}
@@ -17,9 +17,9 @@
 // to the corresponding bad_* function.
 
 use rstest::*;
-use serde_json::json;
+use serde_json::{json, Value};
 
-use llg_test_utils::json_schema_check;
+use llg_test_utils::{json_schema_check, json_test_many};
 
 #[rstest]
 #[case("1963-06-19T08:30:06.283185Z")] // Test Suite: "a valid date-time string"
@@ -453,6 +453,241 @@ pub fn bad_uri(#[case] s: &str) {
     json_schema_check(&schema, &json!(s), false);
 }
 
+#[rstest]
+#[case("http://example.com/a?b#c")] // absolute URI
+#[case("//example.com/path")] // network-path reference
+#[case("/absolute/path")] // absolute-path reference
+#[case("relative/path")] // relative-path reference
+#[case("../up?q=1")] // dot segments and query
+#[case("#fragment")] // same-document reference
+#[case("")] // empty reference
+pub fn good_uri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-reference"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("\\\\WINDOWS\\fileshare")] // Test Suite: backslashes
+#[case("abc def")] // space
+#[case("#frag#ment")] // '#' in fragment
+#[case("a:b:c%")] // incomplete percent encoding
+pub fn bad_uri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-reference"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://ƒøø.ßår/?∂éœ=πîx#πîüx")] // Test Suite: "a valid IRI"
+#[case("http://example.com/päth")]
+#[case("urn:isbn:0451450523")]
+pub fn good_iri(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/abc")] // Test Suite: "an invalid relative IRI Reference"
+#[case("http://exa mple.com/")] // space
+pub fn bad_iri(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://ƒøø.ßår/?∂éœ=πîx#πîüx")]
+#[case("//ƒøø.ßår/?∂éœ=πîx#πîüx")] // Test Suite: "a valid protocol-relative IRI Reference"
+#[case("/âππ")] // Test Suite: "a valid relative IRI Reference"
+#[case("#ƒrägmênt")] // Test Suite: "a valid IRI fragment"
+pub fn good_iri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri-reference"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("\\\\WINDOWS\\filëßåré")] // Test Suite: "an invalid IRI Reference"
+#[case("#ƒräg\\mênt")] // Test Suite: "an invalid IRI fragment"
+pub fn bad_iri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri-reference"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://example.com/dictionary/{term:1}/{term}")] // Test Suite: "a valid uri-template"
+#[case("http://example.com/dictionary")] // Test Suite: "a valid uri-template without variables"
+#[case("dictionary/{term:1}/{term}")] // Test Suite: "a valid relative uri-template"
+#[case("{/path*}{?x,y}{#frag}")] // RFC 6570 level 4 operators
+pub fn good_uri_template(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-template"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("http://example.com/dictionary/{term:1}/{term")] // Test Suite: "an invalid uri-template"
+#[case("{}")] // empty expression
+#[case("{a b}")] // space in variable name
+pub fn bad_uri_template(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-template"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("실례@실례.테스트")] // Test Suite: "a valid idn e-mail (example@example.test in Hangul)"
+#[case("joe.bloggs@example.com")] // Test Suite: "a valid e-mail address"
+pub fn good_idn_email(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-email"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("2962")] // Test Suite: "an invalid idn e-mail address"
+#[case("실례@@실례.테스트")]
+pub fn bad_idn_email(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-email"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("")] // Test Suite: "valid JSON-pointer as stated in RFC 6901"
+#[case("/foo/bar~0/baz~1/%a")] // Test Suite: "a valid JSON-pointer"
+#[case("/foo/0")]
+#[case("/ ")]
+#[case("/c%d")]
+#[case("/i\\j")]
+#[case("/m~0n")]
+pub fn good_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"json-pointer"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/foo/bar~")] // Test Suite: "not a valid JSON-pointer (~ not escaped)"
+#[case("#")] // Test Suite: "not a valid JSON-pointer (URI Fragment Identifier)"
+#[case("a")] // Test Suite: "not a valid JSON-pointer (isn't empty nor starts with /)"
+#[case("/~-1")] // Test Suite: "not a valid JSON-pointer (some escaped, but not all)"
+pub fn bad_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"json-pointer"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("1")] // Test Suite: "a valid upwards RJP"
+#[case("0/foo/bar")] // Test Suite: "a valid downwards RJP"
+#[case("2/0/baz/1/zip")] // Test Suite: "a valid up and then down RJP, with array index"
+#[case("0#")] // Test Suite: "a valid RJP taking the member or index name"
+#[case("120/foo/bar")] // Test Suite: "multi-digit integer prefix"
+pub fn good_relative_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"relative-json-pointer"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/foo/bar")] // Test Suite: "an invalid RJP that is a valid JSON Pointer"
+#[case("-1/foo/bar")] // Test Suite: "negative prefix"
+#[case("0##")] // Test Suite: "## is not a valid json-pointer"
+#[case("01/a")] // Test Suite: "zero cannot be followed by other digits, plus json-pointer"
+#[case("")] // Test Suite: "empty string"
+pub fn bad_relative_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"relative-json-pointer"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("([abc])+\\s+$")] // Test Suite: "a valid regular expression"
+#[case("^(?:a|b)*?c{2,3}(?<name>d)(?=e)[^\\]]$")]
+#[case("a||b")]
+#[case("((a)(b(c)))")] // nested groups
+#[case("")]
+pub fn good_regex(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"regex"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("^(abc]")] // Test Suite: "a regular expression with unclosed parens is invalid"
+#[case("abc)")]
+#[case("*a")] // nothing to repeat
+#[case("a**")]
+#[case("[abc")] // unclosed class
+pub fn bad_regex(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"regex"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("byte", "U3dhZ2dlciByb2Nrcw==")]
+#[case("byte", "")]
+#[case("password", "hunter2\n")]
+#[case("binary", "\u{0}\u{ff}")]
+#[case("int32", "-2147483648")]
+#[case("int32", "2147483647")]
+#[case("int64", "-9223372036854775808")]
+#[case("int64", "9223372036854775807")]
+#[case("float", "1.5e10")]
+#[case("double", "-0.25")]
+pub fn good_openapi_string(#[case] format: &str, #[case] s: &str) {
+    let schema = json!({"type":"string", "format":format});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("byte", "U3dhZ2dlciByb2Nrcw=")]
+#[case("byte", "hello world")]
+#[case("int32", "2147483648")]
+#[case("int32", "1.5")]
+#[case("int64", "9223372036854775808")]
+#[case("int64", "-9223372036854775809")]
+#[case("double", "abc")]
+pub fn bad_openapi_string(#[case] format: &str, #[case] s: &str) {
+    let schema = json!({"type":"string", "format":format});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("int32", json!(-2147483648i64), true)]
+#[case("int32", json!(2147483647), true)]
+#[case("int32", json!(2147483648i64), false)]
+#[case("int32", json!(-2147483649i64), false)]
+#[case("int64", json!(i64::MIN), true)]
+#[case("int64", json!(i64::MAX), true)]
+#[case("int64", json!(9223372036854775808u64), false)]
+#[case("float", json!(1.5), true)]
+#[case("double", json!(1e300), true)]
+#[case("unknown-numeric", json!(1e300), true)]
+pub fn numeric_formats(#[case] format: &str, #[case] value: Value, #[case] expected: bool) {
+    let schema = json!({"type":"number", "format":format});
+    json_schema_check(&schema, &value, expected);
+}
+
+#[test]
+pub fn numeric_format_implies_integer() {
+    let schema = json!({"type":"number", "format":"int32"});
+    json_schema_check(&schema, &json!(1.5), false);
+    let schema = json!({"type":["number", "string"], "format":"int32", "maximum": 10});
+    json_test_many(&schema, &[json!(10), json!("12")], &[json!(11), json!("a")]);
+}
+
+#[test]
+pub fn custom_formats() {
+    let schema = json!({
+        "x-guidance": {"custom_formats": {"sku": "[A-Z]{3}-\\d{4}", "uuid": "[0-9a-f]{4}"}},
+        "type": "object",
+        "properties": {
+            "sku": {"type": "string", "format": "sku"},
+            "id": {"type": "string", "format": "uuid"}
+        }
+    });
+    json_test_many(
+        &schema,
+        &[json!({"sku": "ABC-1234", "id": "12ab"})],
+        &[
+            json!({"sku": "ABC-123"}),
+            json!({"sku": "xABC-1234"}),
+            json!({"sku": "ABC-١٢٣٤"}),
+            json!({"id": "12345678-1234-1234-1234-123456789abc"}),
+        ],
+    );
+}
+
 // ===== Accepted-but-invalid tests =====
 //
 // These values are INVALID per the relevant RFC but ACCEPTED by the current regex.
@@ -1,4 +1,13 @@
-use llg_test_utils::lark_str_test;
+use std::{ffi::CString, sync::Arc};
+
+use llg_test_utils::{
+    get_tok_env, json_err_test, json_err_test_ext, json_test_many_ext, lark_str_test,
+};
+use llguidance::{
+    api::{ParserLimits, TopLevelGrammar},
+    ffi::{llg_free_tokenizer, llg_new_tokenizer, LlgConstraintInit, LlgTokenizerInit},
+    DirRetriever, MapRetriever, Matcher, RetrieveWrapper,
+};
 use rstest::rstest;
 use serde_json::json;
 use serde_json_fmt::JsonFormat;
@@ -364,3 +373,180 @@ fn whitespace_flexible_many_formats(
     );
     lark_str_test(&lark, true, &target_str, true);
 }
+
+fn components_retriever() -> Option<RetrieveWrapper> {
+    Some(RetrieveWrapper::new(Arc::new(MapRetriever::new([(
+        "https://example.com/components.json".to_string(),
+        json!({
+            "$defs": {
+                "id": {"type": "integer", "minimum": 1},
+                "user": {
+                    "type": "object",
+                    "properties": {"id": {"$ref": "#/$defs/id"}},
+                    "required": ["id"],
+                    "additionalProperties": false
+                }
+            }
+        }),
+    )]))))
+}
+
+#[test]
+fn test_schema_bundle() {
+    let schema = json!({
+        "type": "array",
+        "items": {"$ref": "https://example.com/components.json#/$defs/user"}
+    });
+    json_test_many_ext(
+        &schema,
+        components_retriever(),
+        &[json!([]), json!([{"id": 1}, {"id": 7}])],
+        &[json!([{"id": 0}]), json!([{"name": "x"}])],
+    );
+
+    // the retriever is also used with x-guidance
+    let schema = json!({
+        "x-guidance": {"whitespace_flexible": false},
+        "$ref": "https://example.com/components.json#/$defs/user"
+    });
+    json_test_many_ext(
+        &schema,
+        components_retriever(),
+        &[json!({"id": 1})],
+        &[json!({"id": 0})],
+    );
+}
+
+#[test]
+fn test_schema_bundle_missing() {
+    json_err_test_ext(
+        &json!({"$ref": "https://example.com/b.json"}),
+        components_retriever(),
+        "schema not found in bundle: https://example.com/b.json",
+    );
+}
+
+#[test]
+fn test_schema_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("common")).unwrap();
+    std::fs::write(
+        dir.path().join("common/name.json"),
+        r#"{"type": "string", "maxLength": 3}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("person.json"),
+        r#"{"type": "object", "properties": {"name": {"$ref": "common/name.json"}}, "required": ["name"]}"#,
+    )
+    .unwrap();
+    let retriever = || {
+        Some(RetrieveWrapper::new(Arc::new(DirRetriever::new(
+            dir.path(),
+        ))))
+    };
+
+    json_test_many_ext(
+        &json!({"$ref": "https://example.com/person.json"}),
+        retriever(),
+        &[json!({"name": "Bob"})],
+        &[json!({"name": "Alice"}), json!({})],
+    );
+    json_err_test_ext(
+        &json!({"$ref": "https://example.com/missing.json"}),
+        retriever(),
+        "can't read schema",
+    );
+}
+
+#[test]
+fn test_schema_refs_not_from_x_guidance() {
+    // schemas can't make the host read files
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.json"), r#"{"type": "string"}"#).unwrap();
+    json_err_test(
+        &json!({
+            "x-guidance": {"schema_dir": dir.path().to_str().unwrap()},
+            "$ref": "https://example.com/a.json"
+        }),
+        "unknown field `schema_dir`",
+    );
+    json_err_test(
+        &json!({
+            "x-guidance": {"schema_bundle": {"https://example.com/a.json": {"type": "string"}}},
+            "$ref": "https://example.com/a.json"
+        }),
+        "unknown field `schema_bundle`",
+    );
+}
+
+#[test]
+fn test_schema_refs_ffi() {
+    let trie = get_tok_env().tok_trie();
+    let tokens = (0..trie.vocab_size() as u32)
+        .map(|t| trie.token(t).to_vec())
+        .collect::<Vec<_>>();
+    let token_lens = tokens.iter().map(|t| t.len() as u32).collect::<Vec<_>>();
+    let token_bytes = tokens.concat();
+    let tok_init = LlgTokenizerInit {
+        vocab_size: tokens.len() as u32,
+        tok_eos: trie.eos_token(),
+        token_lens: token_lens.as_ptr(),
+        token_bytes: token_bytes.as_ptr(),
+        tokenizer_json: std::ptr::null(),
+        tokenize_assumes_string: false,
+        tokenize_fn: None,
+        use_approximate_greedy_tokenize_fn: true,
+        tokenize_user_data: std::ptr::null(),
+        slices: std::ptr::null(),
+    };
+    let tokenizer = unsafe { llg_new_tokenizer(&tok_init, std::ptr::null_mut(), 0) };
+    assert!(!tokenizer.is_null());
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("name.json"),
+        r#"{"type": "string", "maxLength": 3}"#,
+    )
+    .unwrap();
+    let dir_str = CString::new(dir.path().to_str().unwrap()).unwrap();
+    let bundle = CString::new(
+        json!({"https://example.com/id.json": {"type": "integer", "minimum": 1}}).to_string(),
+    )
+    .unwrap();
+    let init = LlgConstraintInit {
+        tokenizer,
+        log_buffer_level: 0,
+        log_stderr_level: 0,
+        ff_tokens_ok: false,
+        backtrack_ok: false,
+        limits: ParserLimits::default(),
+        json_schema_dir: dir_str.as_ptr(),
+        json_schema_bundle: bundle.as_ptr(),
+    };
+
+    // %json in Lark gets the retriever too
+    let grammar = TopLevelGrammar::from_lark(
+        r#"
+            start: "name=" name " id=" id
+            name: %json { "$ref": "https://example.com/name.json" }
+            id: %json { "$ref": "https://example.com/id.json" }
+        "#
+        .to_string(),
+    );
+    let accepts = |s: &str| {
+        let mut m = Matcher::new(init.build_parser(grammar.clone()));
+        m.consume_tokens(&get_tok_env().tokenize(s)).is_ok() && m.is_accepting().unwrap()
+    };
+    assert!(accepts(r#"name="Bob" id=7"#));
+    assert!(!accepts(r#"name="Alice" id=7"#));
+    assert!(!accepts(r#"name="Bob" id=0"#));
+
+    // and so do top-level JSON schemas
+    let grammar = TopLevelGrammar::from_json_schema(json!({"$ref": "https://example.com/id.json"}));
+    let mut m = Matcher::new(init.build_parser(grammar));
+    m.consume_tokens(&get_tok_env().tokenize("12")).unwrap();
+    assert!(m.is_accepting().unwrap());
+
+    unsafe { llg_free_tokenizer(tokenizer) };
+}
@@ -1,4 +1,6 @@
-use llguidance::substring::chunk_into_words;
+use std::sync::Arc;
+
+use llguidance::{substring::chunk_into_words, DirImportResolver, MapImportResolver};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use serde_json::json;
 
@@ -235,7 +237,7 @@ fn test_lark_syntax_perc() {
     lark_err_test(r#"start: %json { foo"#, "key must be a string");
     lark_err_test(r#"start: %json []"#, "failed to compile JSON schema");
     lark_err_test(
-        r#"start: %json { "if": {} }"#,
+        r#"start: %json { "unevaluatedProperties": false }"#,
         "failed to compile JSON schema",
     );
 
@@ -445,6 +447,178 @@ fn test_lexeme_substring_words_ascii() {
     );
 }
 
+#[test]
+fn test_lexeme_numeric() {
+    lark_str_test_many(
+        r#"start: PORT
+           PORT: %regex { "type": "integer", "minimum": 1, "maximum": 65535 }"#,
+        &["1", "80", "8080", "65535"],
+        &["0", "-1", "65536", "70000", "1.0"],
+    );
+
+    lark_str_test_many(
+        r#"start: %regex { "type": "number", "minimum": -17.3, "maximum": 33.721 }"#,
+        &["-17.3", "0", "1.5", "33.721", "-0.25"],
+        &["-17.4", "33.7211", "40"],
+    );
+
+    lark_str_test_many(
+        r#"start: %regex { "type": "integer", "exclusiveMinimum": 0, "multipleOf": 10 }"#,
+        &["10", "20", "1230"],
+        &["0", "-10", "FINAL_REJECT:5", "FINAL_REJECT:15"],
+    );
+
+    lark_str_test_many(
+        r#"start: "x=" NUM ";"
+           NUM: %regex { "type": "number", "exclusiveMaximum": 1 }"#,
+        &["x=0.5;", "x=-3;"],
+        &["x=1;", "x=1.5;"],
+    );
+
+    lark_err_test(
+        r#"start: %regex { "type": "integer", "minimum": 10, "maximum": 1 }"#,
+        "minimum (10) is greater than maximum (1)",
+    );
+    lark_err_test(
+        r#"start: %regex { "minimum": 10 }"#,
+        "[\"minimum\"] on %regex require \"type\" to be set",
+    );
+    lark_err_test(
+        r#"start: %regex { "type": "boolean" }"#,
+        "unsupported %regex type: \"boolean\"",
+    );
+    lark_err_test(
+        r#"start: %regex { "type": "integer", "substring_chars": "abc" }"#,
+        "only one field can be set on %regex",
+    );
+    lark_err_test(
+        r#"start: %regex { "type": "integer", "minimum": "1" }"#,
+        "failed to parse %regex",
+    );
+}
+
+#[test]
+fn test_lexeme_string_format() {
+    lark_str_test_many(
+        r#"start: "d=" DATE
+           DATE: %regex { "format": "date" }"#,
+        &["d=2024-02-29", "d=1999-12-31"],
+        &["d=2024-13-01", "d=24-01-01", "d=2024-01-01x"],
+    );
+
+    lark_str_test_many(
+        r#"start: %regex { "type": "string", "format": "uuid" }"#,
+        &["123e4567-e89b-12d3-a456-426614174000"],
+        &["FINAL_REJECT:123e4567-e89b-12d3-a456", "123e4567_e89b"],
+    );
+
+    lark_str_test_many(
+        r#"start: %regex { "format": "email", "maxLength": 8 }"#,
+        &["a@b.com", "ab@c.com"],
+        &["abc@d.com", "a b@c.com"],
+    );
+
+    lark_str_test_many(
+        r#"start: %regex { "pattern": "^[a-z]+$", "minLength": 2, "maxLength": 4 }"#,
+        &["ab", "abcd"],
+        &["FINAL_REJECT:a", "abcde", "aB"],
+    );
+
+    // strings are matched raw, without JSON quoting or escaping
+    lark_str_test_many(
+        r#"start: %regex { "minLength": 1, "maxLength": 3 }"#,
+        &["a\"b", "\n"],
+        &["FINAL_REJECT:", "abcd"],
+    );
+
+    lark_err_test(
+        r#"start: %regex { "format": "foobar" }"#,
+        "Unknown format: foobar",
+    );
+    lark_err_test(
+        r#"start: %regex { "minLength": 5, "maxLength": 2 }"#,
+        "minLength (5) is greater than maxLength (2)",
+    );
+    lark_err_test(
+        r#"start: %regex { "pattern": "^a+$", "maxLength": 0 }"#,
+        "Regex is empty",
+    );
+    lark_err_test(
+        r#"start: %regex { "format": "date", "substring_words": "a b" }"#,
+        "only one field can be set on %regex",
+    );
+}
+
+#[test]
+fn test_lexeme_lookahead() {
+    lark_str_test_many(
+        r#"start: PASSWORD
+           PASSWORD: /(?=.*\d)(?=.*[a-z])[a-z0-9]{6,}/"#,
+        &["abc123", "1a2b3c4d"],
+        &[
+            "FINAL_REJECT:abcdef",
+            "FINAL_REJECT:123456",
+            "FINAL_REJECT:ab1",
+            "ab1-",
+        ],
+    );
+
+    lark_str_test_many(
+        r#"start: ID
+           ID: /(?!__)[a-z_]+/"#,
+        &["foo", "_foo", "foo__bar", "_"],
+        &["__foo", "__"],
+    );
+
+    // lookahead only applies to the first alternative; flags apply to all
+    lark_str_test_many(
+        r#"start: /(?=a)[a-z]+|[0-9]+/i"#,
+        &["abc", "ABC", "123"],
+        &["bc", "a1"],
+    );
+
+    lark_err_test(
+        r#"start: /a(?=b)b/"#,
+        "lookahead ((?=...) or (?!...)) is only supported at the start of a regex",
+    );
+    lark_err_test(
+        r#"start: /(?<!a)b/"#,
+        "lookbehind ((?<=...) or (?<!...)) is not supported",
+    );
+    lark_err_test(
+        r#"start: /(?=(?!a)b)b/"#,
+        "nested lookarounds are not supported",
+    );
+}
+
+#[test]
+fn test_lexeme_lazy_quantifier() {
+    lark_str_test_many(
+        r#"start: COMMENT*
+           COMMENT: /<!--.*?-->/"#,
+        &[
+            "<!-- a -->",
+            "<!---->",
+            "<!-- a --><!-- b -->",
+            "<!-- a -- b -->",
+        ],
+        &["<!-- a --> b -->", "FINAL_REJECT:<!-- a"],
+    );
+
+    lark_str_test_many(
+        r#"start: STR ("," STR)*
+           STR: /".*?"/"#,
+        &[r#""a","b""#, r#""""#],
+        &[r#""a"b""#],
+    );
+
+    lark_str_test_many(
+        r#"start: /(?i)x[a-z]+?y/ "z""#,
+        &["xayz", "XAbYz", "xaYz"],
+        &["xyz", "xayyz"],
+    );
+}
+
 #[test]
 fn test_lexeme_substring_words_unicode() {
     lark_str_test_many(
@@ -1860,3 +2034,573 @@ fn test_parametric_long() {
         &[&s2],
     );
 }
+
+fn lark_modules(modules: &[(&str, &str)]) -> Arc<MapImportResolver> {
+    Arc::new(MapImportResolver::new(
+        modules
+            .iter()
+            .map(|(m, src)| (m.to_string(), src.to_string())),
+    ))
+}
+
+#[test]
+fn test_lark_import_module() {
+    let modules = lark_modules(&[
+        (
+            "lib.list",
+            r#"
+                list: "[" (item ("," item)*)? "]"
+                item: NUMBER | list
+                NUMBER: DIGIT+
+                DIGIT: /[0-9]/
+                _sep: ","
+            "#,
+        ),
+        (
+            "lib.kv",
+            r#"
+                %import .list (list, NUMBER)
+                pair: KEY "=" value
+                value: list | NUMBER
+                KEY: /[a-z]+/
+            "#,
+        ),
+    ]);
+
+    lark_import_test_many(
+        r#"
+            %import lib.list (list)
+            start: list
+        "#,
+        modules.clone(),
+        &["[]", "[1,[2,3],[]]"],
+        &["[a]", "[1,]"],
+    );
+
+    // alias; the dependencies of the imported rule don't clash with local names
+    lark_import_test_many(
+        r#"
+            %import lib.list.list -> numbers
+            start: numbers "/" item
+            item: "x"
+        "#,
+        modules.clone(),
+        &["[1,2]/x"],
+        &["[1,2]/1"],
+    );
+
+    // tokens, nested and relative imports, two imports from the same module
+    lark_import_test_many(
+        r#"
+            %import lib.kv (pair, KEY)
+            %import lib.kv.value
+            start: pair | KEY ":" value
+        "#,
+        modules.clone(),
+        &["a=[1,2]", "ab=7", "x:[]"],
+        &["A=1", "FINAL_REJECT:a=", "x=:1"],
+    );
+
+    lark_err_test_ext(
+        r#"
+            %import lib.list (lst)
+            start: lst
+        "#,
+        Some(modules.clone()),
+        r#""lst" not found in module "lib.list""#,
+    );
+    lark_err_test_ext(
+        r#"
+            %import lib.list (list)
+            start: list
+            list: "x"
+        "#,
+        Some(modules.clone()),
+        r#"duplicate rule: "list""#,
+    );
+    lark_err_test_ext(
+        r#"
+            %import lib.missing (list)
+            start: list
+        "#,
+        Some(modules.clone()),
+        r#"can't import "lib.missing": module "lib.missing" not found"#,
+    );
+    lark_err_test(
+        r#"
+            %import lib.list (list)
+            start: list
+        "#,
+        "no import resolver set",
+    );
+}
+
+#[test]
+fn test_lark_import_errors() {
+    let modules = lark_modules(&[
+        ("a", "%import b (y)\nx: \"x\" y?\n"),
+        ("b", "%import a (x)\ny: \"y\" x?\n"),
+        ("bad", "r: \"x\" undefined\n"),
+        ("syntax", "r: (\n"),
+    ]);
+    lark_err_test_ext(
+        r#"
+            %import a (x)
+            start: x
+        "#,
+        Some(modules.clone()),
+        "import cycle: a -> b -> a",
+    );
+    lark_err_test_ext(
+        r#"
+            %import bad (r)
+            start: r
+        "#,
+        Some(modules.clone()),
+        r#"unknown name: "undefined" (in module "bad")"#,
+    );
+    lark_err_test_ext(
+        r#"
+            %import syntax (r)
+            start: r
+        "#,
+        Some(modules.clone()),
+        r#"in module "syntax""#,
+    );
+}
+
+#[test]
+fn test_lark_import_dir() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("shared")).unwrap();
+    std::fs::write(
+        dir.join("shared/words.lark"),
+        r#"
+            %import common.WS
+            words: WORD (_WS WORD)*
+            WORD: /[a-z]+/
+            _WS: WS
+        "#,
+    )
+    .unwrap();
+    let resolver = Arc::new(DirImportResolver::new([
+        dir.join("missing"),
+        dir.to_path_buf(),
+    ]));
+
+    lark_import_test_many(
+        r#"
+            %import shared.words (words)
+            start: words "."
+        "#,
+        resolver.clone(),
+        &["hello world.", "a b  c."],
+        &["Hello.", "FINAL_REJECT:a b"],
+    );
+    lark_err_test_ext(
+        r#"
+            %import shared.sentences (s)
+            start: s
+        "#,
+        Some(resolver),
+        "shared/sentences.lark",
+    );
+}
+
+#[test]
+fn test_lark_override() {
+    lark_str_test_many(
+        r#"
+            start: item ("," item)*
+            item: WORD
+            WORD: /[a-z]+/
+            %override item: WORD | NUMBER
+            %override WORD: /[a-c]+/
+            NUMBER: /[0-9]+/
+        "#,
+        &["abc,12,a"],
+        &["abd", "A"],
+    );
+
+    // override tokens imported from common
+    lark_str_test_many(
+        r#"
+            %import common.INT
+            %override INT: /[0-7]+/
+            start: INT
+        "#,
+        &["017"],
+        &["018"],
+    );
+
+    // override a rule that an imported rule depends on
+    let modules = lark_modules(&[(
+        "lib.list",
+        r#"
+            list: "[" (item ("," item)*)? "]"
+            item: NUMBER | list
+            NUMBER: /[0-9]+/
+        "#,
+    )]);
+    lark_import_test_many(
+        r#"
+            %import lib.list (list, item)
+            %override item: "x" | list
+            start: list
+        "#,
+        modules.clone(),
+        &["[x,[x]]"],
+        &["[1]"],
+    );
+
+    lark_err_test(
+        r#"
+            start: "a"
+            %override item: "b"
+        "#,
+        r#"cannot override a nonexisting rule: "item""#,
+    );
+    lark_err_test(
+        r#"
+            start: "a"
+            %override FOO: "b"
+        "#,
+        r#"cannot override a nonexisting token: "FOO""#,
+    );
+    lark_err_test(
+        r#"
+            start: "a"
+
+            %override FOO: "b"
+        "#,
+        "at 4(13)",
+    );
+}
+
+#[test]
+fn test_lark_declare() {
+    lark_str_test_many(
+        r#"
+            %declare _INDENT _DEDENT
+            start: "a" (_INDENT "b" _DEDENT)? "c"
+        "#,
+        &["ac"],
+        &["abc"],
+    );
+    lark_str_test_many(
+        r#"
+            %declare KEYWORD
+            %override KEYWORD: "if" | "else"
+            start: KEYWORD+
+        "#,
+        &["ifelse"],
+        &["then"],
+    );
+    lark_err_test(
+        r#"
+            %declare FOO
+            start: FOO
+            FOO: "a"
+        "#,
+        r#""FOO" already declared"#,
+    );
+    lark_err_test(
+        r#"
+            start: FOO
+            FOO: "a"
+            %declare FOO
+        "#,
+        r#"duplicate token: "FOO""#,
+    );
+}
+
+#[test]
+fn test_lark_token_priority() {
+    // without priorities, "if" is both IF and NAME
+    lark_str_test_many(
+        r#"
+            start: (IF | NAME) (IF | NAME)
+            IF: "if"
+            NAME: /[a-z]+/
+            %ignore " "
+        "#,
+        &["if x", "x if", "x y"],
+        &[],
+    );
+    lark_str_test_many(
+        r#"
+            start: stmt+
+            stmt: IF NAME | NAME "=" NAME
+            IF.2: "if"
+            NAME: /[a-z]+/
+            %ignore " "
+        "#,
+        // the lexer is contextual: where IF is not allowed, "if" is a NAME
+        &["if x", "x = y if z", "iff = x", "x = if"],
+        &["if = x", "x = y if = z"],
+    );
+    // negative priority: "let" is lexed as NAME where both are allowed,
+    // and as LET only where NAME is not
+    lark_str_test_many(
+        r#"
+            start: stmt+
+            stmt: NAME "=" NUM | LET NAME | "(" LET ")"
+            NAME: /[a-z]+/
+            LET.-1: "let"
+            NUM: /[0-9]+/
+            %ignore " "
+        "#,
+        &["let = 1", "x = 1 (let)", "(let) let = 2"],
+        &["let x", "x = 1 let y"],
+    );
+}
+
+#[test]
+fn test_lark_rule_priority() {
+    // rule priorities don't change the accepted strings
+    lark_str_test_many(
+        r#"
+            start: a | b
+            a.2: "x" "y"?
+            b: "x"
+        "#,
+        &["x", "xy"],
+        &["y"],
+    );
+}
+
+#[test]
+fn test_lark_templates() {
+    lark_str_test_many(
+        r#"
+            start: "[" _separated{item, ","}? "]" ";" _separated{WORD, _semi}
+            _separated{x, sep}: x (sep x)*
+            _semi: ";"
+            item: NUMBER | "[" _separated{item, ","}? "]"
+            NUMBER: /[0-9]+/
+            WORD: /[a-z]+/
+        "#,
+        &["[1,[2,[]],3];a;b", "[];a"],
+        &["[1;2];a", "[1];a,b", "FINAL_REJECT:[1];"],
+    );
+
+    // recursive templates, template arguments
+    lark_str_test_many(
+        r#"
+            start: list{pair{"a", "b"}}
+            list{x}: x | x "," list{x}
+            pair{k, v}: k "=" v
+        "#,
+        &["a=b", "a=b,a=b,a=b"],
+        &["a=a", "b=b"],
+    );
+
+    // templates can be imported
+    let modules = lark_modules(&[(
+        "lib.util",
+        r#"
+            _separated{x, sep}: x (sep x)* _end?
+            _end: "."
+        "#,
+    )]);
+    lark_import_test_many(
+        r#"
+            %import lib.util (_separated)
+            start: _separated{"a", "-"}
+        "#,
+        modules,
+        &["a-a", "a."],
+        &["a--a"],
+    );
+
+    // parameters can be used in stop= and suffix=
+    lark_str_test_many(
+        r#"
+            start: tail{";"} " " tail{END}
+            tail{end}[suffix=end]: /[a-z]*/
+            END: "."
+        "#,
+        &["abc; x.", "; ."],
+        &["abc. x;", "a; b; c."],
+    );
+    lark_ok(
+        r#"
+            start: quoted{"'"} quoted{QQ}
+            quoted{q}[stop=q]: /[a-z]*/
+            QQ: "\""
+        "#,
+    );
+    // but, like in any stop=, only terminals can be used there
+    lark_err_test(
+        r#"
+            start: quoted{q}
+            quoted{x}[stop=x]: /[a-z]*/
+            q: "'"
+        "#,
+        r#"unknown name: "q""#,
+    );
+
+    lark_err_test(
+        r#"
+            start: pair{"a"}
+            pair{k, v}: k "=" v
+        "#,
+        r#"template "pair" expects 2 argument(s), got 1"#,
+    );
+    lark_err_test(
+        r#"
+            start: pair
+            pair{k, v}: k "=" v
+        "#,
+        r#"template "pair" used without arguments"#,
+    );
+    lark_err_test(
+        r#"
+            start: foo{"a"}
+            foo: "x"
+        "#,
+        r#""foo" is not a template"#,
+    );
+    lark_err_test(
+        r#"
+            start: foo{"a"}
+        "#,
+        r#"unknown template: "foo""#,
+    );
+    lark_err_test(
+        r#"
+            start: grow{"a"}
+            grow{x}: x | grow{wrap{x}}
+            wrap{x}: "(" x ")"
+        "#,
+        "template instantiation too deep",
+    );
+    lark_err_test(
+        r#"
+            start: FOO{"a"}
+            FOO{X}: X
+        "#,
+        "templates are only supported for rules",
+    );
+}
+
+#[test]
+fn test_lark_python_compat() {
+    let compat = r#"%llguidance { "python_compat": true }"#;
+
+    lark_str_test_many(
+        &format!(
+            r#"{compat}
+               start: "\x41\101" "\N{{EM DASH}}" "\'" r"\d" "\q" "é\U0001F600""#
+        ),
+        &["AA—'\\d\\qé😀"],
+        &["AA-'\\d\\qé😀", "AA—'d"],
+    );
+
+    lark_str_test_many(
+        &format!(
+            r#"{compat}
+               start: NUM "," WORD "," ALPHA "," BRACES
+               NUM: /(?x) [0-9]+ (\. [0-9]*)?  # a number/
+               WORD: /(?a)\w{{,3}}\Z/
+               ALPHA: /[\N{{GREEK SMALL LETTER ALPHA}}-\N{{GREEK SMALL LETTER GAMMA}}[]+/
+               BRACES: /a{{b}}(?#comment)/"#
+        ),
+        &["12.5,ab,αβ[γ,a{b}", "1,,α,a{b}"],
+        &["1 2,a,α,a{b}", "1,abcd,α,a{b}", "1,é,α,a{b}", "1,a,δ,a{b}"],
+    );
+
+    // modules inherit python_compat
+    lark_import_test_many(
+        &format!(
+            r#"{compat}
+               %import lib.hex (HEX)
+               start: HEX"#
+        ),
+        lark_modules(&[("lib.hex", r#"HEX: "\x30x" /[0-9a-f]{,4}/"#)]),
+        &["0x", "0xbeef"],
+        &["0xbeef0", "\\x30x"],
+    );
+
+    lark_err_test(
+        &format!(
+            r#"{compat}
+               start: /(a)\1/"#
+        ),
+        "backreferences (like \\1) are not supported",
+    );
+    lark_err_test(
+        &format!(
+            r#"{compat}
+               start: /(?P<x>a)(?P=x)/"#
+        ),
+        "backreferences (like (?P=...)) are not supported",
+    );
+    // any Unicode name or alias
+    lark_str_test_many(
+        &format!(
+            r#"{compat}
+               start: "\N{{snowman}}\N{{LINE FEED}}" /[\N{{CYRILLIC SMALL LETTER A}}\N{{BOX DRAWINGS LIGHT HORIZONTAL}}]+/"#
+        ),
+        &["☃\nа─", "☃\n──"],
+        &["☃\na", "☃─"],
+    );
+    lark_err_test(
+        &format!(
+            r#"{compat}
+               start: "\N{{NO SUCH CHARACTER}}""#
+        ),
+        "unknown character name: \\N{NO SUCH CHARACTER}",
+    );
+
+    // without python_compat, JSON string syntax is used
+    lark_err_test(r#"start: "\x41""#, "error parsing string");
+    lark_err_test(
+        r#"start: r"\d""#,
+        "string prefixes are only supported with python_compat",
+    );
+}
+
+#[test]
+fn test_lark_case_insensitive() {
+    // i-flag on strings works the same in rules and terminals
+    lark_str_test_many(
+        r#"start: "select"i " " NAME " " FROM
+           FROM: "from"i
+           NAME: /[a-z]+/"#,
+        &["select x from", "SeLeCt x FROM", "SELECT x From"],
+        &["SELECTx from", "select X from", "selekt x from"],
+    );
+
+    lark_str_test_many(
+        r#"start: header ": " /[^\n]*/
+           header: "content-type"i | "accept"i"#,
+        &["Content-Type: text/html", "ACCEPT: */*", "content-type: "],
+        &["Content_Type: x"],
+    );
+
+    // Unicode case folding
+    lark_str_test_many(
+        r#"start: KW
+           KW: "straße"i | "σίσυφος"i"#,
+        &["Straße", "STRAẞE", "ΣΊΣΥΦΟΣ", "Σίσυφος"],
+        &["STRASSE", "ΣΙΣΥΦΟΣ"],
+    );
+
+    // ranges
+    lark_str_test_many(
+        r#"start: ("a".."f"i)+"#,
+        &["abc", "ABC", "aBcDeF"],
+        &["g", "G"],
+    );
+    lark_err_test(r#"start: "a"i.."f""#, "flags not allowed in this context");
+
+    // regex flags
+    lark_str_test_many(
+        r#"start: /sel(ect)?/i " " /a . b/sx /^foo$/m"#,
+        &["SEL a\nbfoo", "Select a bfoo", "sel axbfoo"],
+        &["SEL axbFOO", "sel a  bfoo"],
+    );
+    lark_err_test(r#"start: /a/l"#, "l-flag is not supported in regexes");
+}
@@ -2,6 +2,8 @@ from typing import List, Tuple, Mapping, Optional, Sequence, Union, TypedDict, D
 from ._util import TokenId, StopReason
 from ._tokenizer import TokenizerWrapper
 
+CaptureNode = Tuple[str, int, int, List[Any]]
+
 
 class LLTokenizer:
     vocab_size: int
@@ -171,6 +173,23 @@ class LLInterpreter:
             "InternalError" - Something went wrong with creating a nested parser.
         """
 
+    def get_capture_tree(self) -> List[CaptureNode]:
+        """
+        Get all captures as a tree of (name, start, end, children) tuples.
+        start and end are byte offsets into get_final_bytes();
+        children are the captures nested inside the given one.
+        """
+
+    def get_final_bytes(self) -> bytes:
+        """
+        Get the bytes generated so far (excluding the prompt).
+        """
+
+    def get_parse_tree(self, named_only: bool = False) -> str:
+        """
+        Get the parse tree of the output so far as JSON; see LLMatcher.get_parse_tree().
+        """
+
     def process_prompt(self, prompt: List[TokenId]) -> List[TokenId]:
         """
         Perform any adjustments to the prompt before completion.
@@ -210,11 +229,14 @@ class LLInterpreter:
 
     def commit_token(
             self,
-            sampled_token: Optional[TokenId]) -> Tuple[int, List[TokenId]]:
+            sampled_token: Optional[TokenId],
+            logprob: Optional[float] = None) -> Tuple[int, List[TokenId]]:
         """
         Perform any adjustments to the sampled token.
         Returns the number of tokens to remove from the prompt and the
         list of tokens to append.
+        If logprob of the sampled token is given, it is summed into the
+        "log_prob" field of the capture and text progress entries.
         If compute_mask() returned None mask, this should be called immediately with None.
         If compute_mask() returned stop, you don't need to call this (but can).
         """
@@ -234,6 +256,8 @@ class LLMatcher:
         *,
         log_level: int = 1,
         limits: Optional[LLParserLimits] = None,
+        json_schema_dir: Optional[str] = None,
+        json_schema_bundle: Union[str, Dict[str, Any], None] = None,
     ) -> "LLMatcher":
         """
         Create a new LLMatcher.
@@ -241,6 +265,10 @@ class LLMatcher:
             tokenizer: LLTokenizer - the tokenizer to use
             grammar: str - either a Lark grammar or stringified JSON representation of LLGuidance grammar
             log_level: int - verbosity level (0: silent, 1: warnings, 2: verbose)
+            json_schema_dir: str - directory to load external $refs of JSON schemas from
+                (top-level or %json in Lark); the path of the $ref URI is resolved relative to it
+            json_schema_bundle: dict or JSON string - object mapping URIs to schemas,
+                used for external $refs of JSON schemas; takes precedence over json_schema_dir
         Raises:
             Never.
 
@@ -388,9 +416,12 @@ class LLMatcher:
         Try consuming a list of tokens and return how many were successfully consumed.
         """
 
-    def consume_token(self, sampled_token: TokenId) -> bool:
+    def consume_token(self,
+                      sampled_token: TokenId,
+                      logprob: Optional[float] = None) -> bool:
         """
         Consume a single token.
+        The optional logprob of the token is recorded for get_bytes_logprob().
         Returns true on success.
         If it returns false, the matcher is in an error state (either from previous errors or it has just entered it).
         """
@@ -444,6 +475,37 @@ class LLMatcher:
         with the same name (e.g., due to repetition of rules).
         """
 
+    def get_capture_tree(self) -> List[CaptureNode]:
+        """
+        Get all captures as a tree of (name, start, end, children) tuples.
+        start and end are byte offsets into get_final_bytes();
+        children are the captures nested inside the given one.
+        """
+
+    def get_final_bytes(self) -> bytes:
+        """
+        Get the bytes consumed by the matcher so far.
+        """
+
+    def get_bytes_logprob(self, start: int, end: int) -> float:
+        """
+        Get the sum of log-probabilities of bytes start..end of get_final_bytes(),
+        for example of a capture from get_capture_tree().
+        A token's logprob (passed to consume_token()) is split evenly among its bytes;
+        tokens consumed without logprob count as 0.0.
+        """
+
+    def get_parse_tree(self, named_only: bool = False) -> str:
+        """
+        Get the parse tree of the consumed bytes as JSON.
+        Nodes are {"name", "start", "end", "is_lexeme", "children"} objects,
+        where start and end are byte offsets into get_final_bytes().
+        If the input is incomplete, only the rules started so far are included.
+        With named_only, nodes of helper symbols are replaced by their children;
+        use %llguidance { "parse_tree": true } to keep all Lark rules as named nodes.
+        Returns "null" if the matcher is in error state.
+        """
+
 
 class JsonCompiler:
 
@@ -616,10 +678,16 @@ class JsonCompileOptions(TypedDict, total=False):
     whitespace_pattern: Optional[str]
     # defaults to true (r"[\x20\x0A\x0D\x09]+"); if false, no whitespace is allowed
     whitespace_flexible: Optional[bool]
+    # allow object properties in any order; defaults to false
+    flexible_property_order: Optional[bool]
     # defaults to false
     coerce_one_of: Optional[bool]
     # ignore unimplemented keywords; defaults to false
     lenient: Optional[bool]
+    # additional string formats, name -> regex
+    custom_formats: Optional[Dict[str, str]]
+    # enforce contentEncoding and contentMediaType: application/json; defaults to false
+    enforce_content: Optional[bool]
 
 
 class LLParserLimits:
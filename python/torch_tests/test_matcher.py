@@ -1,3 +1,4 @@
+import json
 from typing import Any, Dict, List, Tuple
 
 import llguidance
@@ -667,6 +668,52 @@ def test_grammar_warnings() -> None:
                             }})
 
 
+def test_json_schema_refs() -> None:
+    bundle = {
+        "https://example.com/id.json": {
+            "type": "integer",
+            "minimum": 0
+        }
+    }
+    schema = {
+        "type": "object",
+        "properties": {
+            "id": {
+                "$ref": "https://example.com/id.json"
+            }
+        },
+        "required": ["id"],
+        "additionalProperties": False
+    }
+    grm = LLMatcher.grammar_from_json_schema(schema,
+                                             {"whitespace_flexible": False})
+    m = LLMatcher(tokenizer(), grm, json_schema_bundle=bundle)
+    check_err(m)
+    m.consume_tokens(tokenizer().tokenize_str('{"id":42}'))
+    check_err(m)
+    assert m.is_accepting()
+
+    m = LLMatcher(tokenizer(), grm, json_schema_bundle=json.dumps(bundle))
+    check_err(m)
+    assert m.validate_tokens(tokenizer().tokenize_str('{"id":-1}')) < 7
+
+    # also for %json in Lark
+    m = LLMatcher(tokenizer(),
+                  'start: "id=" id\nid: %json {"$ref": "https://example.com/id.json"}',
+                  json_schema_bundle=bundle)
+    check_err(m)
+    m.consume_tokens(tokenizer().tokenize_str("id=7"))
+    check_err(m)
+    assert m.is_accepting()
+
+    # external $refs are not resolved without the bundle
+    m = LLMatcher(tokenizer(), grm)
+    assert m.is_error()
+    m = LLMatcher(tokenizer(), grm, json_schema_bundle='["not an object"]')
+    assert m.is_error()
+    assert "json_schema_bundle must be a JSON object" in m.get_error()
+
+
 def test_get_capture() -> None:
     grm = r"""start: "hello " group1 group2+ group3 " end"
 group1[capture,lazy]: /[a-z]+/
@@ -687,6 +734,57 @@ group3[capture="body"]: /[0-9]{4}/"""
     assert m.get_captures() == [("group1", b"w"), ("body", b"orld"), ("body", b"abcd"), ("body", b"1234")]
 
 
+def test_get_capture_tree() -> None:
+    grm = r"""start: call ("," call)*
+call[capture]: name "(" arg ("," arg)* ")"
+name[capture]: /[a-z]+/
+arg[capture]: /[0-9]+/"""
+    m = matcher(grm)
+    check_err(m)
+
+    assert m.get_capture_tree() == []
+    m.consume_tokens(tokenizer().tokenize_str("foo(1,22),bar(3)"))
+    check_err(m)
+    assert m.get_final_bytes() == b"foo(1,22),bar(3)"
+    assert m.get_capture_tree() == [
+        ("call", 0, 9, [("name", 0, 3, []), ("arg", 4, 5, []), ("arg", 6, 8, [])]),
+        ("call", 10, 16, [("name", 10, 13, []), ("arg", 14, 15, [])]),
+    ]
+
+
+def test_get_parse_tree() -> None:
+    grm = r"""%llguidance { "parse_tree": true }
+start: pair ("," pair)*
+pair: KEY "=" VALUE
+KEY: /[a-z]+/
+VALUE: /[0-9]+/"""
+    m = matcher(grm)
+    check_err(m)
+
+    m.consume_tokens(tokenizer().tokenize_str("a=1,bc=22,"))
+    check_err(m)
+    tree = json.loads(m.get_parse_tree(named_only=True))
+
+    def simplify(node: Any) -> Any:
+        if node["is_lexeme"]:
+            return node["name"]
+        return (node["name"], node["start"], node["end"], [simplify(c) for c in node["children"]])
+
+    assert simplify(tree) == (
+        "start",
+        0,
+        10,
+        [
+            ("pair", 0, 3, ["KEY", '"="', "VALUE"]),
+            '","',
+            ("pair", 4, 9, ["KEY", '"="', "VALUE"]),
+            '","',
+        ],
+    )
+    full = json.loads(m.get_parse_tree())
+    assert full["start"] == 0 and full["end"] == 10
+
+
 def test_multi_eos_tokens_property() -> None:
     """Test that eos_tokens returns the expected list for a single-EOS tokenizer."""
     tok = LLTokenizer("byte")
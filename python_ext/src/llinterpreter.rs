@@ -5,10 +5,11 @@ use llguidance::api::GrammarInit;
 use llguidance::toktrie::{InferenceCapabilities, TokenId};
 use llguidance::{api::TopLevelGrammar, output::ParserOutput};
 use llguidance::{Constraint, Logger};
-use pyo3::types::PyByteArray;
+use pyo3::types::{PyByteArray, PyList};
 use pyo3::{exceptions::PyValueError, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use crate::llmatcher::capture_tree_to_py;
 use crate::parserlimits::LLParserLimits;
 use crate::py::LLTokenizer;
 
@@ -97,6 +98,19 @@ impl LLInterpreter {
         self.inner.parser.stop_reason().to_string()
     }
 
+    fn get_capture_tree<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        capture_tree_to_py(py, &self.inner.capture_tree())
+    }
+
+    fn get_final_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.inner.parser.final_bytes())
+    }
+
+    #[pyo3(signature = (named_only=false))]
+    fn get_parse_tree(&self, named_only: bool) -> String {
+        serde_json::to_string(&self.inner.parse_tree(named_only)).unwrap()
+    }
+
     fn process_prompt(&mut self, prompt: Vec<TokenId>) -> Vec<TokenId> {
         self.inner.process_prompt(prompt)
     }
@@ -163,9 +177,16 @@ impl LLInterpreter {
         Ok((mask, self.json_py_result()))
     }
 
-    #[pyo3(signature = (sampled_token))]
-    fn commit_token(&mut self, sampled_token: Option<TokenId>) -> PyResult<(u32, Vec<TokenId>)> {
-        let pres = self.inner.commit_token(sampled_token).map_err(val_error)?;
+    #[pyo3(signature = (sampled_token, logprob=None))]
+    fn commit_token(
+        &mut self,
+        sampled_token: Option<TokenId>,
+        logprob: Option<f64>,
+    ) -> PyResult<(u32, Vec<TokenId>)> {
+        let pres = self
+            .inner
+            .commit_token_with_logprob(sampled_token, logprob)
+            .map_err(val_error)?;
 
         if pres.stop {
             // inner.commit_token() only returns stop, when compute_mask()
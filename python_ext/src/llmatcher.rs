@@ -5,8 +5,11 @@ use std::ops::DerefMut;
 use anyhow::Result;
 use llguidance::api::GrammarInit;
 use llguidance::api::TopLevelGrammar;
+use llguidance::earley::CaptureNode;
 use llguidance::toktrie::{InferenceCapabilities, SimpleVob, TokEnv, TokenId};
-use llguidance::{json_merge, Logger, Matcher, ParserFactory};
+use llguidance::{
+    bundle_and_dir_retriever, json_merge, Logger, Matcher, ParserFactory, RetrieveWrapper,
+};
 use pyo3::types::{PyList, PyTuple};
 use pyo3::{exceptions::PyValueError, prelude::*};
 use serde_json::json;
@@ -190,7 +193,7 @@ impl LLExecutor {
 
         if mut_refs.len() == 1 {
             let (mut interp, token) = mut_refs.pop().unwrap();
-            return Ok(vec![interp.consume_token(token)]);
+            return Ok(vec![interp.consume_token_inner(token, None)]);
         }
 
         let mut_refs2: Vec<_> = mut_refs
@@ -204,7 +207,7 @@ impl LLExecutor {
             self.pool.install(|| {
                 mut_refs2
                     .into_par_iter()
-                    .map(|(interp, token)| interp.consume_token_inner(token))
+                    .map(|(interp, token)| interp.consume_token_inner(token, None))
                     .collect()
             })
         });
@@ -278,7 +281,7 @@ impl LLMatcher {
         }
     }
 
-    fn consume_token_inner(&mut self, sampled_token: TokenId) -> bool {
+    fn consume_token_inner(&mut self, sampled_token: TokenId, logprob: Option<f64>) -> bool {
         if self.inner.is_stopped()
             && self
                 .tok_env
@@ -288,7 +291,9 @@ impl LLMatcher {
         {
             true
         } else {
-            self.inner.consume_token(sampled_token).is_ok()
+            self.inner
+                .consume_token_with_logprob(sampled_token, logprob)
+                .is_ok()
         }
     }
 }
@@ -299,9 +304,20 @@ fn new_matcher(
     dbg_grammar: String,
     log_level: isize,
     limits: Option<&LLParserLimits>,
+    json_retriever: Option<RetrieveWrapper>,
     py: Python<'_>,
 ) -> Matcher {
     let logger = Logger::new(0, std::cmp::max(0, log_level) as u32);
+    let with_retriever;
+    let fact = match json_retriever {
+        Some(retriever) => {
+            let mut f = fact.clone();
+            f.set_json_retriever(Some(retriever));
+            with_retriever = f;
+            &with_retriever
+        }
+        None => fact,
+    };
     // constructing a grammar can take on the order of 100ms
     // for very large grammars, so we drop the GIL here
     let inner = py.detach(|| {
@@ -317,6 +333,24 @@ fn new_matcher(
     Matcher::new(inner)
 }
 
+/// Retriever for external `$ref`s of JSON schemas, like in `LlgConstraintInit`.
+fn extract_json_retriever(
+    json_schema_dir: Option<String>,
+    json_schema_bundle: Option<Bound<'_, PyAny>>,
+) -> Result<Option<RetrieveWrapper>> {
+    let bundle = match json_schema_bundle {
+        Some(bundle) => match str_or_dict_to_value(bundle)? {
+            serde_json::Value::Object(bundle) => Some(bundle),
+            _ => anyhow::bail!("json_schema_bundle must be a JSON object"),
+        },
+        None => None,
+    };
+    Ok(bundle_and_dir_retriever(
+        bundle.as_ref(),
+        json_schema_dir.as_deref(),
+    ))
+}
+
 fn extract_grammar(grammar: Bound<'_, PyAny>) -> Result<(String, TopLevelGrammar)> {
     if let Ok(s) = grammar.extract::<String>() {
         let r = TopLevelGrammar::from_lark_or_grammar_list(&s)?;
@@ -333,19 +367,30 @@ fn extract_grammar(grammar: Bound<'_, PyAny>) -> Result<(String, TopLevelGrammar
 #[pymethods]
 impl LLMatcher {
     #[new]
-    #[pyo3(signature = (tokenizer, grammar, *, log_level=None, limits=None))]
+    #[pyo3(signature = (tokenizer, grammar, *, log_level=None, limits=None,
+        json_schema_dir=None, json_schema_bundle=None))]
     fn py_new(
         tokenizer: &LLTokenizer,
         grammar: Bound<'_, PyAny>,
         log_level: Option<isize>,
         limits: Option<&LLParserLimits>,
+        json_schema_dir: Option<String>,
+        json_schema_bundle: Option<Bound<'_, PyAny>>,
         py: Python<'_>,
     ) -> Self {
         let fact = tokenizer.factory();
-        let inner = match extract_grammar(grammar) {
-            Ok((dbg_grm, grammar)) => {
-                new_matcher(fact, grammar, dbg_grm, log_level.unwrap_or(1), limits, py)
-            }
+        let inner = match extract_json_retriever(json_schema_dir, json_schema_bundle)
+            .and_then(|retriever| Ok((retriever, extract_grammar(grammar)?)))
+        {
+            Ok((retriever, (dbg_grm, grammar))) => new_matcher(
+                fact,
+                grammar,
+                dbg_grm,
+                log_level.unwrap_or(1),
+                limits,
+                retriever,
+                py,
+            ),
             Err(e) => Matcher::new(Err(e)),
         };
         LLMatcher {
@@ -522,8 +567,9 @@ impl LLMatcher {
         })
     }
 
-    fn consume_token(&mut self, sampled_token: TokenId) -> bool {
-        self.consume_token_inner(sampled_token)
+    #[pyo3(signature = (sampled_token, logprob=None))]
+    fn consume_token(&mut self, sampled_token: TokenId, logprob: Option<f64>) -> bool {
+        self.consume_token_inner(sampled_token, logprob)
     }
 
     fn consume_tokens(&mut self, tokens: Vec<TokenId>) -> bool {
@@ -570,6 +616,37 @@ impl LLMatcher {
             .map(|(name, bytes)| (name.clone(), Cow::Borrowed(bytes.as_slice())))
             .collect()
     }
+
+    fn get_capture_tree<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        capture_tree_to_py(py, &self.inner.capture_tree())
+    }
+
+    fn get_final_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.inner.final_bytes())
+    }
+
+    #[pyo3(signature = (named_only=false))]
+    fn get_parse_tree(&self, named_only: bool) -> String {
+        serde_json::to_string(&self.inner.parse_tree(named_only)).unwrap()
+    }
+
+    fn get_bytes_logprob(&self, start: usize, end: usize) -> f64 {
+        self.inner.bytes_logprob(start..end)
+    }
+}
+
+pub(crate) fn capture_tree_to_py<'py>(
+    py: Python<'py>,
+    nodes: &[CaptureNode],
+) -> PyResult<Bound<'py, PyList>> {
+    let items = nodes
+        .iter()
+        .map(|n| {
+            let children = capture_tree_to_py(py, &n.children)?;
+            Ok((n.name.as_str(), n.start, n.end, children))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    PyList::new(py, items)
 }
 
 pub(crate) fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
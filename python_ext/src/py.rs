@@ -422,8 +422,13 @@ impl JsonCompiler {
             whitespace_flexible: self.whitespace_flexible,
             coerce_one_of: self.coerce_one_of,
             whitespace_pattern: self.whitespace_pattern.clone(),
+            flexible_property_order: false,
             lenient: false,
+            enforce_content: false,
+            custom_formats: Default::default(),
             json_allowed_escapes: self.json_allowed_escapes.clone(),
+            schema_bundle: None,
+            schema_dir: None,
             retriever: None,
         };
         compile_options.apply_to(&mut schema);
@@ -3,10 +3,12 @@
 /// This binary exercises all major features of the llguidance library:
 ///   - Loading grammars from JSON Schema, Lark, internal (.ll.json), and text formats
 ///   - Using a real HuggingFace tokenizer (downloaded on first use)
-///   - Three operating modes:
-///     1. **Mask-only**: Compile the grammar and compute one token mask (no `--input` or `--rnd`)
+///   - Five operating modes:
+///     1. **Mask-only**: Compile the grammar and compute one token mask (no `--input`, `--rnd`, `--enumerate` or `--fuzz`)
 ///     2. **Random generation** (`--rnd N`): Simulate an LLM by sampling random valid tokens
-///     3. **Input validation** (`--input FILE`): Verify a known input conforms to the grammar
+///     3. **Enumeration** (`--enumerate N`): List up to N strings accepted by the grammar
+///     4. **Fuzzing** (`--fuzz N`): Generate N random strings of the grammar, independent of the tokenizer
+///     5. **Input validation** (`--input FILE`): Verify a known input conforms to the grammar
 ///
 /// See `minimal.rs` for a stripped-down version focused on the core decoding loop.
 ///
@@ -14,10 +16,15 @@
 ///   cargo run -- data/blog.schema.json --input data/blog.sample.json
 ///   cargo run -- data/rfc.lark --input data/rfc.xml
 ///   cargo run -- data/blog.schema.json --rnd 100 --verbose
+///   cargo run -- data/sentiment.lark --enumerate 10 --verbose
+///   cargo run -- data/blog.schema.json --fuzz 5
 use clap::Parser;
 use std::{fs::File, io::Read, sync::Arc, vec};
 
-use llguidance::{api::TopLevelGrammar, toktrie::TokEnv, Matcher, ParserFactory};
+use llguidance::{
+    api::TopLevelGrammar, toktrie::TokEnv, EnumerateOptions, Enumerator, FuzzOptions,
+    GrammarFuzzer, Matcher, ParserFactory,
+};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use serde_json::json;
 
@@ -56,9 +63,11 @@ fn dump_tokenizer(name: &str) {
 
 /// CLI arguments for the sample parser.
 ///
-/// The tool operates in three modes depending on which arguments are provided:
-///   - No `--input` or `--rnd`: compile the grammar and compute one mask (test grammar validity)
+/// The tool operates in five modes depending on which arguments are provided:
+///   - No `--input`, `--rnd`, `--enumerate` or `--fuzz`: compile the grammar and compute one mask (test grammar validity)
 ///   - `--rnd N`: generate N random tokens that satisfy the grammar (simulates an LLM)
+///   - `--enumerate N`: print up to N strings accepted by the grammar, with their tokens
+///   - `--fuzz N`: print N random strings accepted by the grammar (one JSON string per line)
 ///   - `--input FILE`: validate that the tokens in FILE conform to the grammar
 #[derive(Parser, Debug, Default)]
 #[command(version, about, long_about = None)]
@@ -83,6 +92,26 @@ pub struct CliOptions {
     #[arg(long, short = 'r')]
     rnd: Option<usize>,
 
+    /// Print up to N completions of the grammar
+    #[arg(long)]
+    enumerate: Option<usize>,
+
+    /// Maximum number of tokens in a completion for --enumerate
+    #[arg(long, default_value = "32")]
+    enumerate_tokens: usize,
+
+    /// Include all tokenizations of completions for --enumerate, not only the canonical one
+    #[arg(long)]
+    all_tokenizations: bool,
+
+    /// Print N random strings accepted by the grammar
+    #[arg(long)]
+    fuzz: Option<usize>,
+
+    /// Maximum nesting depth of grammar rules for --fuzz
+    #[arg(long, default_value = "30")]
+    fuzz_depth: usize,
+
     /// Set stderr log level; 1 is warnings only, 2 is verbose (default: 1)
     #[arg(long, short = 'l', default_value = "1")]
     log_level: u32,
@@ -191,10 +220,11 @@ fn main() {
     let mut constraint = Matcher::new(parser);
 
     // --- Mode 1: Mask-only ---
-    // When no --input or --rnd is given, just compile the grammar and compute
+    // When no --input, --rnd, --enumerate or --fuzz is given, just compile the grammar and compute
     // one token mask. Useful for checking that a grammar is valid and measuring
     // compilation time.
-    if opts.input.is_none() && opts.rnd.is_none() {
+    if opts.input.is_none() && opts.rnd.is_none() && opts.enumerate.is_none() && opts.fuzz.is_none()
+    {
         let _ = constraint.compute_mask().unwrap();
         return;
     }
@@ -266,7 +296,55 @@ fn main() {
         return;
     }
 
-    // --- Mode 3: Input validation (--input FILE) ---
+    // --- Mode 3: Enumeration (--enumerate N) ---
+    // Depth-first search over the allowed tokens, printing every accepted string.
+    // Only feasible for small grammars (e.g., a fixed set of labels); the number
+    // of explored states is limited by --enumerate-tokens.
+    if let Some(max_count) = opts.enumerate {
+        let trie = tok_env.tok_trie();
+        let options = EnumerateOptions {
+            max_tokens: opts.enumerate_tokens,
+            max_count,
+            canonical_only: !opts.all_tokenizations,
+        };
+        let mut num_completions = 0;
+        for completion in Enumerator::new(constraint, options).unwrap() {
+            let completion = completion.unwrap();
+            num_completions += 1;
+            if opts.verbose {
+                println!(
+                    "{:?} {}",
+                    String::from_utf8_lossy(&completion.bytes),
+                    trie.tokens_dbg(&completion.tokens)
+                );
+            } else {
+                println!("{:?}", String::from_utf8_lossy(&completion.bytes));
+            }
+        }
+        eprintln!("Completions: {num_completions}");
+        return;
+    }
+
+    // --- Mode 4: Fuzzing (--fuzz N) ---
+    // Random walk over the grammar rules rather than the tokens, so outputs look like
+    // realistic instances (e.g., of a JSON schema), independent of the tokenizer.
+    // Useful for generating test fixtures.
+    if let Some(count) = opts.fuzz {
+        let options = FuzzOptions {
+            seed: opts.seed as u64,
+            max_depth: opts.fuzz_depth,
+            ..Default::default()
+        };
+        let mut fuzzer = GrammarFuzzer::new(grammar, options).unwrap();
+        for _ in 0..count {
+            let bytes = fuzzer.generate().unwrap();
+            let s = String::from_utf8_lossy(&bytes);
+            println!("{}", serde_json::to_string(&s).unwrap());
+        }
+        return;
+    }
+
+    // --- Mode 5: Input validation (--input FILE) ---
     // Validates that a pre-tokenized input file conforms to the grammar.
     // This simulates an LLM that always produces the "right" answer — useful for
     // testing grammars against known-good outputs.